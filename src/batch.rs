@@ -0,0 +1,78 @@
+use crate::number::byte2number;
+
+/// A block of rows stored column by column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub rows: usize,
+    pub columns: Vec<BatchColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchColumn {
+    Numeric(Vec<Option<f64>>),
+    Char(CharColumn),
+}
+
+/// CHAR values of one column, trailing blanks removed, packed into a single
+/// buffer. Value `i` is `bytes[offsets[i]..offsets[i + 1]]`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CharColumn {
+    pub offsets: Vec<usize>,
+    pub bytes: Vec<u8>,
+}
+
+impl CharColumn {
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> &[u8] {
+        &self.bytes[self.offsets[i]..self.offsets[i + 1]]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.offsets.windows(2).map(|w| &self.bytes[w[0]..w[1]])
+    }
+}
+
+impl Batch {
+    /// Splits `rows` consecutive observations of `line_length` bytes into columns.
+    pub(crate) fn from_lines(
+        lines: &[u8],
+        rows: usize,
+        line_length: usize,
+        columns: &[(u32, u16, u16)],
+    ) -> Self {
+        let columns = columns
+            .iter()
+            .map(|(npos, nlng, ntype)| {
+                let start = *npos as usize;
+                let end = start + *nlng as usize;
+                let cells = lines
+                    .chunks_exact(line_length)
+                    .take(rows)
+                    .map(|line| &line[start..end]);
+                if *ntype == 1 {
+                    BatchColumn::Numeric(cells.map(byte2number).collect())
+                } else {
+                    let mut column = CharColumn {
+                        offsets: Vec::with_capacity(rows + 1),
+                        bytes: Vec::with_capacity(rows * (end - start)),
+                    };
+                    column.offsets.push(0);
+                    for cell in cells {
+                        let len = cell.iter().rposition(|b| *b != b' ').map_or(0, |i| i + 1);
+                        column.bytes.extend_from_slice(&cell[..len]);
+                        column.offsets.push(column.bytes.len());
+                    }
+                    BatchColumn::Char(column)
+                }
+            })
+            .collect();
+        Batch { rows, columns }
+    }
+}
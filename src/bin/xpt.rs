@@ -0,0 +1,902 @@
+#[cfg(feature = "async")]
+compile_error!("the xpt binary uses the blocking reader, build it without the `async` feature");
+
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use xpt_r::compare::{CompareMethod, CompareOptions, Comparison};
+use xpt_r::convert::{csv_header, csv_row, json_row};
+use xpt_r::define::display_format;
+use xpt_r::detect::TextEncoding;
+use xpt_r::error::XPTError;
+use xpt_r::filter::filter_file;
+use xpt_r::join::{join_files, JoinConflict, JoinInput, JoinKind, JoinOptions};
+use xpt_r::migrate::{migrate_file, LongValues};
+use xpt_r::part::{ColumnType, DocumentHeader, DocumentMeta, StringDecoder, StringEncoder};
+use xpt_r::profile::{ProfileOptions, Profiler};
+use xpt_r::reader::{CharMode, RawReader, Reader};
+use xpt_r::shrink::shrink_file;
+use xpt_r::sort::{first_unsorted, sort_file, SortKey, SortOptions};
+use xpt_r::split::{merge_files, merge_manifest, split_file, SplitLimit, SplitManifest};
+use xpt_r::stack::stack_files;
+use xpt_r::transform::{transform_file, Transform};
+
+/// `println!` through [`stdout`], returning other write errors.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        writeln!(stdout(), $($arg)*)
+    };
+}
+
+/// `print!` through [`stdout`], returning other write errors.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        write!(stdout(), $($arg)*)
+    };
+}
+
+/// Locked standard output that exits quietly once the reading end of a pipe
+/// is closed, as by `xpt head big.xpt | head -3`, where `println!` panics.
+struct Stdout(std::io::StdoutLock<'static>);
+
+fn stdout() -> Stdout {
+    Stdout(std::io::stdout().lock())
+}
+
+fn exit_on_broken_pipe<T>(result: std::io::Result<T>) -> std::io::Result<T> {
+    match result {
+        Err(error) if error.kind() == ErrorKind::BrokenPipe => std::process::exit(0),
+        result => result,
+    }
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        exit_on_broken_pipe(self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        exit_on_broken_pipe(self.0.flush())
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    name = "xpt",
+    version,
+    about = "Inspect and convert SAS transport files"
+)]
+struct Cli {
+    /// Text encoding of CHAR values: auto, utf-8, latin1, gbk or shift-jis.
+    #[arg(long, global = true, default_value = "auto")]
+    encoding: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the library and dataset headers and the column table.
+    Info { file: PathBuf },
+    /// Print the first rows.
+    Head {
+        file: PathBuf,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        rows: usize,
+    },
+    /// Convert the file to another format.
+    Convert {
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        to: Format,
+        /// Output file, standard output when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare two datasets in the manner of PROC COMPARE. Exits with 1 when
+    /// they differ.
+    Diff {
+        base: PathBuf,
+        compare: PathBuf,
+        /// ID variables matching observations, by position when omitted.
+        #[arg(long, value_delimiter = ',')]
+        id: Vec<String>,
+        #[arg(long, value_enum, default_value = "exact")]
+        method: Method,
+        /// Tolerance of the absolute and relative methods.
+        #[arg(long, default_value_t = 1e-5)]
+        criterion: f64,
+        /// Differences listed per variable.
+        #[arg(long, default_value_t = 50)]
+        max_print: usize,
+    },
+    /// Print per-column summary statistics and frequent values.
+    Stats {
+        file: PathBuf,
+        /// Most frequent CHAR values shown per column.
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+        /// Compute exact quantiles, keeping every NUMERIC value in memory.
+        #[arg(long)]
+        exact: bool,
+    },
+    /// Keep the rows matching a WHERE-style expression, such as
+    /// `LBTESTCD = "ALT" and LBSTRESN > 3 * ULN`.
+    Filter {
+        file: PathBuf,
+        #[arg(long = "where")]
+        expression: String,
+        /// Output transport file, CSV on standard output when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Sort the rows by a BY list such as `USUBJID DESCENDING LBSEQ`, or with
+    /// --check exit with 1 when they are not sorted.
+    Sort {
+        file: PathBuf,
+        #[arg(long)]
+        by: String,
+        #[arg(short, long, required_unless_present = "check")]
+        output: Option<PathBuf>,
+        #[arg(long, conflicts_with = "output")]
+        check: bool,
+        /// Memory for observations before sorted runs spill to disk, with an
+        /// optional K, M or G suffix.
+        #[arg(long, value_parser = parse_size, default_value = "256M")]
+        max_memory: u64,
+    },
+    /// Copy the file with its columns changed. Renames apply first, and the
+    /// other options name the columns as renamed.
+    Transform {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// OLD=NEW
+        #[arg(long, value_parser = parse_assignment)]
+        rename: Vec<(String, String)>,
+        /// NAME=LABEL
+        #[arg(long, value_parser = parse_assignment)]
+        label: Vec<(String, String)>,
+        /// NAME=FORMAT, such as AVAL=8.2, or NAME= to remove it.
+        #[arg(long, value_parser = parse_assignment)]
+        format: Vec<(String, String)>,
+        #[arg(long, value_delimiter = ',')]
+        drop: Vec<String>,
+        /// Columns to move to the front, in order.
+        #[arg(long, value_delimiter = ',')]
+        order: Vec<String>,
+        /// NAME=FORMAT, writing a NUMERIC column as CHAR.
+        #[arg(long, value_parser = parse_assignment)]
+        to_char: Vec<(String, String)>,
+        /// NAME=INFORMAT, reading a CHAR column as NUMERIC.
+        #[arg(long, value_parser = parse_assignment)]
+        to_num: Vec<(String, String)>,
+    },
+    /// Rewrite the file as another transport version.
+    Migrate {
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        to: Version,
+        /// Cut names, labels and lengths V5 cannot hold instead of failing.
+        #[arg(long)]
+        truncate: bool,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Rewrite the file with every CHAR length cut to its longest value.
+    Shrink {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Split the file into complete parts and write a manifest beside them.
+    Split {
+        file: PathBuf,
+        /// Largest part size, with an optional K, M or G suffix.
+        #[arg(long, value_parser = parse_size, conflicts_with = "max_rows")]
+        max_bytes: Option<u64>,
+        #[arg(long, required_unless_present = "max_bytes")]
+        max_rows: Option<usize>,
+        /// Directory for the parts and the manifest.
+        #[arg(short, long, default_value = ".")]
+        directory: PathBuf,
+    },
+    /// Stack datasets of one domain, harmonizing their columns.
+    Stack {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Join two datasets on key variables in the manner of a DATA step MERGE.
+    Join {
+        left: PathBuf,
+        right: PathBuf,
+        #[arg(long, value_delimiter = ',', required = true)]
+        by: Vec<String>,
+        #[arg(long, value_enum, default_value = "full")]
+        how: How,
+        /// Match the inputs in memory instead of requiring them sorted.
+        #[arg(long)]
+        hashed: bool,
+        /// Input supplying the non-key columns both hold.
+        #[arg(long, value_enum, default_value = "right")]
+        conflict: Conflict,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Concatenate parts with the same columns, listed or from a manifest.
+    Merge {
+        #[arg(required_unless_present = "manifest")]
+        parts: Vec<PathBuf>,
+        #[arg(long, conflicts_with = "parts")]
+        manifest: Option<PathBuf>,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+/// Parses a byte count such as `5G` or `500M`, in powers of 1024.
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, unit) = match size.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => (&size[..i], size[i..].to_ascii_uppercase()),
+        None => (size, String::new()),
+    };
+    let factor: u64 = match unit.trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown size unit in {}", size)),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| format!("{}: {}", size, e))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| format!("size too large: {}", size))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Method {
+    Exact,
+    Absolute,
+    Relative,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum How {
+    Inner,
+    Left,
+    Full,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Conflict {
+    Right,
+    Left,
+    Error,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Version {
+    V5,
+    V8,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+    Parquet,
+}
+
+/// The encoding named by `--encoding`, `None` for `auto`.
+fn parse_encoding(encoding: &str) -> Result<Option<TextEncoding>, XPTError> {
+    Ok(Some(match encoding.to_ascii_lowercase().as_str() {
+        "auto" => return Ok(None),
+        "utf-8" | "utf8" => TextEncoding::Utf8,
+        "latin1" | "iso-8859-1" => TextEncoding::Latin1,
+        "gbk" => TextEncoding::Gbk,
+        "shift-jis" | "shift_jis" | "sjis" => TextEncoding::ShiftJis,
+        other => return Err(XPTError::DecodeError(format!("unknown encoding {}", other))),
+    }))
+}
+
+fn not_built(encoding: TextEncoding) -> XPTError {
+    XPTError::DecodeError(format!(
+        "{} needs a build with the multi_encoding feature",
+        encoding
+    ))
+}
+
+fn open<'a>(file: &'a mut File, encoding: &str) -> Result<(RawReader<'a>, DocumentMeta), XPTError> {
+    let encoding = match parse_encoding(encoding)? {
+        Some(encoding) => encoding,
+        None => return Reader::new_auto(file).start(),
+    };
+    let decoder = encoding.decoder().ok_or_else(|| not_built(encoding))?;
+    Reader::new(file, decoder).start()
+}
+
+/// Splits `NAME=VALUE`.
+fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    assignment
+        .split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got {}", assignment))
+}
+
+/// Decoder and encoder for commands that rewrite `path`, detecting the
+/// encoding for `auto` and falling back to UTF-8.
+fn codec(path: &Path, encoding: &str) -> Result<(StringDecoder, StringEncoder), XPTError> {
+    let encoding = match parse_encoding(encoding)? {
+        Some(encoding) => encoding,
+        None => {
+            let mut file = File::open(path)?;
+            let (_, meta) = Reader::new_auto(&mut file).start()?;
+            meta.encoding
+                .filter(|guess| !guess.is_ascii() && guess.encoding.decoder().is_some())
+                .map_or(TextEncoding::Utf8, |guess| guess.encoding)
+        }
+    };
+    Ok((
+        encoding.decoder().ok_or_else(|| not_built(encoding))?,
+        encoding.encoder().ok_or_else(|| not_built(encoding))?,
+    ))
+}
+
+fn output(path: &Option<PathBuf>) -> Result<Box<dyn Write>, XPTError> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(stdout())),
+    })
+}
+
+fn info(path: &Path, encoding: &str) -> Result<(), XPTError> {
+    let mut file = File::open(path)?;
+    let (mut data, meta) = open(&mut file, encoding)?;
+    let mut rows = 0;
+    while let Some(batch) = data.read_batch(4096)? {
+        rows += batch.rows;
+    }
+    outln!("File:       {}", path.display())?;
+    outln!("Format:     {:?}", meta.version)?;
+    outln!("Library:    {}", meta.library)?;
+    outln!("Dataset:    {}", meta.dataset_name)?;
+    outln!("Label:      {}", meta.dataset_label)?;
+    if !meta.dataset_type.is_empty() {
+        outln!("Type:       {}", meta.dataset_type)?;
+    }
+    outln!("SAS:        {}", meta.dataset_version)?;
+    outln!("OS:         {}", meta.dataset_operation_system)?;
+    outln!("Created:    {}", meta.dataset_created)?;
+    outln!("Modified:   {}", meta.dataset_modified)?;
+    if let Some(guess) = &meta.encoding {
+        outln!(
+            "Encoding:   {} ({:.0}% confidence)",
+            guess.encoding,
+            guess.confidence * 100.0
+        )?;
+    }
+    outln!("Rows:       {}", rows)?;
+    outln!("Columns:    {}", meta.columns.len())?;
+    outln!()?;
+    let mut table = vec![vec![
+        "#".to_string(),
+        "Name".to_string(),
+        "Type".to_string(),
+        "Length".to_string(),
+        "Pos".to_string(),
+        "Format".to_string(),
+        "Informat".to_string(),
+        "Label".to_string(),
+    ]];
+    for (i, column) in meta.columns.iter().enumerate() {
+        table.push(vec![
+            (i + 1).to_string(),
+            column.name.clone(),
+            match column.column_type {
+                ColumnType::NUMERIC => "Num",
+                ColumnType::CHAR => "Char",
+            }
+            .to_string(),
+            column.length.to_string(),
+            column.position.to_string(),
+            display_format(&column.format).unwrap_or_default(),
+            display_format(&column.in_format).unwrap_or_default(),
+            column.label.clone(),
+        ]);
+    }
+    print_table(&table)?;
+    Ok(())
+}
+
+fn print_table(table: &[Vec<String>]) -> Result<(), XPTError> {
+    let mut widths = vec![0; table.first().map_or(0, |row| row.len())];
+    for row in table {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in table {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        outln!("{}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+fn head(path: &Path, encoding: &str, rows: usize) -> Result<(), XPTError> {
+    let mut file = File::open(path)?;
+    let (mut data, meta) = open(&mut file, encoding)?;
+    data.set_char_mode(CharMode::Lossy);
+    // replace invalid bytes in the encoding the values are decoded with
+    let encoding = match parse_encoding(encoding)? {
+        Some(encoding) => Some(encoding),
+        None => meta
+            .encoding
+            .filter(|guess| !guess.is_ascii())
+            .map(|guess| guess.encoding),
+    };
+    if let Some(lossy_decoder) = encoding.and_then(|encoding| encoding.lossy_decoder()) {
+        data.set_lossy_decoder(lossy_decoder);
+    }
+    let mut table = vec![meta.columns.iter().map(|c| c.name.clone()).collect()];
+    while table.len() <= rows {
+        match data.read_line()? {
+            Some(row) => table.push(row.iter().map(|value| value.to_string()).collect()),
+            None => break,
+        }
+    }
+    print_table(&table)?;
+    Ok(())
+}
+
+fn convert(
+    path: &Path,
+    encoding: &str,
+    format: Format,
+    output_path: &Option<PathBuf>,
+) -> Result<(), XPTError> {
+    let mut file = File::open(path)?;
+    let (mut data, meta) = open(&mut file, encoding)?;
+    match format {
+        Format::Csv => {
+            let mut out = output(output_path)?;
+            writeln!(out, "{}", csv_header(&meta))?;
+            while let Some(row) = data.read_line()? {
+                writeln!(out, "{}", csv_row(&row))?;
+            }
+            out.flush()?;
+        }
+        Format::Json => {
+            let mut out = output(output_path)?;
+            write!(out, "[")?;
+            let mut first = true;
+            while let Some(row) = data.read_line()? {
+                write!(
+                    out,
+                    "{}\n{}",
+                    if first { "" } else { "," },
+                    json_row(&meta, &row)
+                )?;
+                first = false;
+            }
+            writeln!(out, "\n]")?;
+            out.flush()?;
+        }
+        #[cfg(feature = "parquet")]
+        Format::Parquet => {
+            let out = output_path.as_ref().ok_or_else(|| {
+                XPTError::EncodeError("parquet output needs --output".to_string())
+            })?;
+            let mut writer = xpt_r::convert::ParquetWriter::new(
+                BufWriter::new(File::create(out)?),
+                &meta,
+                data.string_decoder(),
+            )?;
+            while let Some(batch) = data.read_batch(65536)? {
+                writer.write_batch(&batch)?;
+            }
+            writer.finish()?;
+        }
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => {
+            let _ = (data, meta);
+            return Err(XPTError::EncodeError(
+                "parquet output needs a build with the parquet feature".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn diff(
+    base_path: &Path,
+    compare_path: &Path,
+    encoding: &str,
+    options: &CompareOptions,
+) -> Result<bool, XPTError> {
+    let mut base_file = File::open(base_path)?;
+    let mut compare_file = File::open(compare_path)?;
+    let (mut base, base_meta) = open(&mut base_file, encoding)?;
+    let (mut compare, compare_meta) = open(&mut compare_file, encoding)?;
+    let mut comparison = Comparison::new(&base_meta, &compare_meta, options)?;
+    while let Some(row) = compare.read_row()? {
+        comparison.add_compare(&row)?;
+    }
+    while let Some(row) = base.read_row()? {
+        comparison.check_base(&row)?;
+    }
+    let report = comparison.finish();
+    outln!("Base:    {}", base_path.display())?;
+    outln!("Compare: {}", compare_path.display())?;
+    outln!()?;
+    out!("{}", report)?;
+    Ok(report.is_equal())
+}
+
+fn stats(path: &Path, encoding: &str, top: usize, exact: bool) -> Result<(), XPTError> {
+    let mut file = File::open(path)?;
+    let (mut data, meta) = open(&mut file, encoding)?;
+    let options = ProfileOptions {
+        top_n: top,
+        exact_quantiles: exact,
+        ..Default::default()
+    };
+    let mut profiler = Profiler::new(&meta, data.string_decoder(), &options);
+    while let Some(row) = data.read_row()? {
+        profiler.check_row(&row);
+    }
+    for profile in profiler.finish() {
+        outln!("{}", profile)?;
+    }
+    Ok(())
+}
+
+fn filter(
+    path: &Path,
+    encoding: &str,
+    expression: &str,
+    output_path: &Option<PathBuf>,
+) -> Result<(), XPTError> {
+    if let Some(output_path) = output_path {
+        let (decoder, encoder) = codec(path, encoding)?;
+        let rows = filter_file(path, output_path, expression, decoder, encoder)?;
+        outln!("{}  {} rows", output_path.display(), rows)?;
+        return Ok(());
+    }
+    let mut file = File::open(path)?;
+    let (mut data, meta) = open(&mut file, encoding)?;
+    data.filter(expression)?;
+    let mut out = output(&None)?;
+    writeln!(out, "{}", csv_header(&meta))?;
+    while let Some(row) = data.read_line()? {
+        writeln!(out, "{}", csv_row(&row))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn sort(
+    path: &Path,
+    encoding: &str,
+    by: &str,
+    output: &Option<PathBuf>,
+    max_memory: u64,
+) -> Result<bool, XPTError> {
+    let keys = SortKey::parse_by(by)?;
+    let (decoder, encoder) = codec(path, encoding)?;
+    let Some(output) = output else {
+        return Ok(match first_unsorted(path, &keys, decoder)? {
+            Some(row) => {
+                outln!(
+                    "{}  not sorted by {}, first at row {}",
+                    path.display(),
+                    by,
+                    row
+                )?;
+                false
+            }
+            None => {
+                outln!("{}  sorted by {}", path.display(), by)?;
+                true
+            }
+        });
+    };
+    let options = SortOptions {
+        max_memory: max_memory as usize,
+        ..SortOptions::default()
+    };
+    let report = sort_file(path, output, &keys, &options, decoder, encoder)?;
+    outln!(
+        "{}  {} rows, {} sorted runs",
+        output.display(),
+        report.rows,
+        report.runs
+    )?;
+    Ok(true)
+}
+
+fn transform(
+    path: &Path,
+    encoding: &str,
+    output: &Path,
+    transforms: &[Transform],
+) -> Result<(), XPTError> {
+    let (decoder, encoder) = codec(path, encoding)?;
+    let report = transform_file(path, output, transforms, decoder, encoder)?;
+    out!("{}", report)?;
+    Ok(())
+}
+
+fn migrate(
+    path: &Path,
+    encoding: &str,
+    to: DocumentHeader,
+    long_values: LongValues,
+    output: &Path,
+) -> Result<(), XPTError> {
+    let (decoder, encoder) = codec(path, encoding)?;
+    let report = migrate_file(path, output, to, long_values, decoder, encoder)?;
+    out!("{}", report)?;
+    Ok(())
+}
+
+fn shrink(path: &Path, encoding: &str, output: &Path) -> Result<(), XPTError> {
+    let (decoder, encoder) = codec(path, encoding)?;
+    let report = shrink_file(path, output, decoder, encoder)?;
+    out!("{}", report)?;
+    Ok(())
+}
+
+fn split(path: &Path, encoding: &str, limit: SplitLimit, directory: &Path) -> Result<(), XPTError> {
+    let (decoder, encoder) = codec(path, encoding)?;
+    let manifest = split_file(path, directory, limit, decoder, encoder)?;
+    for part in &manifest.parts {
+        outln!(
+            "{}  rows {}-{}  {} bytes",
+            directory.join(&part.file).display(),
+            part.first_row,
+            part.first_row + part.rows - 1,
+            part.bytes
+        )?;
+    }
+    Ok(())
+}
+
+fn merge(
+    parts: &[PathBuf],
+    manifest: &Option<PathBuf>,
+    encoding: &str,
+    output: &Path,
+) -> Result<(), XPTError> {
+    let first = manifest
+        .as_ref()
+        .or(parts.first())
+        .ok_or_else(|| XPTError::EncodeError("no parts to merge".to_string()))?;
+    let sample = match manifest {
+        Some(manifest) => {
+            let listed = SplitManifest::parse(&std::fs::read_to_string(manifest)?)?;
+            let part = listed
+                .parts
+                .first()
+                .ok_or_else(|| XPTError::EncodeError("the manifest lists no parts".to_string()))?;
+            first.parent().unwrap_or(Path::new("")).join(&part.file)
+        }
+        None => first.clone(),
+    };
+    let (decoder, encoder) = codec(&sample, encoding)?;
+    let rows = match manifest {
+        Some(manifest) => merge_manifest(manifest, output, decoder, encoder)?,
+        None => merge_files(parts, output, decoder, encoder)?,
+    };
+    outln!("{}  {} rows", output.display(), rows)?;
+    Ok(())
+}
+
+fn stack(inputs: &[PathBuf], encoding: &str, output: &Path) -> Result<(), XPTError> {
+    let (decoder, encoder) = codec(&inputs[0], encoding)?;
+    let report = stack_files(inputs, output, decoder, encoder)?;
+    out!("{}", report)?;
+    Ok(())
+}
+
+fn join(
+    left: &Path,
+    right: &Path,
+    encoding: &str,
+    keys: &[String],
+    options: &JoinOptions,
+    output: &Path,
+) -> Result<(), XPTError> {
+    let (decoder, encoder) = codec(left, encoding)?;
+    let report = join_files(left, right, output, keys, options, decoder, encoder)?;
+    out!("{}", report)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let success = |_| ExitCode::SUCCESS;
+    let result = match &cli.command {
+        Command::Info { file } => info(file, &cli.encoding).map(success),
+        Command::Head { file, rows } => head(file, &cli.encoding, *rows).map(success),
+        Command::Convert { file, to, output } => {
+            convert(file, &cli.encoding, *to, output).map(success)
+        }
+        Command::Stats { file, top, exact } => {
+            stats(file, &cli.encoding, *top, *exact).map(success)
+        }
+        Command::Filter {
+            file,
+            expression,
+            output,
+        } => filter(file, &cli.encoding, expression, output).map(success),
+        Command::Sort {
+            file,
+            by,
+            output,
+            max_memory,
+            ..
+        } => sort(file, &cli.encoding, by, output, *max_memory).map(|sorted| {
+            if sorted {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(1)
+            }
+        }),
+        Command::Transform {
+            file,
+            output,
+            rename,
+            label,
+            format,
+            drop,
+            order,
+            to_char,
+            to_num,
+        } => {
+            let mut transforms = Vec::new();
+            for (from, to) in rename {
+                transforms.push(Transform::Rename {
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+            for (name, label) in label {
+                transforms.push(Transform::Relabel {
+                    name: name.clone(),
+                    label: label.clone(),
+                });
+            }
+            for (name, format) in format {
+                transforms.push(Transform::Reformat {
+                    name: name.clone(),
+                    format: format.clone(),
+                });
+            }
+            if !drop.is_empty() {
+                transforms.push(Transform::Drop(drop.clone()));
+            }
+            if !order.is_empty() {
+                transforms.push(Transform::Reorder(order.clone()));
+            }
+            for (name, format) in to_char {
+                transforms.push(Transform::ToChar {
+                    name: name.clone(),
+                    format: format.clone(),
+                    length: None,
+                });
+            }
+            for (name, informat) in to_num {
+                transforms.push(Transform::ToNumeric {
+                    name: name.clone(),
+                    informat: informat.clone(),
+                });
+            }
+            transform(file, &cli.encoding, output, &transforms).map(success)
+        }
+        Command::Migrate {
+            file,
+            to,
+            truncate,
+            output,
+        } => {
+            let to = match to {
+                Version::V5 => DocumentHeader::V5,
+                Version::V8 => DocumentHeader::V8,
+            };
+            let long_values = if *truncate {
+                LongValues::Truncate
+            } else {
+                LongValues::Fail
+            };
+            migrate(file, &cli.encoding, to, long_values, output).map(success)
+        }
+        Command::Shrink { file, output } => shrink(file, &cli.encoding, output).map(success),
+        Command::Split {
+            file,
+            max_bytes,
+            max_rows,
+            directory,
+        } => {
+            let limit = match (max_bytes, max_rows) {
+                (Some(bytes), _) => SplitLimit::Bytes(*bytes),
+                (None, rows) => SplitLimit::Rows(rows.unwrap_or_default()),
+            };
+            split(file, &cli.encoding, limit, directory).map(success)
+        }
+        Command::Stack { inputs, output } => stack(inputs, &cli.encoding, output).map(success),
+        Command::Join {
+            left,
+            right,
+            by,
+            how,
+            hashed,
+            conflict,
+            output,
+        } => {
+            let options = JoinOptions {
+                kind: match how {
+                    How::Inner => JoinKind::Inner,
+                    How::Left => JoinKind::Left,
+                    How::Full => JoinKind::Full,
+                },
+                input: if *hashed {
+                    JoinInput::Hashed
+                } else {
+                    JoinInput::Sorted
+                },
+                conflict: match conflict {
+                    Conflict::Right => JoinConflict::Right,
+                    Conflict::Left => JoinConflict::Left,
+                    Conflict::Error => JoinConflict::Error,
+                },
+            };
+            join(left, right, &cli.encoding, by, &options, output).map(success)
+        }
+        Command::Merge {
+            parts,
+            manifest,
+            output,
+        } => merge(parts, manifest, &cli.encoding, output).map(success),
+        Command::Diff {
+            base,
+            compare,
+            id,
+            method,
+            criterion,
+            max_print,
+        } => {
+            let options = CompareOptions {
+                id_columns: id.clone(),
+                method: match method {
+                    Method::Exact => CompareMethod::Exact,
+                    Method::Absolute => CompareMethod::Absolute(*criterion),
+                    Method::Relative => CompareMethod::Relative(*criterion),
+                },
+                max_listing: *max_print,
+            };
+            diff(base, compare, &cli.encoding, &options).map(|equal| {
+                if equal {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::from(1)
+                }
+            })
+        }
+    };
+    result.unwrap_or_else(|error| {
+        eprintln!("xpt: {}", error);
+        ExitCode::from(2)
+    })
+}
@@ -0,0 +1,574 @@
+use crate::error::XPTError;
+use crate::part::{ColumnType, DocumentMeta, StringDecoder};
+use crate::reader::{Reader, Val};
+use crate::validate::{Report, ReportRule, Severity};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Core designation of a variable in the SDTM Implementation Guide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Core {
+    Required,
+    Expected,
+    Permissible,
+}
+
+/// One variable of a domain as described by the implementation guide.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableSpec {
+    pub domain: String,
+    pub name: String,
+    pub label: String,
+    pub column_type: ColumnType,
+    /// Length the guide prescribes, if any.
+    pub length: Option<u16>,
+    pub core: Core,
+}
+
+/// Variable table the structural checks are driven by.
+#[derive(Debug, Clone, Default)]
+pub struct VariableTable {
+    pub variables: Vec<VariableSpec>,
+}
+
+// domain,variable,type,length,core,label; `--` stands for the domain prefix
+const SDTM_IG: &str = "\
+DM,STUDYID,Char,,Req,Study Identifier
+DM,DOMAIN,Char,2,Req,Domain Abbreviation
+DM,USUBJID,Char,,Req,Unique Subject Identifier
+DM,SUBJID,Char,,Req,Subject Identifier for the Study
+DM,RFSTDTC,Char,,Exp,Subject Reference Start Date/Time
+DM,RFENDTC,Char,,Exp,Subject Reference End Date/Time
+DM,SITEID,Char,,Req,Study Site Identifier
+DM,AGE,Num,,Exp,Age
+DM,AGEU,Char,,Exp,Age Units
+DM,SEX,Char,,Req,Sex
+DM,RACE,Char,,Exp,Race
+DM,ARMCD,Char,,Exp,Planned Arm Code
+DM,ARM,Char,,Exp,Description of Planned Arm
+DM,COUNTRY,Char,,Req,Country
+DM,DMDTC,Char,,Perm,Date/Time of Collection
+AE,STUDYID,Char,,Req,Study Identifier
+AE,DOMAIN,Char,2,Req,Domain Abbreviation
+AE,USUBJID,Char,,Req,Unique Subject Identifier
+AE,--SEQ,Num,,Req,Sequence Number
+AE,--TERM,Char,,Req,Reported Term for the Adverse Event
+AE,--DECOD,Char,,Req,Dictionary-Derived Term
+AE,--SER,Char,,Exp,Serious Event
+AE,--REL,Char,,Exp,Causality
+AE,--OUT,Char,,Perm,Outcome of Adverse Event
+AE,--STDTC,Char,,Exp,Start Date/Time of Adverse Event
+AE,--ENDTC,Char,,Exp,End Date/Time of Adverse Event
+LB,STUDYID,Char,,Req,Study Identifier
+LB,DOMAIN,Char,2,Req,Domain Abbreviation
+LB,USUBJID,Char,,Req,Unique Subject Identifier
+LB,--SEQ,Num,,Req,Sequence Number
+LB,--TESTCD,Char,,Req,Lab Test or Examination Short Name
+LB,--TEST,Char,,Req,Lab Test or Examination Name
+LB,--ORRES,Char,,Exp,Result or Finding in Original Units
+LB,--ORRESU,Char,,Exp,Original Units
+LB,--STRESC,Char,,Exp,Character Result/Finding in Std Format
+LB,--STRESN,Num,,Exp,Numeric Result/Finding in Standard Units
+LB,--STRESU,Char,,Exp,Standard Units
+LB,--NRIND,Char,,Exp,Reference Range Indicator
+LB,--STAT,Char,,Perm,Completion Status
+LB,VISITNUM,Num,,Exp,Visit Number
+LB,--DTC,Char,,Exp,Date/Time of Specimen Collection
+VS,STUDYID,Char,,Req,Study Identifier
+VS,DOMAIN,Char,2,Req,Domain Abbreviation
+VS,USUBJID,Char,,Req,Unique Subject Identifier
+VS,--SEQ,Num,,Req,Sequence Number
+VS,--TESTCD,Char,,Req,Vital Signs Test Short Name
+VS,--TEST,Char,,Req,Vital Signs Test Name
+VS,--ORRES,Char,,Exp,Result or Finding in Original Units
+VS,--ORRESU,Char,,Exp,Original Units
+VS,--STRESC,Char,,Exp,Character Result/Finding in Std Format
+VS,--STRESN,Num,,Exp,Numeric Result/Finding in Standard Units
+VS,--STRESU,Char,,Exp,Standard Units
+VS,VISITNUM,Num,,Exp,Visit Number
+VS,--DTC,Char,,Exp,Date/Time of Measurements
+CM,STUDYID,Char,,Req,Study Identifier
+CM,DOMAIN,Char,2,Req,Domain Abbreviation
+CM,USUBJID,Char,,Req,Unique Subject Identifier
+CM,--SEQ,Num,,Req,Sequence Number
+CM,--TRT,Char,,Req,Reported Name of Drug Med or Therapy
+CM,--DECOD,Char,,Perm,Standardized Medication Name
+CM,--STDTC,Char,,Perm,Start Date/Time of Medication
+CM,--ENDTC,Char,,Perm,End Date/Time of Medication
+EX,STUDYID,Char,,Req,Study Identifier
+EX,DOMAIN,Char,2,Req,Domain Abbreviation
+EX,USUBJID,Char,,Req,Unique Subject Identifier
+EX,--SEQ,Num,,Req,Sequence Number
+EX,--TRT,Char,,Req,Name of Treatment
+EX,--DOSE,Num,,Exp,Dose
+EX,--DOSU,Char,,Exp,Dose Units
+EX,--STDTC,Char,,Exp,Start Date/Time of Treatment
+EX,--ENDTC,Char,,Exp,End Date/Time of Treatment
+";
+
+impl VariableTable {
+    /// The bundled subset of the SDTM IG: DM, AE, LB, VS, CM and EX.
+    pub fn sdtm_ig() -> Self {
+        Self::from_csv(SDTM_IG).expect("bundled SDTM IG table is valid")
+    }
+
+    /// Parses lines of `domain,variable,type,length,core,label`, where type is
+    /// `Char` or `Num`, length may be empty, core is `Req`, `Exp` or `Perm` and
+    /// a leading `--` in the variable name stands for the domain code.
+    /// A header line starting with `domain` is skipped.
+    pub fn from_csv(csv: &str) -> Result<Self, XPTError> {
+        let mut variables = Vec::new();
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.to_ascii_lowercase().starts_with("domain,") {
+                continue;
+            }
+            let error = |what: &str| {
+                XPTError::ParseError(format!("line {}: {} in {:?}", number + 1, what, line))
+            };
+            let fields: Vec<&str> = line.splitn(6, ',').map(str::trim).collect();
+            if fields.len() != 6 {
+                return Err(error("expected 6 fields"));
+            }
+            let domain = fields[0].to_ascii_uppercase();
+            let name = match fields[1].strip_prefix("--") {
+                Some(suffix) => format!("{}{}", domain, suffix),
+                None => fields[1].to_string(),
+            };
+            let column_type = match fields[2] {
+                "Char" => ColumnType::CHAR,
+                "Num" => ColumnType::NUMERIC,
+                _ => return Err(error("unknown type")),
+            };
+            let length = match fields[3] {
+                "" => None,
+                length => Some(length.parse().map_err(|_| error("invalid length"))?),
+            };
+            let core = match fields[4] {
+                "Req" => Core::Required,
+                "Exp" => Core::Expected,
+                "Perm" => Core::Permissible,
+                _ => return Err(error("unknown core")),
+            };
+            variables.push(VariableSpec {
+                domain,
+                name,
+                label: fields[5].to_string(),
+                column_type,
+                length,
+                core,
+            });
+        }
+        Ok(VariableTable { variables })
+    }
+
+    pub fn domain<'t>(&'t self, domain: &'t str) -> impl Iterator<Item = &'t VariableSpec> + 't {
+        self.variables
+            .iter()
+            .filter(move |v| v.domain.eq_ignore_ascii_case(domain))
+    }
+}
+
+/// Structural SDTM checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SdtmRule {
+    /// SD001: the domain is not described by the variable table.
+    UnknownDomain,
+    /// SD002: a required variable is missing.
+    RequiredVariable,
+    /// SD003: an expected variable is missing.
+    ExpectedVariable,
+    /// SD004: the variable type differs from the guide.
+    VariableType,
+    /// SD005: the variable length differs from the guide.
+    VariableLength,
+    /// SD006: --SEQ is not unique within a subject.
+    SequenceUnique,
+    /// SD007: a --DTC value is not ISO 8601.
+    Iso8601,
+    /// SD008: DOMAIN does not hold the domain code.
+    DomainValue,
+}
+
+impl ReportRule for SdtmRule {
+    fn id(&self) -> &'static str {
+        match self {
+            SdtmRule::UnknownDomain => "SD001",
+            SdtmRule::RequiredVariable => "SD002",
+            SdtmRule::ExpectedVariable => "SD003",
+            SdtmRule::VariableType => "SD004",
+            SdtmRule::VariableLength => "SD005",
+            SdtmRule::SequenceUnique => "SD006",
+            SdtmRule::Iso8601 => "SD007",
+            SdtmRule::DomainValue => "SD008",
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            SdtmRule::UnknownDomain | SdtmRule::ExpectedVariable => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+pub type ConformanceReport = Report<SdtmRule>;
+
+/// Checks the variables of `meta` against the `domain` entries of `table`.
+pub fn check_meta(domain: &str, meta: &DocumentMeta, table: &VariableTable) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    let columns: HashMap<&str, _> = meta.columns.iter().map(|c| (c.name.as_str(), c)).collect();
+    let mut known = false;
+    for spec in table.domain(domain) {
+        known = true;
+        let name = Some(spec.name.as_str());
+        let Some(column) = columns.get(spec.name.as_str()) else {
+            match spec.core {
+                Core::Required => report.push(
+                    SdtmRule::RequiredVariable,
+                    name,
+                    format!("required variable ({}) is missing", spec.label),
+                ),
+                Core::Expected => report.push(
+                    SdtmRule::ExpectedVariable,
+                    name,
+                    format!("expected variable ({}) is missing", spec.label),
+                ),
+                Core::Permissible => {}
+            }
+            continue;
+        };
+        if column.column_type != spec.column_type {
+            report.push(
+                SdtmRule::VariableType,
+                name,
+                format!(
+                    "type is {:?}, the guide defines {:?}",
+                    column.column_type, spec.column_type
+                ),
+            );
+        }
+        if let Some(length) = spec.length {
+            if column.length != length {
+                report.push(
+                    SdtmRule::VariableLength,
+                    name,
+                    format!("length is {}, the guide defines {}", column.length, length),
+                );
+            }
+        }
+    }
+    if !known {
+        report.push(
+            SdtmRule::UnknownDomain,
+            None,
+            format!("domain {} is not in the variable table", domain),
+        );
+    }
+    report
+}
+
+/// True for ISO 8601 dates and date/times as SDTM uses them, including
+/// partial values such as `2003-12` and `2003---15` with unknown components.
+pub fn is_iso8601(value: &str) -> bool {
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    fn component(part: &str, width: usize, range: std::ops::RangeInclusive<u32>) -> bool {
+        part == "-"
+            || (part.len() == width
+                && part.bytes().all(|b| b.is_ascii_digit())
+                && part.parse::<u32>().is_ok_and(|v| range.contains(&v)))
+    }
+    // YYYY[-MM[-DD]], where an unknown month is written as a single hyphen
+    let mut rest = date;
+    if rest.len() < 4 || !rest.as_bytes()[..4].iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    rest = &rest[4..];
+    let mut parts = Vec::new();
+    while !rest.is_empty() {
+        let Some(after) = rest.strip_prefix('-') else {
+            return false;
+        };
+        if let Some(after_unknown) = after.strip_prefix('-') {
+            parts.push("-");
+            rest = after_unknown;
+        } else {
+            let end = after.find('-').unwrap_or(after.len());
+            parts.push(&after[..end]);
+            rest = &after[end..];
+        }
+    }
+    let date_ok = match parts.as_slice() {
+        [] => true,
+        [month] => component(month, 2, 1..=12) && *month != "-",
+        [month, day] => component(month, 2, 1..=12) && component(day, 2, 1..=31),
+        _ => false,
+    };
+    if !date_ok {
+        return false;
+    }
+    let Some(time) = time else {
+        return true;
+    };
+    // an unknown day cannot be followed by a known time in SDTM, but
+    // hh[:mm[:ss[.fff]]] with unknown components is accepted
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (time, None),
+    };
+    let components: Vec<&str> = time.split(':').collect();
+    let time_ok = match components.as_slice() {
+        [hour] => component(hour, 2, 0..=23),
+        [hour, minute] => component(hour, 2, 0..=23) && component(minute, 2, 0..=59),
+        [hour, minute, second] => {
+            component(hour, 2, 0..=23)
+                && component(minute, 2, 0..=59)
+                && component(second, 2, 0..=59)
+        }
+        _ => false,
+    };
+    time_ok
+        && fraction.is_none_or(|f| {
+            components.len() == 3 && !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit())
+        })
+}
+
+/// Runs the metadata checks and then the value checks row by row.
+pub struct ConformanceChecker {
+    report: ConformanceReport,
+    domain: String,
+    domain_index: Option<usize>,
+    usubjid_index: Option<usize>,
+    seq_index: Option<usize>,
+    dtc_indexes: Vec<(usize, String)>,
+    sequences: HashSet<(String, u64)>,
+    duplicates: HashMap<String, (usize, usize)>,
+    invalid_dates: HashMap<usize, (usize, usize, String)>,
+    wrong_domain: usize,
+    rows: usize,
+}
+
+impl ConformanceChecker {
+    pub fn new(domain: &str, meta: &DocumentMeta, table: &VariableTable) -> Self {
+        let domain = domain.to_ascii_uppercase();
+        let index = |name: &str| meta.columns.iter().position(|c| c.name == name);
+        let dtc_indexes = meta
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.column_type == ColumnType::CHAR && c.name.ends_with("DTC"))
+            .map(|(i, c)| (i, c.name.clone()))
+            .collect();
+        ConformanceChecker {
+            report: check_meta(&domain, meta, table),
+            domain_index: index("DOMAIN"),
+            usubjid_index: index("USUBJID"),
+            seq_index: index(&format!("{}SEQ", domain)),
+            dtc_indexes,
+            domain,
+            sequences: HashSet::new(),
+            duplicates: HashMap::new(),
+            invalid_dates: HashMap::new(),
+            wrong_domain: 0,
+            rows: 0,
+        }
+    }
+
+    pub fn check_row(&mut self, row: &[Val]) {
+        self.rows += 1;
+        if let Some(Val::Char(value)) = self.domain_index.map(|i| &row[i]) {
+            if *value != self.domain {
+                self.wrong_domain += 1;
+            }
+        }
+        if let (Some(Val::Char(subject)), Some(Val::Number(seq))) = (
+            self.usubjid_index.map(|i| &row[i]),
+            self.seq_index.map(|i| &row[i]),
+        ) {
+            if !self.sequences.insert((subject.clone(), seq.to_bits())) {
+                let entry = self
+                    .duplicates
+                    .entry(subject.clone())
+                    .or_insert((0, self.rows));
+                entry.0 += 1;
+            }
+        }
+        for (i, _) in &self.dtc_indexes {
+            if let Val::Char(value) = &row[*i] {
+                if !value.is_empty() && !is_iso8601(value) {
+                    let entry =
+                        self.invalid_dates
+                            .entry(*i)
+                            .or_insert((0, self.rows, value.clone()));
+                    entry.0 += 1;
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> ConformanceReport {
+        if self.wrong_domain > 0 {
+            self.report.push(
+                SdtmRule::DomainValue,
+                Some("DOMAIN"),
+                format!("{} rows do not hold {}", self.wrong_domain, self.domain),
+            );
+        }
+        let mut duplicates: Vec<_> = self.duplicates.into_iter().collect();
+        duplicates.sort_by_key(|(_, (_, first))| *first);
+        let seq_name = format!("{}SEQ", self.domain);
+        for (subject, (count, first)) in duplicates {
+            self.report.push(
+                SdtmRule::SequenceUnique,
+                Some(&seq_name),
+                format!(
+                    "{} duplicate values for subject {}, first in row {}",
+                    count, subject, first
+                ),
+            );
+        }
+        for (i, name) in &self.dtc_indexes {
+            if let Some((count, first, value)) = self.invalid_dates.get(i) {
+                self.report.push(
+                    SdtmRule::Iso8601,
+                    Some(name),
+                    format!(
+                        "{} values are not ISO 8601, first {:?} in row {}",
+                        count, value, first
+                    ),
+                );
+            }
+        }
+        self.report
+    }
+}
+
+/// Checks the file at `path` as the `domain` dataset against `table`.
+#[cfg(not(feature = "async"))]
+pub fn check_file<P: AsRef<Path>>(
+    path: P,
+    domain: &str,
+    table: &VariableTable,
+    string_decoder: StringDecoder,
+) -> Result<ConformanceReport, XPTError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut checker = ConformanceChecker::new(domain, &meta, table);
+    while let Some(row) = data.read_line()? {
+        checker.check_row(&row);
+    }
+    Ok(checker.finish())
+}
+
+/// Checks the file at `path` as the `domain` dataset against `table`.
+#[cfg(feature = "async")]
+pub async fn check_file<P: AsRef<Path>>(
+    path: P,
+    domain: &str,
+    table: &VariableTable,
+    string_decoder: StringDecoder,
+) -> Result<ConformanceReport, XPTError> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut checker = ConformanceChecker::new(domain, &meta, table);
+    while let Some(row) = data.read_line().await? {
+        checker.check_row(&row);
+    }
+    Ok(checker.finish())
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(not(feature = "async"))]
+    use crate::cdisc::{check_file, SdtmRule};
+    use crate::cdisc::{is_iso8601, Core, VariableTable};
+    use crate::part::ColumnType;
+    #[cfg(not(feature = "async"))]
+    use crate::reader::UTF8_STRING_DECODER;
+
+    #[test]
+    fn test_variable_table() {
+        let table = VariableTable::sdtm_ig();
+        let seq = table.domain("lb").find(|v| v.name == "LBSEQ").unwrap();
+        assert_eq!(seq.column_type, ColumnType::NUMERIC);
+        assert_eq!(seq.core, Core::Required);
+
+        let custom = VariableTable::from_csv(
+            "domain,variable,type,length,core,label\nXX,--SEQ,Num,8,Req,Sequence Number\n",
+        )
+        .unwrap();
+        assert_eq!(custom.variables[0].name, "XXSEQ");
+        assert_eq!(custom.variables[0].length, Some(8));
+        assert!(VariableTable::from_csv("XX,--SEQ,Text,,Req,Sequence Number").is_err());
+    }
+
+    #[test]
+    fn test_is_iso8601() {
+        for valid in [
+            "2013",
+            "2013-01",
+            "2013-01-11",
+            "2013-01-11T08",
+            "2013-01-11T08:30",
+            "2013-01-11T08:30:15",
+            "2013-01-11T08:30:15.125",
+            "2003---15",
+            "2003-12-15T-:15",
+        ] {
+            assert!(is_iso8601(valid), "{}", valid);
+        }
+        for invalid in [
+            "13",
+            "2013/01/11",
+            "2013-13-01",
+            "2013-01-32",
+            "2013-00-00",
+            "2013-01-00",
+            "2013-1-1",
+            "11JAN2013",
+            "2013-01-11T25:00",
+            "2013-01-11T08:30.5",
+            "2013-01-11 08:30",
+        ] {
+            assert!(!is_iso8601(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_check_file() {
+        let table = VariableTable::sdtm_ig();
+        let report = check_file("sample/LB2.xpt", "LB", &table, UTF8_STRING_DECODER).unwrap();
+        assert!(report.is_valid(), "{}", report);
+        let missing: Vec<_> = report
+            .findings
+            .iter()
+            .filter(|f| f.rule == SdtmRule::ExpectedVariable)
+            .filter_map(|f| f.variable.as_deref())
+            .collect();
+        assert_eq!(
+            missing,
+            vec!["LBORRESU", "LBSTRESC", "LBSTRESU", "LBNRIND", "VISITNUM"]
+        );
+
+        let mut bytes = std::fs::read("sample/LB2.xpt").unwrap();
+        let at = bytes.windows(10).position(|w| w == b"2013-01-12").unwrap();
+        bytes[at..at + 10].copy_from_slice(b"12JAN2013 ");
+        let path = std::env::temp_dir().join("xpt-r-cdisc-lb.xpt");
+        std::fs::write(&path, bytes).unwrap();
+        let report = check_file(&path, "LB", &table, UTF8_STRING_DECODER).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let finding = report
+            .findings
+            .iter()
+            .find(|f| f.rule == SdtmRule::Iso8601)
+            .unwrap();
+        assert_eq!(finding.variable.as_deref(), Some("LBDTC"));
+        assert!(!report.is_valid());
+    }
+}
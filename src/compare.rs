@@ -0,0 +1,814 @@
+use crate::error::XPTError;
+use crate::number::byte2number;
+use crate::part::{ColumnMeta, DocumentMeta, StringDecoder};
+use crate::reader::Reader;
+use crate::view::RowView;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Write};
+use std::path::Path;
+
+/// How NUMERIC values are judged equal, after PROC COMPARE's `METHOD=`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CompareMethod {
+    #[default]
+    Exact,
+    /// Equal when the absolute difference is at most the criterion.
+    Absolute(f64),
+    /// Equal when the difference is at most the criterion times the larger
+    /// magnitude of the two values.
+    Relative(f64),
+}
+
+impl CompareMethod {
+    fn equal(&self, base: f64, compare: f64) -> bool {
+        let difference = (base - compare).abs();
+        match self {
+            CompareMethod::Exact => base == compare,
+            CompareMethod::Absolute(criterion) => difference <= *criterion,
+            CompareMethod::Relative(criterion) => {
+                difference <= criterion * base.abs().max(compare.abs())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompareOptions {
+    /// Variables matching base and compare observations. Observations are
+    /// matched by position when empty.
+    pub id_columns: Vec<String>,
+    pub method: CompareMethod,
+    /// Differences listed per variable, PROC COMPARE's `MAXPRINT=`.
+    pub max_listing: usize,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        CompareOptions {
+            id_columns: Vec::new(),
+            method: CompareMethod::Exact,
+            max_listing: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeDifference {
+    OnlyInBase,
+    OnlyInCompare,
+    Type,
+    Length(u16, u16),
+    Label(String, String),
+    Format(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataDifference {
+    pub column: String,
+    pub difference: AttributeDifference,
+}
+
+/// One unequal value, with the ID values of its observation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueDifference {
+    /// ID values, or the observation number when matching by position.
+    pub id: Vec<String>,
+    pub base: String,
+    pub compare: String,
+    /// Compare minus base, for NUMERIC values that are both present.
+    pub difference: Option<f64>,
+}
+
+/// Unequal values of one variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDifferences {
+    pub column: String,
+    pub numeric: bool,
+    pub unequal: usize,
+    pub max_difference: Option<f64>,
+    /// The first `max_listing` differences.
+    pub listing: Vec<ValueDifference>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CompareReport {
+    pub id_columns: Vec<String>,
+    pub base_rows: usize,
+    pub compare_rows: usize,
+    pub base_columns: usize,
+    pub compare_columns: usize,
+    pub common_columns: usize,
+    pub metadata: Vec<MetadataDifference>,
+    /// Observations matched in both datasets.
+    pub common_rows: usize,
+    /// Matched observations with at least one unequal value.
+    pub unequal_rows: usize,
+    /// ID values of the observations found in one dataset only.
+    pub only_in_base: Vec<Vec<String>>,
+    pub only_in_compare: Vec<Vec<String>>,
+    /// ID values of observations whose IDs repeat in the base dataset,
+    /// which are left out of the comparison.
+    pub duplicate_base_ids: Vec<Vec<String>>,
+    /// ID values of observations whose IDs repeat in the compare dataset,
+    /// which are left out of the comparison.
+    pub duplicate_compare_ids: Vec<Vec<String>>,
+    /// Variables compared, in base order, with their unequal values.
+    pub variables: Vec<VariableDifferences>,
+}
+
+impl CompareReport {
+    /// True when metadata, observations and values all match.
+    pub fn is_equal(&self) -> bool {
+        self.metadata.is_empty()
+            && self.only_in_base.is_empty()
+            && self.only_in_compare.is_empty()
+            && self.duplicate_base_ids.is_empty()
+            && self.duplicate_compare_ids.is_empty()
+            && self.unequal_rows == 0
+    }
+
+    pub fn unequal_values(&self) -> usize {
+        self.variables.iter().map(|v| v.unequal).sum()
+    }
+}
+
+/// A value as compared. Missing NUMERIC values keep the code they are
+/// stored with, so `.A` and `.B` differ as they do in SAS.
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    Number(f64),
+    Missing(u8),
+    Char(String),
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cell::Number(number) => write!(f, "{}", number),
+            Cell::Missing(b'.') => write!(f, "."),
+            Cell::Missing(code) => write!(f, ".{}", *code as char),
+            Cell::Char(str) => write!(f, "{}", str),
+        }
+    }
+}
+
+fn cells(row: &RowView) -> Result<Vec<Cell>, XPTError> {
+    (0..row.len())
+        .map(|i| {
+            let bytes = row.get_bytes(i);
+            Ok(if !row.is_numeric(i) {
+                Cell::Char(row.get_str(i)?)
+            } else if let Some(number) = byte2number(bytes) {
+                Cell::Number(number)
+            } else {
+                Cell::Missing(bytes.first().copied().unwrap_or(b'.'))
+            })
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum KeyPart {
+    Number(u64),
+    Missing(u8),
+    Char(String),
+}
+
+fn key_part(cell: &Cell) -> KeyPart {
+    match cell {
+        // 0.0 and -0.0 identify the same observation
+        Cell::Number(number) => KeyPart::Number((number + 0.0).to_bits()),
+        Cell::Missing(code) => KeyPart::Missing(*code),
+        Cell::Char(str) => KeyPart::Char(str.clone()),
+    }
+}
+
+fn format_name(column: &ColumnMeta) -> String {
+    crate::define::display_format(&column.format).unwrap_or_default()
+}
+
+/// Compares a base dataset with a compare dataset, in the manner of
+/// PROC COMPARE. The compare observations are held in memory, the base
+/// observations are streamed through [`Comparison::check_base`].
+pub struct Comparison {
+    options: CompareOptions,
+    report: CompareReport,
+    // (base index, compare index) of the compared variables
+    compared: Vec<(usize, usize)>,
+    base_ids: Vec<usize>,
+    compare_ids: Vec<usize>,
+    compare_rows: Vec<Vec<Cell>>,
+    matched: Vec<bool>,
+    index: HashMap<Vec<KeyPart>, usize>,
+    base_keys: HashSet<Vec<KeyPart>>,
+}
+
+impl Comparison {
+    pub fn new(
+        base: &DocumentMeta,
+        compare: &DocumentMeta,
+        options: &CompareOptions,
+    ) -> Result<Self, XPTError> {
+        let position = |meta: &DocumentMeta, name: &str| {
+            meta.columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(name))
+        };
+        let mut base_ids = Vec::new();
+        let mut compare_ids = Vec::new();
+        for id in &options.id_columns {
+            match (position(base, id), position(compare, id)) {
+                (Some(b), Some(c)) => {
+                    base_ids.push(b);
+                    compare_ids.push(c);
+                }
+                _ => {
+                    return Err(XPTError::ParseError(format!(
+                        "ID variable {} is not in both datasets",
+                        id
+                    )))
+                }
+            }
+        }
+        let mut report = CompareReport {
+            id_columns: options.id_columns.clone(),
+            base_columns: base.columns.len(),
+            compare_columns: compare.columns.len(),
+            ..Default::default()
+        };
+        let mut compared = Vec::new();
+        for (b, column) in base.columns.iter().enumerate() {
+            let mut push = |difference| {
+                report.metadata.push(MetadataDifference {
+                    column: column.name.clone(),
+                    difference,
+                })
+            };
+            let Some(c) = position(compare, &column.name) else {
+                push(AttributeDifference::OnlyInBase);
+                continue;
+            };
+            let other = &compare.columns[c];
+            if column.column_type != other.column_type {
+                push(AttributeDifference::Type);
+            } else if !base_ids.contains(&b) {
+                compared.push((b, c));
+            }
+            if column.length != other.length {
+                push(AttributeDifference::Length(column.length, other.length));
+            }
+            if column.label != other.label {
+                push(AttributeDifference::Label(
+                    column.label.clone(),
+                    other.label.clone(),
+                ));
+            }
+            if format_name(column) != format_name(other) {
+                push(AttributeDifference::Format(
+                    format_name(column),
+                    format_name(other),
+                ));
+            }
+        }
+        report.common_columns = base.columns.len()
+            - report
+                .metadata
+                .iter()
+                .filter(|m| m.difference == AttributeDifference::OnlyInBase)
+                .count();
+        for column in &compare.columns {
+            if position(base, &column.name).is_none() {
+                report.metadata.push(MetadataDifference {
+                    column: column.name.clone(),
+                    difference: AttributeDifference::OnlyInCompare,
+                });
+            }
+        }
+        report.variables = compared
+            .iter()
+            .map(|(b, _)| VariableDifferences {
+                column: base.columns[*b].name.clone(),
+                numeric: base.columns[*b].column_type == crate::part::ColumnType::NUMERIC,
+                unequal: 0,
+                max_difference: None,
+                listing: Vec::new(),
+            })
+            .collect();
+        Ok(Comparison {
+            options: options.clone(),
+            report,
+            compared,
+            base_ids,
+            compare_ids,
+            compare_rows: Vec::new(),
+            matched: Vec::new(),
+            index: HashMap::new(),
+            base_keys: HashSet::new(),
+        })
+    }
+
+    /// Adds the next observation of the compare dataset.
+    pub fn add_compare(&mut self, row: &RowView) -> Result<(), XPTError> {
+        let row = cells(row)?;
+        if !self.compare_ids.is_empty() {
+            let key = self
+                .compare_ids
+                .iter()
+                .map(|i| key_part(&row[*i]))
+                .collect();
+            if self.index.contains_key(&key) {
+                let id = self
+                    .compare_ids
+                    .iter()
+                    .map(|i| row[*i].to_string())
+                    .collect();
+                self.report.duplicate_compare_ids.push(id);
+                return Ok(());
+            }
+            self.index.insert(key, self.compare_rows.len());
+        }
+        self.compare_rows.push(row);
+        self.matched.push(false);
+        self.report.compare_rows += 1;
+        Ok(())
+    }
+
+    /// Compares the next observation of the base dataset.
+    pub fn check_base(&mut self, row: &RowView) -> Result<(), XPTError> {
+        let row = cells(row)?;
+        self.report.base_rows += 1;
+        let (found, id) = if self.base_ids.is_empty() {
+            let i = self.report.base_rows - 1;
+            (
+                Some(i).filter(|i| *i < self.compare_rows.len()),
+                vec![self.report.base_rows.to_string()],
+            )
+        } else {
+            let key: Vec<_> = self.base_ids.iter().map(|i| key_part(&row[*i])).collect();
+            let id = self.base_ids.iter().map(|i| row[*i].to_string()).collect();
+            if self.base_keys.contains(&key) {
+                self.report.duplicate_base_ids.push(id);
+                return Ok(());
+            }
+            let found = self.index.get(&key).copied();
+            self.base_keys.insert(key);
+            (found, id)
+        };
+        let Some(found) = found else {
+            self.report.only_in_base.push(id);
+            return Ok(());
+        };
+        self.matched[found] = true;
+        self.report.common_rows += 1;
+        let other = &self.compare_rows[found];
+        let mut unequal = false;
+        for ((b, c), variable) in self.compared.iter().zip(&mut self.report.variables) {
+            let (base, compare) = (&row[*b], &other[*c]);
+            let (equal, difference) = match (base, compare) {
+                (Cell::Number(x), Cell::Number(y)) => {
+                    (self.options.method.equal(*x, *y), Some(y - x))
+                }
+                (x, y) => (x == y, None),
+            };
+            if equal {
+                continue;
+            }
+            unequal = true;
+            variable.unequal += 1;
+            if let Some(difference) = difference {
+                let max = variable.max_difference.get_or_insert(0.0);
+                *max = max.max(difference.abs());
+            }
+            if variable.listing.len() < self.options.max_listing {
+                variable.listing.push(ValueDifference {
+                    id: id.clone(),
+                    base: base.to_string(),
+                    compare: compare.to_string(),
+                    difference,
+                });
+            }
+        }
+        if unequal {
+            self.report.unequal_rows += 1;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> CompareReport {
+        for (row, matched) in self.compare_rows.iter().zip(&self.matched) {
+            if *matched {
+                continue;
+            }
+            let id = if self.compare_ids.is_empty() {
+                vec![(self.report.only_in_compare.len() + self.report.base_rows + 1).to_string()]
+            } else {
+                self.compare_ids
+                    .iter()
+                    .map(|i| row[*i].to_string())
+                    .collect()
+            };
+            self.report.only_in_compare.push(id);
+        }
+        self.report
+    }
+}
+
+fn count_rule(f: &mut std::fmt::Formatter<'_>, label: &str, count: usize) -> std::fmt::Result {
+    writeln!(f, "  {:<62} {}", label, count)
+}
+
+impl Display for CompareReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let only = |kind: AttributeDifference| {
+            self.metadata
+                .iter()
+                .filter(|m| m.difference == kind)
+                .map(|m| m.column.as_str())
+                .collect::<Vec<_>>()
+        };
+        writeln!(f, "Data Set Summary")?;
+        writeln!(
+            f,
+            "  Base:    {} observations, {} variables",
+            self.base_rows, self.base_columns
+        )?;
+        writeln!(
+            f,
+            "  Compare: {} observations, {} variables",
+            self.compare_rows, self.compare_columns
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Variables Summary")?;
+        count_rule(f, "Number of variables in common:", self.common_columns)?;
+        for (label, kind) in [
+            (
+                "Number of variables in base only:",
+                AttributeDifference::OnlyInBase,
+            ),
+            (
+                "Number of variables in compare only:",
+                AttributeDifference::OnlyInCompare,
+            ),
+            (
+                "Number of variables with conflicting types:",
+                AttributeDifference::Type,
+            ),
+        ] {
+            let columns = only(kind);
+            if !columns.is_empty() {
+                writeln!(
+                    f,
+                    "  {:<62} {} ({})",
+                    label,
+                    columns.len(),
+                    columns.join(" ")
+                )?;
+            }
+        }
+        let attributes: Vec<_> = self
+            .metadata
+            .iter()
+            .filter_map(|m| {
+                let (attribute, base, compare) = match &m.difference {
+                    AttributeDifference::Length(b, c) => ("Len", b.to_string(), c.to_string()),
+                    AttributeDifference::Label(b, c) => ("Label", b.clone(), c.clone()),
+                    AttributeDifference::Format(b, c) => ("Format", b.clone(), c.clone()),
+                    _ => return None,
+                };
+                Some((m.column.as_str(), attribute, base, compare))
+            })
+            .collect();
+        if !attributes.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Listing of Common Variables with Differing Attributes")?;
+            writeln!(
+                f,
+                "  {:<10} {:<8} {:<40} Compare",
+                "Variable", "Attribute", "Base"
+            )?;
+            for (column, attribute, base, compare) in attributes {
+                writeln!(
+                    f,
+                    "  {:<10} {:<8} {:<40} {}",
+                    column, attribute, base, compare
+                )?;
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "Observation Summary")?;
+        count_rule(f, "Number of observations in common:", self.common_rows)?;
+        count_rule(
+            f,
+            "Number of observations in base only:",
+            self.only_in_base.len(),
+        )?;
+        count_rule(
+            f,
+            "Number of observations in compare only:",
+            self.only_in_compare.len(),
+        )?;
+        for (label, rows) in [
+            (
+                "Number of duplicate observations in base:",
+                &self.duplicate_base_ids,
+            ),
+            (
+                "Number of duplicate observations in compare:",
+                &self.duplicate_compare_ids,
+            ),
+        ] {
+            if !rows.is_empty() {
+                count_rule(f, label, rows.len())?;
+            }
+        }
+        count_rule(
+            f,
+            "Number of observations with some compared variables unequal:",
+            self.unequal_rows,
+        )?;
+        count_rule(
+            f,
+            "Number of observations with all compared variables equal:",
+            self.common_rows - self.unequal_rows,
+        )?;
+        let id_label = if self.id_columns.is_empty() {
+            "Obs".to_string()
+        } else {
+            self.id_columns.join(" ")
+        };
+        for (title, rows) in [
+            ("in Base Only", &self.only_in_base),
+            ("in Compare Only", &self.only_in_compare),
+            ("with Duplicate IDs in Base", &self.duplicate_base_ids),
+            ("with Duplicate IDs in Compare", &self.duplicate_compare_ids),
+        ] {
+            if !rows.is_empty() {
+                writeln!(f)?;
+                writeln!(f, "Observations {}", title)?;
+                writeln!(f, "  {}", id_label)?;
+                for id in rows {
+                    writeln!(f, "  {}", id.join(" "))?;
+                }
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "Values Comparison Summary")?;
+        let unequal: Vec<_> = self.variables.iter().filter(|v| v.unequal > 0).collect();
+        count_rule(
+            f,
+            "Number of variables compared with all observations equal:",
+            self.variables.len() - unequal.len(),
+        )?;
+        count_rule(
+            f,
+            "Number of variables compared with some observations unequal:",
+            unequal.len(),
+        )?;
+        count_rule(
+            f,
+            "Total number of values which compare unequal:",
+            self.unequal_values(),
+        )?;
+        if unequal.is_empty() {
+            return Ok(());
+        }
+        writeln!(f)?;
+        writeln!(f, "Variables with Unequal Values")?;
+        writeln!(
+            f,
+            "  {:<10} {:<5} {:>6}  MaxDif",
+            "Variable", "Type", "Ndif"
+        )?;
+        for variable in &unequal {
+            let line = format!(
+                "  {:<10} {:<5} {:>6}  {}",
+                variable.column,
+                if variable.numeric { "NUM" } else { "CHAR" },
+                variable.unequal,
+                variable
+                    .max_difference
+                    .map(|d| d.to_string())
+                    .unwrap_or_default()
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Value Comparison Results for Variables")?;
+        for variable in unequal {
+            writeln!(f)?;
+            writeln!(f, "  {}", variable.column)?;
+            let mut line = format!("  {:<24} || {:>20}  {:>20}", id_label, "Base", "Compare");
+            if variable.numeric {
+                line.push_str(&format!("  {:>12}  {:>9}", "Diff.", "% Diff"));
+            }
+            writeln!(f, "{}", line)?;
+            for value in &variable.listing {
+                let mut line = format!(
+                    "  {:<24} || {:>20}  {:>20}",
+                    value.id.join(" "),
+                    value.base,
+                    value.compare
+                );
+                if let Some(difference) = value.difference {
+                    let base: f64 = value.base.parse().unwrap_or(0.0);
+                    let _ = write!(line, "  {:>12}", difference);
+                    if base != 0.0 {
+                        let _ = write!(line, "  {:>9.4}", difference / base * 100.0);
+                    }
+                }
+                writeln!(f, "{}", line)?;
+            }
+            if variable.unequal > variable.listing.len() {
+                writeln!(
+                    f,
+                    "  ... {} more not listed",
+                    variable.unequal - variable.listing.len()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares the file at `base` with the file at `compare`.
+#[cfg(not(feature = "async"))]
+pub fn compare_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    base: P,
+    compare: Q,
+    string_decoder: StringDecoder,
+    options: &CompareOptions,
+) -> Result<CompareReport, XPTError> {
+    let mut base_file = std::fs::File::open(base)?;
+    let mut compare_file = std::fs::File::open(compare)?;
+    let mut base_reader = Reader::new(&mut base_file, string_decoder);
+    let mut compare_reader = Reader::new(&mut compare_file, string_decoder);
+    let (mut base_data, base_meta) = base_reader.start()?;
+    let (mut compare_data, compare_meta) = compare_reader.start()?;
+    let mut comparison = Comparison::new(&base_meta, &compare_meta, options)?;
+    while let Some(row) = compare_data.read_row()? {
+        comparison.add_compare(&row)?;
+    }
+    while let Some(row) = base_data.read_row()? {
+        comparison.check_base(&row)?;
+    }
+    Ok(comparison.finish())
+}
+
+/// Compares the file at `base` with the file at `compare`.
+#[cfg(feature = "async")]
+pub async fn compare_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    base: P,
+    compare: Q,
+    string_decoder: StringDecoder,
+    options: &CompareOptions,
+) -> Result<CompareReport, XPTError> {
+    let mut base_file = tokio::fs::File::open(base).await?;
+    let mut compare_file = tokio::fs::File::open(compare).await?;
+    let mut base_reader = Reader::new(&mut base_file, string_decoder);
+    let mut compare_reader = Reader::new(&mut compare_file, string_decoder);
+    let (mut base_data, base_meta) = base_reader.start().await?;
+    let (mut compare_data, compare_meta) = compare_reader.start().await?;
+    let mut comparison = Comparison::new(&base_meta, &compare_meta, options)?;
+    while let Some(row) = compare_data.read_row().await? {
+        comparison.add_compare(&row)?;
+    }
+    while let Some(row) = base_data.read_row().await? {
+        comparison.check_base(&row)?;
+    }
+    Ok(comparison.finish())
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(not(feature = "async"))]
+    use crate::compare::{compare_files, Comparison};
+    use crate::compare::{AttributeDifference, CompareMethod, CompareOptions};
+    #[cfg(not(feature = "async"))]
+    use crate::number::number2byte;
+    #[cfg(not(feature = "async"))]
+    use crate::reader::{Reader, UTF8_STRING_DECODER};
+    #[cfg(not(feature = "async"))]
+    use crate::view::RowView;
+
+    #[test]
+    fn test_compare_method() {
+        assert!(CompareMethod::Exact.equal(1.0, 1.0));
+        assert!(!CompareMethod::Exact.equal(1.0, 1.0 + 1e-15));
+        assert!(CompareMethod::Absolute(1e-9).equal(1.0, 1.0 + 1e-10));
+        assert!(!CompareMethod::Absolute(1e-9).equal(1.0, 1.001));
+        assert!(CompareMethod::Relative(1e-3).equal(1000.0, 1000.5));
+        assert!(!CompareMethod::Relative(1e-3).equal(1.0, 1.5));
+        let options = CompareOptions::default();
+        assert_eq!(options.max_listing, 50);
+        assert_ne!(AttributeDifference::OnlyInBase, AttributeDifference::Type);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_compare_files() {
+        let options = CompareOptions {
+            id_columns: vec!["USUBJID".to_string(), "LBSEQ".to_string()],
+            ..Default::default()
+        };
+        let report = compare_files(
+            "sample/LB2.xpt",
+            "sample/LB2.xpt",
+            UTF8_STRING_DECODER,
+            &options,
+        )
+        .unwrap();
+        assert!(report.is_equal(), "{}", report);
+        assert_eq!(report.common_rows, 9);
+        assert_eq!(report.variables.len(), 7);
+
+        // compare against an altered copy: one value off, one observation missing
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, meta) = reader.start().unwrap();
+        let columns = data.columns().to_vec();
+        let mut lines = Vec::new();
+        while let Some(row) = data.read_row().unwrap() {
+            lines.push(row.line().to_vec());
+        }
+        let cell = columns[7].0 as usize..columns[7].0 as usize + 8;
+        let mut comparison = Comparison::new(&meta, &meta, &options).unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            let mut copy = line.clone();
+            if i == 1 {
+                number2byte(Some(18.5), &mut copy[cell.clone()]).unwrap();
+            }
+            if i != 8 {
+                comparison
+                    .add_compare(&RowView::new(&copy, &columns, UTF8_STRING_DECODER))
+                    .unwrap();
+            }
+        }
+        for line in &lines {
+            comparison
+                .check_base(&RowView::new(line, &columns, UTF8_STRING_DECODER))
+                .unwrap();
+        }
+        let report = comparison.finish();
+        assert!(!report.is_equal());
+        assert_eq!(report.only_in_base, vec![vec!["01-701-1017", "309"]]);
+        assert_eq!(report.unequal_rows, 1);
+        let stresn = report
+            .variables
+            .iter()
+            .find(|v| v.column == "LBSTRESN")
+            .unwrap();
+        assert_eq!(stresn.unequal, 1);
+        assert_eq!(stresn.max_difference, Some(0.5));
+        assert_eq!(stresn.listing[0].id, vec!["01-701-1015", "102"]);
+        let text = report.to_string();
+        assert!(text.contains("Number of observations in base only:"));
+        assert!(text.contains("LBSTRESN"));
+
+        // special missing values differ by their code, and repeated IDs
+        // are reported for either dataset
+        let mut special = lines.clone();
+        special[0][cell.clone()].copy_from_slice(b"A\0\0\0\0\0\0\0");
+        special[1][cell.clone()].copy_from_slice(b"B\0\0\0\0\0\0\0");
+        let mut altered = special.clone();
+        altered[0][cell.clone()].copy_from_slice(b"B\0\0\0\0\0\0\0");
+        let mut comparison = Comparison::new(&meta, &meta, &options).unwrap();
+        for line in altered.iter().chain(&altered[2..3]) {
+            comparison
+                .add_compare(&RowView::new(line, &columns, UTF8_STRING_DECODER))
+                .unwrap();
+        }
+        for line in special.iter().chain(&special[3..4]) {
+            comparison
+                .check_base(&RowView::new(line, &columns, UTF8_STRING_DECODER))
+                .unwrap();
+        }
+        let report = comparison.finish();
+        assert_eq!(report.unequal_rows, 1);
+        let stresn = report
+            .variables
+            .iter()
+            .find(|v| v.column == "LBSTRESN")
+            .unwrap();
+        assert_eq!(
+            (
+                stresn.listing[0].base.as_str(),
+                stresn.listing[0].compare.as_str()
+            ),
+            (".A", ".B")
+        );
+        assert_eq!(report.duplicate_base_ids, vec![vec!["01-701-1016", "204"]]);
+        assert_eq!(
+            report.duplicate_compare_ids,
+            vec![vec!["01-701-1015", "103"]]
+        );
+        let text = report.to_string();
+        assert!(text.contains("Number of duplicate observations in base:"));
+        assert!(text.contains("Observations with Duplicate IDs in Compare"));
+
+        let missing = CompareOptions {
+            id_columns: vec!["VISITNUM".to_string()],
+            ..Default::default()
+        };
+        assert!(Comparison::new(&meta, &meta, &missing).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::part::DocumentMeta;
+use crate::reader::Val;
+use crate::validate::json_string;
+#[cfg(feature = "parquet")]
+use {
+    crate::batch::{Batch, BatchColumn},
+    crate::error::XPTError,
+    crate::part::{ColumnType, StringDecoder},
+    arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray},
+    arrow_schema::{DataType, Field, Schema},
+    parquet::arrow::ArrowWriter,
+    std::sync::Arc,
+};
+
+/// Quotes `str` for CSV when it holds a separator, quote or line break.
+pub fn csv_field(str: &str) -> String {
+    if str.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", str.replace('"', "\"\""))
+    } else {
+        str.to_string()
+    }
+}
+
+/// The CSV header line of the column names, without a line break.
+pub fn csv_header(meta: &DocumentMeta) -> String {
+    meta.columns
+        .iter()
+        .map(|column| csv_field(&column.name))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// One row as a CSV line, without a line break. Missing values are empty.
+pub fn csv_row(row: &[Val]) -> String {
+    row.iter()
+        .map(|value| csv_field(&value.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// One row as a JSON object keyed by column name. Missing NUMERIC values
+/// are `null`.
+pub fn json_row(meta: &DocumentMeta, row: &[Val]) -> String {
+    let fields: Vec<String> = meta
+        .columns
+        .iter()
+        .zip(row)
+        .map(|(column, value)| {
+            let value = match value {
+                Val::Number(number) => number.to_string(),
+                Val::Char(str) => json_string(str),
+                Val::Nil => "null".to_string(),
+            };
+            format!("{}:{}", json_string(&column.name), value)
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Writes batches to a Parquet file, NUMERIC columns as nullable doubles and
+/// CHAR columns as strings with trailing blanks removed.
+#[cfg(feature = "parquet")]
+pub struct ParquetWriter<W: std::io::Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: Arc<Schema>,
+    string_decoder: StringDecoder,
+}
+
+#[cfg(feature = "parquet")]
+fn parquet_error(error: impl std::fmt::Display) -> XPTError {
+    XPTError::EncodeError(error.to_string())
+}
+
+#[cfg(feature = "parquet")]
+impl<W: std::io::Write + Send> ParquetWriter<W> {
+    pub fn new(
+        output: W,
+        meta: &DocumentMeta,
+        string_decoder: StringDecoder,
+    ) -> Result<Self, XPTError> {
+        let fields: Vec<Field> = meta
+            .columns
+            .iter()
+            .map(|column| {
+                let data_type = match column.column_type {
+                    ColumnType::NUMERIC => DataType::Float64,
+                    ColumnType::CHAR => DataType::Utf8,
+                };
+                Field::new(column.name.clone(), data_type, true)
+            })
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+        let writer = ArrowWriter::try_new(output, schema.clone(), None).map_err(parquet_error)?;
+        Ok(ParquetWriter {
+            writer,
+            schema,
+            string_decoder,
+        })
+    }
+
+    pub fn write_batch(&mut self, batch: &Batch) -> Result<(), XPTError> {
+        let mut arrays: Vec<ArrayRef> = Vec::with_capacity(batch.columns.len());
+        for column in &batch.columns {
+            arrays.push(match column {
+                BatchColumn::Numeric(values) => Arc::new(Float64Array::from(values.clone())),
+                BatchColumn::Char(values) => {
+                    let strings = values
+                        .iter()
+                        .map(self.string_decoder)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Arc::new(StringArray::from(strings))
+                }
+            });
+        }
+        let record_batch =
+            RecordBatch::try_new(self.schema.clone(), arrays).map_err(parquet_error)?;
+        self.writer.write(&record_batch).map_err(parquet_error)
+    }
+
+    pub fn finish(self) -> Result<(), XPTError> {
+        self.writer.close().map(|_| ()).map_err(parquet_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::convert::{csv_field, csv_row};
+    use crate::reader::Val;
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("5\" tall"), "\"5\"\" tall\"");
+        let row = vec![Val::Char("ALT".to_string()), Val::Number(22.0), Val::Nil];
+        assert_eq!(csv_row(&row), "ALT,22,");
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_json_row() {
+        use crate::convert::json_row;
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, meta) = reader.start().unwrap();
+        let row = data.read_line().unwrap().unwrap();
+        let json = json_row(&meta, &row);
+        assert!(json.starts_with("{\"STUDYID\":\"CDISCPILOT01\",\"DOMAIN\":\"LB\""));
+        assert!(json.contains("\"LBSEQ\":101,"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "parquet", not(feature = "async")))]
+    fn test_parquet_writer() {
+        use crate::convert::ParquetWriter;
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, meta) = reader.start().unwrap();
+        let mut output = Vec::new();
+        let mut writer = ParquetWriter::new(&mut output, &meta, UTF8_STRING_DECODER).unwrap();
+        while let Some(batch) = data.read_batch(4).unwrap() {
+            writer.write_batch(&batch).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(&output[..4], b"PAR1");
+        assert_eq!(&output[output.len() - 4..], b"PAR1");
+    }
+}
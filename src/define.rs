@@ -0,0 +1,939 @@
+use crate::error::XPTError;
+use crate::part::{ColumnMeta, ColumnType, DocumentMeta, StringDecoder};
+use crate::reader::{Reader, Val};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DefineVersion {
+    #[default]
+    V2_0,
+    V2_1,
+}
+
+impl DefineVersion {
+    fn namespace(&self) -> &'static str {
+        match self {
+            DefineVersion::V2_0 => "http://www.cdisc.org/ns/def/v2.0",
+            DefineVersion::V2_1 => "http://www.cdisc.org/ns/def/v2.1",
+        }
+    }
+
+    fn number(&self) -> &'static str {
+        match self {
+            DefineVersion::V2_0 => "2.0.0",
+            DefineVersion::V2_1 => "2.1.0",
+        }
+    }
+}
+
+/// Study level content of the generated define.xml.
+#[derive(Debug, Clone)]
+pub struct DefineOptions {
+    pub version: DefineVersion,
+    pub study_name: String,
+    pub study_description: String,
+    pub protocol_name: String,
+    /// `SDTMIG`, `ADaMIG` or `SENDIG`.
+    pub standard_name: String,
+    pub standard_version: String,
+    /// `Tabulation` for SDTM and SEND, `Analysis` for ADaM.
+    pub purpose: String,
+    /// ISO 8601 creation time, the current UTC time when `None`.
+    pub creation_date_time: Option<String>,
+}
+
+impl Default for DefineOptions {
+    fn default() -> Self {
+        DefineOptions {
+            version: DefineVersion::V2_0,
+            study_name: String::new(),
+            study_description: String::new(),
+            protocol_name: String::new(),
+            standard_name: "SDTMIG".to_string(),
+            standard_version: "3.3".to_string(),
+            purpose: "Tabulation".to_string(),
+            creation_date_time: None,
+        }
+    }
+}
+
+/// A dataset to describe: one ItemGroupDef and the ItemDefs of its columns.
+#[derive(Debug, Clone)]
+pub struct DefineDataset {
+    pub name: String,
+    pub label: String,
+    /// File name the `def:leaf` points at.
+    pub file_name: String,
+    pub columns: Vec<ColumnMeta>,
+}
+
+impl DefineDataset {
+    /// Describes `meta` as stored in `file_name`, which names the dataset.
+    pub fn new(file_name: &str, meta: &DocumentMeta) -> Self {
+        let name = Path::new(file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_ascii_uppercase())
+            .unwrap_or_default();
+        DefineDataset {
+            name,
+            label: meta.dataset_label.clone(),
+            file_name: file_name.to_string(),
+            columns: meta.columns.clone(),
+        }
+    }
+}
+
+/// Observation class of the standard SDTM and ADaM datasets.
+pub fn dataset_class(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "DM" | "CO" | "SE" | "SV" => "SPECIAL PURPOSE",
+        "CM" | "EX" | "EC" | "PR" | "SU" | "AG" | "ML" => "INTERVENTIONS",
+        "AE" | "CE" | "DS" | "DV" | "HO" | "MH" => "EVENTS",
+        "DA" | "DD" | "EG" | "FA" | "IE" | "IS" | "LB" | "MB" | "MI" | "MK" | "MS" | "PC"
+        | "PE" | "PP" | "QS" | "RP" | "RS" | "SC" | "SS" | "TR" | "TU" | "VS" => "FINDINGS",
+        "TA" | "TD" | "TE" | "TI" | "TM" | "TS" | "TV" => "TRIAL DESIGN",
+        "ADSL" => "SUBJECT LEVEL ANALYSIS DATASET",
+        "ADAE" => "OCCURRENCE DATA STRUCTURE",
+        name if name.starts_with("AD") => "BASIC DATA STRUCTURE",
+        _ => return None,
+    })
+}
+
+/// A format or informat of a column written out, such as `8.2` or `DATE9.`.
+pub fn display_format(format: &(String, u16, u16)) -> Option<String> {
+    let (name, length, decimals) = format;
+    if name.is_empty() && *length == 0 {
+        return None;
+    }
+    let mut format = name.clone();
+    if *length > 0 {
+        let _ = write!(format, "{}", length);
+    }
+    format.push('.');
+    if *decimals > 0 {
+        let _ = write!(format, "{}", decimals);
+    }
+    Some(format)
+}
+
+pub(crate) fn xml_escape(str: &str) -> String {
+    let mut xml = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '"' => xml.push_str("&quot;"),
+            '\'' => xml.push_str("&apos;"),
+            c => xml.push(c),
+        }
+    }
+    xml
+}
+
+fn description(xml: &mut String, indent: &str, text: &str) {
+    let _ = writeln!(
+        xml,
+        "{indent}<Description><TranslatedText xml:lang=\"en\">{}</TranslatedText></Description>",
+        xml_escape(text)
+    );
+}
+
+/// Current UTC date and time of day as `(year, month, day, seconds)`.
+pub(crate) fn utc_today() -> (i64, i64, i64, u64) {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, time) = ((seconds / 86400) as i64, seconds % 86400);
+    let (year, month, day) = civil_from_days(days);
+    (year, month, day, time)
+}
+
+/// Civil date `(year, month, day)` of a day counted from 1970-01-01, after
+/// Howard Hinnant.
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days from 1970-01-01 to a civil date, the inverse of [`civil_from_days`].
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Current UTC time as `YYYY-MM-DDThh:mm:ss`.
+fn utc_now() -> String {
+    let (year, month, day, time) = utc_today();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Writes a define.xml skeleton describing `datasets`.
+///
+/// Datasets, variables, data types, lengths and display formats are filled
+/// in; origins, codelists and comments are left for the data standards team.
+pub fn write_define(datasets: &[DefineDataset], options: &DefineOptions) -> String {
+    let version = options.version;
+    let study = xml_escape(&options.study_name);
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<ODM xmlns=\"http://www.cdisc.org/ns/odm/v1.3\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+         xmlns:def=\"{}\" ODMVersion=\"1.3.2\" FileType=\"Snapshot\" FileOID=\"DEF.{}\" \
+         CreationDateTime=\"{}\">",
+        version.namespace(),
+        study,
+        options.creation_date_time.clone().unwrap_or_else(utc_now),
+    );
+    let _ = writeln!(xml, "  <Study OID=\"ST.{}\">", study);
+    xml.push_str("    <GlobalVariables>\n");
+    let _ = writeln!(xml, "      <StudyName>{}</StudyName>", study);
+    let _ = writeln!(
+        xml,
+        "      <StudyDescription>{}</StudyDescription>",
+        xml_escape(&options.study_description)
+    );
+    let _ = writeln!(
+        xml,
+        "      <ProtocolName>{}</ProtocolName>",
+        xml_escape(&options.protocol_name)
+    );
+    xml.push_str("    </GlobalVariables>\n");
+    let standard = xml_escape(&options.standard_name);
+    let standard_version = xml_escape(&options.standard_version);
+    match version {
+        DefineVersion::V2_0 => {
+            let _ = writeln!(
+                xml,
+                "    <MetaDataVersion OID=\"MDV.{study}\" Name=\"{study}\" def:DefineVersion=\"{}\" \
+                 def:StandardName=\"{standard}\" def:StandardVersion=\"{standard_version}\">",
+                version.number()
+            );
+        }
+        DefineVersion::V2_1 => {
+            let _ = writeln!(
+                xml,
+                "    <MetaDataVersion OID=\"MDV.{study}\" Name=\"{study}\" def:DefineVersion=\"{}\">",
+                version.number()
+            );
+            xml.push_str("      <def:Standards>\n");
+            let _ = writeln!(
+                xml,
+                "        <def:Standard OID=\"STD.1\" Name=\"{standard}\" Type=\"IG\" \
+                 Version=\"{standard_version}\" Status=\"Final\"/>"
+            );
+            xml.push_str("      </def:Standards>\n");
+        }
+    }
+    let purpose = xml_escape(&options.purpose);
+    for dataset in datasets {
+        let name = xml_escape(&dataset.name);
+        let domain = if name.starts_with("AD") {
+            String::new()
+        } else {
+            format!(" Domain=\"{}\"", name)
+        };
+        let (class_attribute, class_element) = match (dataset_class(&dataset.name), version) {
+            (Some(class), DefineVersion::V2_0) => (format!(" def:Class=\"{}\"", class), None),
+            (Some(class), DefineVersion::V2_1) => (String::new(), Some(class)),
+            (None, _) => (String::new(), None),
+        };
+        let standard_oid = if version == DefineVersion::V2_1 {
+            " def:StandardOID=\"STD.1\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            xml,
+            "      <ItemGroupDef OID=\"IG.{name}\"{domain} Name=\"{name}\" SASDatasetName=\"{name}\" \
+             Repeating=\"Yes\" IsReferenceData=\"No\" Purpose=\"{purpose}\"{class_attribute} \
+             def:Structure=\"\"{standard_oid} def:ArchiveLocationID=\"LF.{name}\">"
+        );
+        description(&mut xml, "        ", &dataset.label);
+        for (order, column) in dataset.columns.iter().enumerate() {
+            let _ = writeln!(
+                xml,
+                "        <ItemRef ItemOID=\"IT.{name}.{}\" OrderNumber=\"{}\" Mandatory=\"No\"/>",
+                xml_escape(&column.name),
+                order + 1
+            );
+        }
+        if let Some(class) = class_element {
+            let _ = writeln!(xml, "        <def:Class Name=\"{}\"/>", class);
+        }
+        let file_name = xml_escape(&dataset.file_name);
+        let _ = writeln!(
+            xml,
+            "        <def:leaf ID=\"LF.{name}\" xlink:href=\"{file_name}\"><def:title>{file_name}</def:title></def:leaf>"
+        );
+        xml.push_str("      </ItemGroupDef>\n");
+    }
+    for dataset in datasets {
+        let name = xml_escape(&dataset.name);
+        for column in &dataset.columns {
+            let column_name = xml_escape(&column.name);
+            let (data_type, length) = match column.column_type {
+                // the stored length of a numeric is its precision, not a width
+                ColumnType::NUMERIC => ("float", String::new()),
+                ColumnType::CHAR => ("text", format!(" Length=\"{}\"", column.length)),
+            };
+            let format = display_format(&column.format)
+                .map(|format| format!(" def:DisplayFormat=\"{}\"", xml_escape(&format)))
+                .unwrap_or_default();
+            let _ = writeln!(
+                xml,
+                "      <ItemDef OID=\"IT.{name}.{column_name}\" Name=\"{column_name}\" \
+                 DataType=\"{data_type}\"{length} SASFieldName=\"{column_name}\"{format}>"
+            );
+            description(&mut xml, "        ", &column.label);
+            xml.push_str("      </ItemDef>\n");
+        }
+    }
+    xml.push_str("    </MetaDataVersion>\n");
+    xml.push_str("  </Study>\n");
+    xml.push_str("</ODM>\n");
+    xml
+}
+
+/// Reads the headers of the files at `paths` and writes their define.xml.
+#[cfg(not(feature = "async"))]
+pub fn define_files<P: AsRef<Path>>(
+    paths: &[P],
+    string_decoder: StringDecoder,
+    options: &DefineOptions,
+) -> Result<String, XPTError> {
+    let mut datasets = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = path.as_ref();
+        let mut file = std::fs::File::open(path)?;
+        let (_, meta) = Reader::new(&mut file, string_decoder).start()?;
+        datasets.push(DefineDataset::new(&file_name(path), &meta));
+    }
+    Ok(write_define(&datasets, options))
+}
+
+/// Reads the headers of the files at `paths` and writes their define.xml.
+#[cfg(feature = "async")]
+pub async fn define_files<P: AsRef<Path>>(
+    paths: &[P],
+    string_decoder: StringDecoder,
+    options: &DefineOptions,
+) -> Result<String, XPTError> {
+    let mut datasets = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::open(path).await?;
+        let (_, meta) = Reader::new(&mut file, string_decoder).start().await?;
+        datasets.push(DefineDataset::new(&file_name(path), &meta));
+    }
+    Ok(write_define(&datasets, options))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Origin of a variable as documented by `def:Origin`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Origin {
+    /// `Collected`, `Derived`, `Assigned`, `Protocol`, `Predecessor`, ...
+    pub origin_type: String,
+    /// define.xml 2.1 `Source`, such as `Investigator` or `Sponsor`.
+    pub source: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeListItem {
+    pub coded_value: String,
+    /// `None` for the items of an enumerated list.
+    pub decode: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CodeList {
+    pub oid: String,
+    pub name: String,
+    pub data_type: String,
+    pub items: Vec<CodeListItem>,
+}
+
+impl CodeList {
+    fn item(&self, value: &Val) -> Option<&CodeListItem> {
+        match value {
+            Val::Char(value) => self.items.iter().find(|item| item.coded_value == *value),
+            Val::Number(value) => self
+                .items
+                .iter()
+                .find(|item| item.coded_value.parse::<f64>().ok() == Some(*value)),
+            Val::Nil => None,
+        }
+    }
+
+    /// True when `value` is one of the coded values. Missing values and
+    /// blank CHAR values are not checked.
+    pub fn contains(&self, value: &Val) -> bool {
+        match value {
+            Val::Nil => true,
+            Val::Char(str) if str.is_empty() => true,
+            value => self.item(value).is_some(),
+        }
+    }
+
+    pub fn decode(&self, value: &Val) -> Option<&str> {
+        self.item(value)?.decode.as_deref()
+    }
+}
+
+/// A variable as documented by its ItemDef.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VariableDefinition {
+    pub oid: String,
+    pub name: String,
+    pub label: String,
+    pub data_type: String,
+    pub length: Option<u32>,
+    pub significant_digits: Option<u32>,
+    pub display_format: Option<String>,
+    pub origin: Option<Origin>,
+    pub codelist: Option<CodeList>,
+}
+
+/// A dataset as documented by its ItemGroupDef, variables in ItemRef order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DatasetDefinition {
+    pub name: String,
+    pub label: String,
+    pub variables: Vec<VariableDefinition>,
+}
+
+/// The dataset and variable documentation read from a define.xml.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Define {
+    pub datasets: Vec<DatasetDefinition>,
+}
+
+impl Define {
+    /// Parses a define.xml 2.0 or 2.1 document.
+    pub fn parse(xml: &str) -> Result<Self, XPTError> {
+        let mut groups: Vec<(DatasetDefinition, Vec<String>)> = Vec::new();
+        let mut items: HashMap<String, (VariableDefinition, Option<String>)> = HashMap::new();
+        let mut codelists: HashMap<String, CodeList> = HashMap::new();
+        let mut item: Option<(VariableDefinition, Option<String>)> = None;
+        let mut codelist: Option<CodeList> = None;
+        let mut stack: Vec<&str> = Vec::new();
+        for event in XmlTokens::new(xml) {
+            match event? {
+                XmlEvent::Start {
+                    name,
+                    attributes,
+                    empty,
+                } => {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|(name, _)| *name == key)
+                            .map(|(_, value)| value.clone())
+                    };
+                    let number = |key: &str| attribute(key).and_then(|v| v.trim().parse().ok());
+                    match name {
+                        "ItemGroupDef" => groups.push((
+                            DatasetDefinition {
+                                name: attribute("Name").unwrap_or_default(),
+                                ..Default::default()
+                            },
+                            Vec::new(),
+                        )),
+                        "ItemRef" if stack.last() == Some(&"ItemGroupDef") => {
+                            if let (Some(group), Some(oid)) =
+                                (groups.last_mut(), attribute("ItemOID"))
+                            {
+                                group.1.push(oid);
+                            }
+                        }
+                        "ItemDef" => {
+                            item = Some((
+                                VariableDefinition {
+                                    oid: attribute("OID").unwrap_or_default(),
+                                    name: attribute("Name").unwrap_or_default(),
+                                    data_type: attribute("DataType").unwrap_or_default(),
+                                    length: number("Length"),
+                                    significant_digits: number("SignificantDigits"),
+                                    display_format: attribute("DisplayFormat"),
+                                    ..Default::default()
+                                },
+                                None,
+                            ))
+                        }
+                        "CodeListRef" => {
+                            if let Some((_, reference)) = &mut item {
+                                *reference = attribute("CodeListOID");
+                            }
+                        }
+                        "Origin" => {
+                            if let Some((variable, _)) = &mut item {
+                                variable.origin = Some(Origin {
+                                    origin_type: attribute("Type").unwrap_or_default(),
+                                    source: attribute("Source"),
+                                    description: None,
+                                });
+                            }
+                        }
+                        "CodeList" => {
+                            codelist = Some(CodeList {
+                                oid: attribute("OID").unwrap_or_default(),
+                                name: attribute("Name").unwrap_or_default(),
+                                data_type: attribute("DataType").unwrap_or_default(),
+                                items: Vec::new(),
+                            })
+                        }
+                        "CodeListItem" | "EnumeratedItem" => {
+                            if let Some(codelist) = &mut codelist {
+                                codelist.items.push(CodeListItem {
+                                    coded_value: attribute("CodedValue").unwrap_or_default(),
+                                    decode: None,
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                    if !empty {
+                        stack.push(name);
+                    } else {
+                        end_element(name, &mut item, &mut items, &mut codelist, &mut codelists);
+                    }
+                }
+                XmlEvent::End(name) => {
+                    if stack.pop() != Some(name) {
+                        return Err(XPTError::ParseError(format!(
+                            "unexpected closing tag {}",
+                            name
+                        )));
+                    }
+                    end_element(name, &mut item, &mut items, &mut codelist, &mut codelists);
+                }
+                XmlEvent::Text(text) => {
+                    let n = stack.len();
+                    if n < 3 || stack[n - 1] != "TranslatedText" {
+                        continue;
+                    }
+                    match (stack[n - 3], stack[n - 2]) {
+                        ("ItemGroupDef", "Description") => {
+                            if let Some((group, _)) = groups.last_mut() {
+                                group.label = text;
+                            }
+                        }
+                        ("ItemDef", "Description") => {
+                            if let Some((variable, _)) = &mut item {
+                                variable.label = text;
+                            }
+                        }
+                        ("Origin", "Description") => {
+                            if let Some(origin) = item.as_mut().and_then(|i| i.0.origin.as_mut()) {
+                                origin.description = Some(text);
+                            }
+                        }
+                        ("CodeListItem", "Decode") => {
+                            if let Some(last) = codelist.as_mut().and_then(|c| c.items.last_mut()) {
+                                last.decode = Some(text);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if let Some(open) = stack.last() {
+            return Err(XPTError::ParseError(format!("unclosed tag {}", open)));
+        }
+        let datasets = groups
+            .into_iter()
+            .map(|(mut dataset, oids)| {
+                dataset.variables = oids
+                    .iter()
+                    .filter_map(|oid| items.get(oid))
+                    .map(|(variable, reference)| VariableDefinition {
+                        codelist: reference.as_ref().and_then(|r| codelists.get(r)).cloned(),
+                        ..variable.clone()
+                    })
+                    .collect();
+                dataset
+            })
+            .collect();
+        Ok(Define { datasets })
+    }
+
+    pub fn dataset(&self, name: &str) -> Option<&DatasetDefinition> {
+        self.datasets
+            .iter()
+            .find(|dataset| dataset.name.eq_ignore_ascii_case(name))
+    }
+}
+
+fn end_element(
+    name: &str,
+    item: &mut Option<(VariableDefinition, Option<String>)>,
+    items: &mut HashMap<String, (VariableDefinition, Option<String>)>,
+    codelist: &mut Option<CodeList>,
+    codelists: &mut HashMap<String, CodeList>,
+) {
+    match name {
+        "ItemDef" => {
+            if let Some(item) = item.take() {
+                items.insert(item.0.oid.clone(), item);
+            }
+        }
+        "CodeList" => {
+            if let Some(codelist) = codelist.take() {
+                codelists.insert(codelist.oid.clone(), codelist);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Documentation of the columns of one file, matched by variable name.
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    columns: Vec<Option<VariableDefinition>>,
+}
+
+impl Overlay {
+    pub fn new(dataset: &DatasetDefinition, meta: &DocumentMeta) -> Self {
+        Overlay {
+            columns: meta
+                .columns
+                .iter()
+                .map(|column| {
+                    dataset
+                        .variables
+                        .iter()
+                        .find(|v| v.name.eq_ignore_ascii_case(&column.name))
+                        .cloned()
+                })
+                .collect(),
+        }
+    }
+
+    /// The definition of column `i`, `None` when the define.xml lacks it.
+    pub fn column(&self, i: usize) -> Option<&VariableDefinition> {
+        self.columns.get(i)?.as_ref()
+    }
+
+    /// Decode of `value` in the codelist of column `i`.
+    pub fn decode(&self, i: usize, value: &Val) -> Option<&str> {
+        self.column(i)?.codelist.as_ref()?.decode(value)
+    }
+
+    /// Indexes of the cells of `row` whose value is not in their codelist.
+    pub fn out_of_codelist(&self, row: &[Val]) -> Vec<usize> {
+        row.iter()
+            .enumerate()
+            .filter(|(i, value)| {
+                self.column(*i)
+                    .and_then(|v| v.codelist.as_ref())
+                    .is_some_and(|codelist| !codelist.contains(value))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+enum XmlEvent<'x> {
+    Start {
+        name: &'x str,
+        attributes: Vec<(&'x str, String)>,
+        empty: bool,
+    },
+    End(&'x str),
+    Text(String),
+}
+
+/// Just enough of an XML pull parser for define.xml. Namespace prefixes are
+/// dropped from element and attribute names.
+struct XmlTokens<'x> {
+    xml: &'x str,
+    position: usize,
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn xml_unescape(str: &str) -> Result<String, XPTError> {
+    let mut text = String::with_capacity(str.len());
+    let mut rest = str;
+    while let Some(at) = rest.find('&') {
+        text.push_str(&rest[..at]);
+        let end = rest[at..]
+            .find(';')
+            .ok_or_else(|| XPTError::ParseError(format!("unterminated entity in {:?}", str)))?;
+        let entity = &rest[at + 1..at + end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|d| d.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        text.push(c.ok_or_else(|| XPTError::ParseError(format!("unknown entity &{};", entity)))?);
+        rest = &rest[at + end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+impl<'x> XmlTokens<'x> {
+    fn new(xml: &'x str) -> Self {
+        XmlTokens { xml, position: 0 }
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<&'x str, XPTError> {
+        let rest = &self.xml[self.position..];
+        let at = rest
+            .find(end)
+            .ok_or_else(|| XPTError::ParseError(format!("missing {:?}", end)))?;
+        self.position += at + end.len();
+        Ok(&rest[..at])
+    }
+
+    fn tag(&mut self) -> Result<Option<XmlEvent<'x>>, XPTError> {
+        let rest = &self.xml[self.position..];
+        if rest.starts_with("<?") {
+            self.skip_past("?>")?;
+            return Ok(None);
+        }
+        if rest.starts_with("<!--") {
+            self.skip_past("-->")?;
+            return Ok(None);
+        }
+        if rest.starts_with("<![CDATA[") {
+            self.position += "<![CDATA[".len();
+            return Ok(Some(XmlEvent::Text(self.skip_past("]]>")?.to_string())));
+        }
+        if rest.starts_with("<!") {
+            self.skip_past(">")?;
+            return Ok(None);
+        }
+        self.position += 1;
+        let tag = self.skip_past(">")?;
+        if let Some(name) = tag.strip_prefix('/') {
+            return Ok(Some(XmlEvent::End(local_name(name.trim()))));
+        }
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut attributes = Vec::new();
+        let mut rest = tag[name_end..].trim_start();
+        while !rest.is_empty() {
+            let malformed = || XPTError::ParseError(format!("malformed attributes in <{}>", tag));
+            let equals = rest.find('=').ok_or_else(malformed)?;
+            let key = rest[..equals].trim();
+            let value = rest[equals + 1..].trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'');
+            let quote = quote.ok_or_else(malformed)?;
+            let end = value[1..].find(quote).ok_or_else(malformed)?;
+            attributes.push((local_name(key), xml_unescape(&value[1..end + 1])?));
+            rest = value[end + 2..].trim_start();
+        }
+        Ok(Some(XmlEvent::Start {
+            name: local_name(&tag[..name_end]),
+            attributes,
+            empty,
+        }))
+    }
+}
+
+impl<'x> Iterator for XmlTokens<'x> {
+    type Item = Result<XmlEvent<'x>, XPTError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.xml.len() {
+            let rest = &self.xml[self.position..];
+            if rest.starts_with('<') {
+                match self.tag() {
+                    Ok(None) => continue,
+                    result => return result.transpose(),
+                }
+            }
+            let end = rest.find('<').unwrap_or(rest.len());
+            self.position += end;
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                return Some(xml_unescape(text).map(XmlEvent::Text));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::define::{dataset_class, xml_escape, xml_unescape, Define};
+    #[cfg(not(feature = "async"))]
+    use crate::define::{define_files, DefineOptions, DefineVersion, Overlay};
+    use crate::reader::Val;
+    #[cfg(not(feature = "async"))]
+    use crate::reader::{Reader, UTF8_STRING_DECODER};
+
+    const DEFINE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- hand written for the tests -->
+<ODM xmlns="http://www.cdisc.org/ns/odm/v1.3" xmlns:def="http://www.cdisc.org/ns/def/v2.0">
+  <Study OID="ST.CDISC01">
+    <MetaDataVersion OID="MDV.CDISC01" Name="CDISC01">
+      <ItemGroupDef OID="IG.LB" Name="LB" Repeating="Yes" def:Class="FINDINGS">
+        <Description><TranslatedText xml:lang="en">Laboratory Test Results</TranslatedText></Description>
+        <ItemRef ItemOID="IT.LB.LBTESTCD" OrderNumber="1" Mandatory="Yes"/>
+        <ItemRef ItemOID="IT.LB.LBSTRESN" OrderNumber="2" Mandatory="No"/>
+      </ItemGroupDef>
+      <ItemDef OID="IT.LB.LBTESTCD" Name="LBTESTCD" DataType="text" Length="8">
+        <Description><TranslatedText xml:lang="en">Lab Test &amp; Examination Short Name</TranslatedText></Description>
+        <CodeListRef CodeListOID="CL.LBTESTCD"/>
+        <def:Origin Type="Assigned"/>
+      </ItemDef>
+      <ItemDef OID="IT.LB.LBSTRESN" Name="LBSTRESN" DataType="float" SignificantDigits="2" def:DisplayFormat="8.2">
+        <Description><TranslatedText xml:lang="en">Numeric Result</TranslatedText></Description>
+        <def:Origin Type="Derived">
+          <Description><TranslatedText xml:lang="en">LBORRES converted to standard units</TranslatedText></Description>
+        </def:Origin>
+      </ItemDef>
+      <ItemDef OID="IT.LB.LBORRES.ALT" Name="LBORRES" DataType="text" Length="20"/>
+      <CodeList OID="CL.LBTESTCD" Name="Laboratory Test Code" DataType="text">
+        <CodeListItem CodedValue="ALT"><Decode><TranslatedText>Alanine Aminotransferase</TranslatedText></Decode></CodeListItem>
+        <CodeListItem CodedValue="AST"><Decode><TranslatedText><![CDATA[Aspartate Aminotransferase]]></TranslatedText></Decode></CodeListItem>
+      </CodeList>
+    </MetaDataVersion>
+  </Study>
+</ODM>
+"#;
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(xml_escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+        assert_eq!(dataset_class("LB"), Some("FINDINGS"));
+        assert_eq!(dataset_class("ADLB"), Some("BASIC DATA STRUCTURE"));
+        assert_eq!(dataset_class("XX"), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_define_files() {
+        let mut options = DefineOptions {
+            study_name: "CDISC01".to_string(),
+            creation_date_time: Some("2026-01-01T00:00:00".to_string()),
+            ..Default::default()
+        };
+        let xml = define_files(&["sample/LB2.xpt"], UTF8_STRING_DECODER, &options).unwrap();
+        assert!(xml.contains("xmlns:def=\"http://www.cdisc.org/ns/def/v2.0\""));
+        assert!(xml.contains("<ItemGroupDef OID=\"IG.LB2\" Domain=\"LB2\" Name=\"LB2\""));
+        assert!(xml.contains(
+            "<ItemDef OID=\"IT.LB2.LBSEQ\" Name=\"LBSEQ\" DataType=\"float\" SASFieldName=\"LBSEQ\">"
+        ));
+        assert!(xml.contains("Name=\"USUBJID\" DataType=\"text\" Length=\"20\""));
+        assert!(xml.contains("<def:leaf ID=\"LF.LB2\" xlink:href=\"lb2.xpt\">"));
+        assert_eq!(xml.matches("<ItemRef ").count(), 9);
+        assert_eq!(xml.matches("<ItemDef ").count(), 9);
+        assert!(xml
+            .contains("<TranslatedText xml:lang=\"en\">Laboratory Test Results</TranslatedText>"));
+        assert_eq!(xml.matches("</ItemDef>").count(), 9);
+
+        options.version = DefineVersion::V2_1;
+        let xml = define_files(&["sample/LB2.xpt"], UTF8_STRING_DECODER, &options).unwrap();
+        assert!(xml.contains("def:DefineVersion=\"2.1.0\""));
+        assert!(xml.contains("<def:Standard OID=\"STD.1\" Name=\"SDTMIG\""));
+    }
+
+    #[test]
+    fn test_parse_define() {
+        assert_eq!(xml_unescape("a &lt;&#65;&#x42;&gt;").unwrap(), "a <AB>");
+        let define = Define::parse(DEFINE).unwrap();
+        let lb = define.dataset("lb").unwrap();
+        assert_eq!(lb.label, "Laboratory Test Results");
+        assert_eq!(lb.variables.len(), 2);
+        let testcd = &lb.variables[0];
+        assert_eq!(testcd.label, "Lab Test & Examination Short Name");
+        assert_eq!(testcd.length, Some(8));
+        assert_eq!(testcd.origin.as_ref().unwrap().origin_type, "Assigned");
+        let codelist = testcd.codelist.as_ref().unwrap();
+        assert_eq!(
+            codelist.decode(&Val::Char("AST".to_string())),
+            Some("Aspartate Aminotransferase")
+        );
+        assert!(!codelist.contains(&Val::Char("GLUC".to_string())));
+        assert!(codelist.contains(&Val::Char(String::new())));
+        let stresn = &lb.variables[1];
+        assert_eq!(stresn.significant_digits, Some(2));
+        assert_eq!(stresn.display_format.as_deref(), Some("8.2"));
+        assert_eq!(
+            stresn.origin.as_ref().unwrap().description.as_deref(),
+            Some("LBORRES converted to standard units")
+        );
+        assert!(Define::parse("<ODM><Study></ODM>").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_overlay() {
+        let options = DefineOptions::default();
+        let generated = define_files(&["sample/LB2.xpt"], UTF8_STRING_DECODER, &options).unwrap();
+        let define = Define::parse(&generated).unwrap();
+        let lb2 = define.dataset("LB2").unwrap();
+        assert_eq!(lb2.variables.len(), 9);
+        assert_eq!(lb2.variables[2].name, "USUBJID");
+        assert_eq!(lb2.variables[2].length, Some(20));
+
+        let define = Define::parse(DEFINE).unwrap();
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, meta) = reader.start().unwrap();
+        data.set_overlay(Overlay::new(define.dataset("LB").unwrap(), &meta));
+        let overlay = data.overlay().unwrap();
+        assert!(overlay.column(0).is_none());
+        assert_eq!(overlay.column(7).unwrap().significant_digits, Some(2));
+        let mut flagged = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            if !data.out_of_codelist().is_empty() {
+                assert_eq!(data.out_of_codelist(), &[4]);
+                flagged.push(row[4].to_string());
+            } else {
+                assert!(data.overlay().unwrap().decode(4, &row[4]).is_some());
+            }
+        }
+        assert_eq!(flagged, vec!["GLUC"; 3]);
+    }
+}
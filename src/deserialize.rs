@@ -1,5 +1,5 @@
 use crate::deserialize_in_order;
-use crate::part::{V5NameSt, V8NameSt, XptHeader};
+use crate::part::{V5NameSt, V8NameSt};
 
 // 基础trait
 pub trait FromBytes {
@@ -35,11 +35,6 @@ impl<const COUNT: usize> FromBytes for U8Array<COUNT> {
         }
     }
 }
-impl<T: XptHeader> FromBytes for BufferFromByteArray<T> {
-    fn from_bytes(input: &[u8]) -> Self {
-        BufferFromByteArray(T::new(input.try_into().unwrap()).unwrap())
-    }
-}
 
 impl FromBytes for V5NameSt {
     fn from_bytes(input: &[u8]) -> Self {
@@ -78,6 +73,7 @@ impl FromBytes for V5NameSt {
             nifd,
             npos,
             rest: String::new(),
+            raw: input.to_vec(),
         }
     }
 }
@@ -121,15 +117,14 @@ impl FromBytes for V8NameSt {
             nifd,
             npos,
             nlname,
-            lablen,
             rest: String::new(),
+            lablen,
+            raw: input.to_vec(),
         }
     }
 }
 
 #[derive(Debug)]
-pub(crate) struct U8Array<const COUNT: usize> {
-    pub(crate) inner: [u8; COUNT],
+pub struct U8Array<const COUNT: usize> {
+    pub inner: [u8; COUNT],
 }
-
-pub struct BufferFromByteArray<T>(pub T);
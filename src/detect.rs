@@ -0,0 +1,317 @@
+use crate::part::{StringDecoder, StringEncoder};
+#[cfg(feature = "multi_encoding")]
+use crate::reader::{
+    GBK_LOSSY_STRING_DECODER, GBK_STRING_DECODER, SHIFT_JIS_LOSSY_STRING_DECODER,
+    SHIFT_JIS_STRING_DECODER,
+};
+use crate::reader::{LATIN1_STRING_DECODER, UTF8_LOSSY_STRING_DECODER, UTF8_STRING_DECODER};
+#[cfg(feature = "multi_encoding")]
+use crate::writer::{GBK_STRING_ENCODER, SHIFT_JIS_STRING_ENCODER};
+use crate::writer::{LATIN1_STRING_ENCODER, UTF8_STRING_ENCODER};
+use std::fmt::Display;
+
+/// Candidate encodings for the text stored in an XPT file.
+///
+/// XPT carries no encoding field, so this is always a guess made from
+/// the bytes of names, labels and CHAR values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextEncoding {
+    Utf8,
+    Gbk,
+    ShiftJis,
+    Latin1,
+}
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 4] = [
+        TextEncoding::Utf8,
+        TextEncoding::Gbk,
+        TextEncoding::ShiftJis,
+        TextEncoding::Latin1,
+    ];
+
+    /// Decoder for this encoding, `None` when it needs the `multi_encoding` feature.
+    pub fn decoder(&self) -> Option<StringDecoder> {
+        match self {
+            TextEncoding::Utf8 => Some(UTF8_STRING_DECODER),
+            TextEncoding::Latin1 => Some(LATIN1_STRING_DECODER),
+            #[cfg(feature = "multi_encoding")]
+            TextEncoding::Gbk => Some(GBK_STRING_DECODER),
+            #[cfg(feature = "multi_encoding")]
+            TextEncoding::ShiftJis => Some(SHIFT_JIS_STRING_DECODER),
+            #[cfg(not(feature = "multi_encoding"))]
+            TextEncoding::Gbk | TextEncoding::ShiftJis => None,
+        }
+    }
+
+    /// Decoder replacing invalid bytes with U+FFFD, for
+    /// [`RawReader::set_lossy_decoder`](crate::reader::RawReader::set_lossy_decoder).
+    pub fn lossy_decoder(&self) -> Option<StringDecoder> {
+        match self {
+            TextEncoding::Utf8 => Some(UTF8_LOSSY_STRING_DECODER),
+            TextEncoding::Latin1 => Some(LATIN1_STRING_DECODER),
+            #[cfg(feature = "multi_encoding")]
+            TextEncoding::Gbk => Some(GBK_LOSSY_STRING_DECODER),
+            #[cfg(feature = "multi_encoding")]
+            TextEncoding::ShiftJis => Some(SHIFT_JIS_LOSSY_STRING_DECODER),
+            #[cfg(not(feature = "multi_encoding"))]
+            TextEncoding::Gbk | TextEncoding::ShiftJis => None,
+        }
+    }
+
+    /// Encoder for this encoding, `None` when it needs the `multi_encoding` feature.
+    pub fn encoder(&self) -> Option<StringEncoder> {
+        match self {
+            TextEncoding::Utf8 => Some(UTF8_STRING_ENCODER),
+            TextEncoding::Latin1 => Some(LATIN1_STRING_ENCODER),
+            #[cfg(feature = "multi_encoding")]
+            TextEncoding::Gbk => Some(GBK_STRING_ENCODER),
+            #[cfg(feature = "multi_encoding")]
+            TextEncoding::ShiftJis => Some(SHIFT_JIS_STRING_ENCODER),
+            #[cfg(not(feature = "multi_encoding"))]
+            TextEncoding::Gbk | TextEncoding::ShiftJis => None,
+        }
+    }
+
+    // ties between encodings are broken in favour of the more common one
+    fn prior(&self) -> f64 {
+        match self {
+            TextEncoding::Utf8 => 1.0,
+            TextEncoding::Gbk => 0.9,
+            TextEncoding::ShiftJis => 0.85,
+            TextEncoding::Latin1 => 0.6,
+        }
+    }
+}
+
+impl Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Gbk => "GBK",
+            TextEncoding::ShiftJis => "Shift_JIS",
+            TextEncoding::Latin1 => "ISO-8859-1",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Result of an encoding detection pass.
+#[derive(Debug, Clone)]
+pub struct EncodingGuess {
+    pub encoding: TextEncoding,
+    /// Share of the sampled text that voted for `encoding`, between 0 and 1.
+    pub confidence: f64,
+    /// Score of every candidate, best first.
+    pub scores: Vec<(TextEncoding, f64)>,
+    /// Number of samples that contained non-ASCII bytes.
+    pub samples: usize,
+}
+
+impl EncodingGuess {
+    /// True when every sample was plain ASCII, so any decoder works.
+    pub fn is_ascii(&self) -> bool {
+        self.samples == 0
+    }
+}
+
+/// Accumulates text samples and scores the candidate encodings.
+#[derive(Debug, Default)]
+pub struct EncodingDetector {
+    votes: [f64; 4],
+    samples: usize,
+}
+
+impl EncodingDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let bytes = trim_padding(bytes);
+        if bytes.is_ascii() {
+            return;
+        }
+        let scores = TextEncoding::ALL.map(|e| {
+            let plausibility = match e {
+                TextEncoding::Utf8 => utf8_plausibility(bytes),
+                TextEncoding::Gbk => gbk_plausibility(bytes),
+                TextEncoding::ShiftJis => shift_jis_plausibility(bytes),
+                TextEncoding::Latin1 => latin1_plausibility(bytes),
+            };
+            plausibility.map_or(0.0, |p| p * e.prior())
+        });
+        let total: f64 = scores.iter().sum();
+        if total > 0.0 {
+            for (vote, score) in self.votes.iter_mut().zip(scores) {
+                *vote += score / total;
+            }
+        }
+        self.samples += 1;
+    }
+
+    pub fn guess(&self) -> EncodingGuess {
+        if self.samples == 0 {
+            return EncodingGuess {
+                encoding: TextEncoding::Utf8,
+                confidence: 1.0,
+                scores: TextEncoding::ALL.iter().map(|e| (*e, 1.0)).collect(),
+                samples: 0,
+            };
+        }
+        let mut scores: Vec<(TextEncoding, f64)> = TextEncoding::ALL
+            .iter()
+            .zip(self.votes)
+            .map(|(e, vote)| (*e, vote / self.samples as f64))
+            .collect();
+        // stable sort keeps the prior order for equal scores
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        EncodingGuess {
+            encoding: scores[0].0,
+            confidence: scores[0].1,
+            scores,
+            samples: self.samples,
+        }
+    }
+}
+
+/// Scores `samples` and returns the most likely encoding.
+pub fn detect_encoding<'b, I: IntoIterator<Item = &'b [u8]>>(samples: I) -> EncodingGuess {
+    let mut detector = EncodingDetector::new();
+    for sample in samples {
+        detector.feed(sample);
+    }
+    detector.guess()
+}
+
+fn trim_padding(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .iter()
+        .rposition(|b| *b != b' ' && *b != 0)
+        .map_or(0, |i| i + 1);
+    &bytes[..end]
+}
+
+// `None` means the bytes are not valid in the encoding at all, otherwise
+// the share of multi-byte characters that look like real text.
+fn utf8_plausibility(bytes: &[u8]) -> Option<f64> {
+    std::str::from_utf8(bytes).ok().map(|_| 1.0)
+}
+
+fn gbk_plausibility(bytes: &[u8]) -> Option<f64> {
+    let mut chars = 0;
+    let mut plausible = 0.0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        if lead < 0x80 {
+            i += 1;
+            continue;
+        }
+        let trail = *bytes.get(i + 1)?;
+        if !(0x81..=0xfe).contains(&lead) || !(0x40..=0xfe).contains(&trail) || trail == 0x7f {
+            return None;
+        }
+        chars += 1;
+        // GB2312 hanzi rows, then the symbol rows
+        let weight = if (0xb0..=0xf7).contains(&lead) && trail >= 0xa1 {
+            1.0
+        } else if (0xa1..=0xa9).contains(&lead) && trail >= 0xa1 {
+            0.5
+        } else {
+            0.2
+        };
+        // a lone ideograph between ASCII letters is rarer than a run
+        let isolated = i > 0
+            && bytes[i - 1].is_ascii_alphanumeric()
+            && bytes.get(i + 2).is_none_or(|b| *b < 0x80);
+        plausible += if isolated { weight * 0.5 } else { weight };
+        i += 2;
+    }
+    Some(plausible / chars.max(1) as f64)
+}
+
+fn shift_jis_plausibility(bytes: &[u8]) -> Option<f64> {
+    let mut chars = 0;
+    let mut plausible = 0.0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        if lead < 0x80 {
+            i += 1;
+            continue;
+        }
+        chars += 1;
+        if (0xa1..=0xdf).contains(&lead) {
+            // half-width katakana, legal but uncommon in labels
+            plausible += 0.25;
+            i += 1;
+            continue;
+        }
+        let trail = *bytes.get(i + 1)?;
+        if !((0x81..=0x9f).contains(&lead) || (0xe0..=0xfc).contains(&lead))
+            || !((0x40..=0x7e).contains(&trail) || (0x80..=0xfc).contains(&trail))
+        {
+            return None;
+        }
+        // 0xeb and above are vendor and user-defined rows
+        plausible += if lead <= 0xea { 1.0 } else { 0.2 };
+        i += 2;
+    }
+    Some(plausible / chars.max(1) as f64)
+}
+
+fn latin1_plausibility(bytes: &[u8]) -> Option<f64> {
+    let mut high = 0;
+    let mut plausible = 0;
+    for (i, b) in bytes.iter().enumerate() {
+        if *b < 0x80 {
+            continue;
+        }
+        high += 1;
+        // accented letters sit alone between ASCII, C1 controls never appear
+        let isolated =
+            (i == 0 || bytes[i - 1] < 0x80) && bytes.get(i + 1).is_none_or(|n| *n < 0x80);
+        if *b >= 0xc0 && *b != 0xd7 && *b != 0xf7 && isolated {
+            plausible += 1;
+        }
+    }
+    Some(plausible as f64 / high.max(1) as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::detect::{detect_encoding, TextEncoding};
+
+    #[test]
+    fn test_detect_encoding() {
+        let ascii = detect_encoding([b"Sequence Number".as_slice()]);
+        assert!(ascii.is_ascii());
+        assert_eq!(ascii.encoding, TextEncoding::Utf8);
+
+        let utf8 = detect_encoding(["实验室检查", "丙氨酸氨基转移酶  "].map(str::as_bytes));
+        assert_eq!(utf8.encoding, TextEncoding::Utf8);
+
+        // "实验室检查", "中文"
+        let gbk = detect_encoding([
+            [0xca, 0xb5, 0xd1, 0xe9, 0xca, 0xd2, 0xbc, 0xec, 0xb2, 0xe9].as_slice(),
+            &[0xd6, 0xd0, 0xce, 0xc4, b' ', b' '],
+        ]);
+        assert_eq!(gbk.encoding, TextEncoding::Gbk);
+        assert!(gbk.confidence > 0.5);
+
+        // "日本語", "テスト"
+        let sjis = detect_encoding([
+            [0x93, 0xfa, 0x96, 0x7b, 0x8c, 0xea].as_slice(),
+            &[0x83, 0x65, 0x83, 0x58, 0x83, 0x67],
+        ]);
+        assert_eq!(sjis.encoding, TextEncoding::ShiftJis);
+
+        // "Café", "Müller"
+        let latin1 = detect_encoding([
+            [b'C', b'a', b'f', 0xe9].as_slice(),
+            &[b'M', 0xfc, b'l', b'l', b'e', b'r'],
+        ]);
+        assert_eq!(latin1.encoding, TextEncoding::Latin1);
+    }
+}
@@ -5,6 +5,11 @@ use std::string::FromUtf8Error;
 pub enum XPTError {
     DecodeError(String),
     ParseError(String),
+    EncodeError(String),
+    /// Magnitude too large for an IBM double.
+    Overflow(f64),
+    /// Non-zero magnitude too small for an IBM double.
+    Underflow(f64),
 }
 
 impl Display for XPTError {
@@ -12,6 +17,9 @@ impl Display for XPTError {
         let str = match self {
             XPTError::DecodeError(a) => a.to_string(),
             XPTError::ParseError(a) => a.to_string(),
+            XPTError::EncodeError(a) => a.to_string(),
+            XPTError::Overflow(a) => format!("{} is too large for an IBM double", a),
+            XPTError::Underflow(a) => format!("{} is too small for an IBM double", a),
         };
         write!(f, "{}", str)
     }
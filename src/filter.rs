@@ -0,0 +1,731 @@
+use crate::error::XPTError;
+use crate::part::{ColumnType, DocumentMeta, StringDecoder, StringEncoder};
+use crate::reader::{Reader, Val};
+use crate::view::RowView;
+use crate::writer::{layout, relayout, MemberHeader, Writer};
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// Compares CHAR values as SAS does, the shorter one padded with blanks.
+pub fn compare_padded(a: &[u8], b: &[u8]) -> Ordering {
+    let length = a.len().max(b.len());
+    let byte = |bytes: &[u8], i: usize| bytes.get(i).copied().unwrap_or(b' ');
+    (0..length)
+        .map(|i| byte(a, i).cmp(&byte(b, i)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Compares NUMERIC values with missing values lowest.
+pub fn compare_numbers(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Missing,
+    Str(String),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 17] = [
+    "^=", "~=", "!=", "<=", ">=", "=", "<", ">", "+", "-", "*", "/", "(", ")", ",", "&", "|",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, XPTError> {
+    let error = |message: String| Err(XPTError::ParseError(message));
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            // a doubled quote stands for the quote itself
+            let mut str = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return error(format!("unterminated string in {}", expression)),
+                    Some(q) if *q == c && chars.get(i + 1) == Some(&c) => {
+                        str.push(c);
+                        i += 2;
+                    }
+                    Some(q) if *q == c => break,
+                    Some(other) => {
+                        str.push(*other);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push(Token::Str(str));
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let sign = usize::from(matches!(chars.get(i + 1), Some('+') | Some('-')));
+                if chars.get(i + 1 + sign).is_some_and(|d| d.is_ascii_digit()) {
+                    i += 1 + sign;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let number: String = chars[start..i].iter().collect();
+            match number.parse() {
+                Ok(number) => tokens.push(Token::Number(number)),
+                Err(_) => return error(format!("bad number {}", number)),
+            }
+        } else if c == '.' {
+            // `.`, `._` and `.A` to `.Z` are missing values
+            i += 1;
+            if chars
+                .get(i)
+                .is_some_and(|d| *d == '_' || d.is_ascii_alphabetic())
+                && !chars
+                    .get(i + 1)
+                    .is_some_and(|d| d.is_ascii_alphanumeric() || *d == '_')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Missing);
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            i += symbol.len();
+            tokens.push(Token::Symbol(symbol));
+        } else if c == '^' || c == '~' {
+            i += 1;
+            tokens.push(Token::Symbol("not"));
+        } else {
+            return error(format!("unexpected {:?} in {}", c, expression));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(Option<f64>),
+    Str(String),
+    Column(usize, ColumnType),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Arithmetic(char, Box<Expr>, Box<Expr>),
+    Compare(Comparison, Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Vec<Expr>),
+    IsMissing(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn column_type(&self) -> ColumnType {
+        match self {
+            Expr::Str(_) => ColumnType::CHAR,
+            Expr::Column(_, column_type) => *column_type,
+            _ => ColumnType::NUMERIC,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(Option<f64>),
+    Char(String),
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Number(number) => number.is_some_and(|n| n != 0.0),
+            Value::Char(str) => !str.trim().is_empty(),
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => *number,
+            Value::Char(_) => None,
+        }
+    }
+
+    fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Char(a), Value::Char(b)) => compare_padded(a.as_bytes(), b.as_bytes()),
+            (a, b) => compare_numbers(a.number(), b.number()),
+        }
+    }
+}
+
+fn boolean(value: bool) -> Value {
+    Value::Number(Some(if value { 1.0 } else { 0.0 }))
+}
+
+struct Parser<'a, F: Fn(&str) -> Option<(usize, ColumnType)>> {
+    tokens: Vec<Token>,
+    position: usize,
+    lookup: &'a F,
+}
+
+impl<F: Fn(&str) -> Option<(usize, ColumnType)>> Parser<'_, F> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn keyword(&self, keywords: &[&str]) -> Option<String> {
+        match self.peek() {
+            Some(Token::Name(name)) => keywords
+                .iter()
+                .find(|k| name.eq_ignore_ascii_case(k))
+                .map(|k| k.to_string()),
+            Some(Token::Symbol(symbol)) => keywords
+                .iter()
+                .find(|k| *k == symbol)
+                .map(|k| k.to_string()),
+            _ => None,
+        }
+    }
+
+    fn accept(&mut self, keywords: &[&str]) -> Option<String> {
+        let keyword = self.keyword(keywords);
+        if keyword.is_some() {
+            self.position += 1;
+        }
+        keyword
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), XPTError> {
+        self.accept(&[symbol]).map(|_| ()).ok_or_else(|| {
+            XPTError::ParseError(format!("expected {} at {:?}", symbol, self.peek()))
+        })
+    }
+
+    fn check_type(expr: &Expr, column_type: ColumnType, what: &str) -> Result<(), XPTError> {
+        if expr.column_type() != column_type {
+            return Err(XPTError::ParseError(format!(
+                "{} needs a {:?} operand",
+                what, column_type
+            )));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, XPTError> {
+        let mut expr = self.and()?;
+        while self.accept(&["or", "|"]).is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, XPTError> {
+        let mut expr = self.not()?;
+        while self.accept(&["and", "&"]).is_some() {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, XPTError> {
+        if self.accept(&["not"]).is_some() {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, XPTError> {
+        let left = self.sum()?;
+        if self.accept(&["is"]).is_some() {
+            let negated = self.accept(&["not"]).is_some();
+            if self.accept(&["missing", "null"]).is_none() {
+                return Err(XPTError::ParseError(
+                    "expected MISSING after IS".to_string(),
+                ));
+            }
+            let expr = Expr::IsMissing(Box::new(left));
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+        let negated = self.keyword(&["not"]).is_some()
+            && matches!(self.tokens.get(self.position + 1), Some(Token::Name(n)) if n.eq_ignore_ascii_case("in"));
+        if negated {
+            self.position += 1;
+        }
+        if self.accept(&["in"]).is_some() {
+            self.expect("(")?;
+            let mut list = vec![self.sum()?];
+            while self.accept(&[","]).is_some() {
+                list.push(self.sum()?);
+            }
+            self.expect(")")?;
+            for item in &list {
+                Self::check_type(item, left.column_type(), "IN")?;
+            }
+            let expr = Expr::In(Box::new(left), list);
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+        let comparison = match self
+            .accept(&[
+                "=", "eq", "^=", "~=", "!=", "ne", "<", "lt", "<=", "le", ">", "gt", ">=", "ge",
+            ])
+            .map(|k| k.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("=") | Some("eq") => Comparison::Eq,
+            Some("<") | Some("lt") => Comparison::Lt,
+            Some("<=") | Some("le") => Comparison::Le,
+            Some(">") | Some("gt") => Comparison::Gt,
+            Some(">=") | Some("ge") => Comparison::Ge,
+            Some(_) => Comparison::Ne,
+            None => return Ok(left),
+        };
+        let right = self.sum()?;
+        Self::check_type(&right, left.column_type(), "comparison")?;
+        Ok(Expr::Compare(comparison, Box::new(left), Box::new(right)))
+    }
+
+    fn sum(&mut self) -> Result<Expr, XPTError> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.accept(&["+", "-"]) {
+            let right = self.product()?;
+            Self::check_type(&expr, ColumnType::NUMERIC, &op)?;
+            Self::check_type(&right, ColumnType::NUMERIC, &op)?;
+            expr = Expr::Arithmetic(op.chars().next().unwrap(), Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, XPTError> {
+        let mut expr = self.unary()?;
+        while let Some(op) = self.accept(&["*", "/"]) {
+            let right = self.unary()?;
+            Self::check_type(&expr, ColumnType::NUMERIC, &op)?;
+            Self::check_type(&right, ColumnType::NUMERIC, &op)?;
+            expr = Expr::Arithmetic(op.chars().next().unwrap(), Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, XPTError> {
+        if self.accept(&["-"]).is_some() {
+            let expr = self.unary()?;
+            Self::check_type(&expr, ColumnType::NUMERIC, "-")?;
+            return Ok(Expr::Negate(Box::new(expr)));
+        }
+        if self.accept(&["+"]).is_some() {
+            return self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, XPTError> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(number)) => Ok(Expr::Number(Some(number))),
+            Some(Token::Missing) => Ok(Expr::Number(None)),
+            Some(Token::Str(str)) => Ok(Expr::Str(str)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => match (self.lookup)(&name) {
+                Some((i, column_type)) => Ok(Expr::Column(i, column_type)),
+                None => Err(XPTError::ParseError(format!("unknown column {}", name))),
+            },
+            other => Err(XPTError::ParseError(format!(
+                "unexpected {:?}",
+                other.map_or("end of expression".to_string(), |t| format!("{:?}", t))
+            ))),
+        }
+    }
+}
+
+/// A compiled row filter, in the manner of a SAS `WHERE` clause.
+///
+/// Expressions combine column names (matched ignoring case), numbers,
+/// quoted strings and the missing value `.` with `+ - * /`, the comparisons
+/// `= ^= < <= > >=` or their mnemonics `EQ NE LT LE GT GE`, `IN (…)`,
+/// `IS [NOT] MISSING`, and `AND`, `OR`, `NOT`. As in SAS, a missing value
+/// is lower than every number, arithmetic on it stays missing, and strings
+/// compare as if padded with blanks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn new(expression: &str, meta: &DocumentMeta) -> Result<Self, XPTError> {
+        Self::compile(expression, |name| {
+            meta.columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(name))
+                .map(|i| (i, meta.columns[i].column_type))
+        })
+    }
+
+    pub(crate) fn compile<F: Fn(&str) -> Option<(usize, ColumnType)>>(
+        expression: &str,
+        lookup: F,
+    ) -> Result<Self, XPTError> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+            lookup: &lookup,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(XPTError::ParseError(format!(
+                "unexpected {:?} in {}",
+                token, expression
+            )));
+        }
+        Ok(Filter { expr })
+    }
+
+    pub fn matches(&self, row: &[Val]) -> bool {
+        let value = |i: usize| {
+            Ok(match &row[i] {
+                Val::Number(number) => Value::Number(Some(*number)),
+                Val::Char(str) => Value::Char(str.clone()),
+                Val::Nil => Value::Number(None),
+            })
+        };
+        Self::eval(&self.expr, &value).is_ok_and(|v| v.is_true())
+    }
+
+    /// Evaluates the filter on a row view, decoding only the CHAR cells it
+    /// refers to.
+    pub fn matches_row(&self, row: &RowView) -> Result<bool, XPTError> {
+        let value = |i: usize| {
+            Ok(if row.is_numeric(i) {
+                Value::Number(row.get_f64(i))
+            } else {
+                Value::Char(row.get_str(i)?)
+            })
+        };
+        Self::eval(&self.expr, &value).map(|v| v.is_true())
+    }
+
+    fn eval<F: Fn(usize) -> Result<Value, XPTError>>(
+        expr: &Expr,
+        value: &F,
+    ) -> Result<Value, XPTError> {
+        Ok(match expr {
+            Expr::Number(number) => Value::Number(*number),
+            Expr::Str(str) => Value::Char(str.clone()),
+            Expr::Column(i, _) => value(*i)?,
+            Expr::Negate(expr) => Value::Number(Self::eval(expr, value)?.number().map(|n| -n)),
+            Expr::Not(expr) => boolean(!Self::eval(expr, value)?.is_true()),
+            Expr::Arithmetic(op, left, right) => {
+                let left = Self::eval(left, value)?.number();
+                let right = Self::eval(right, value)?.number();
+                Value::Number(match (left, right) {
+                    (Some(a), Some(b)) => match op {
+                        '+' => Some(a + b),
+                        '-' => Some(a - b),
+                        '*' => Some(a * b),
+                        // division by zero gives a missing value
+                        _ => Some(a / b).filter(|_| b != 0.0),
+                    },
+                    _ => None,
+                })
+            }
+            Expr::Compare(comparison, left, right) => {
+                let left = Self::eval(left, value)?;
+                let right = Self::eval(right, value)?;
+                boolean(comparison.holds(left.compare(&right)))
+            }
+            Expr::In(left, list) => {
+                let left = Self::eval(left, value)?;
+                let mut found = false;
+                for item in list {
+                    if left.compare(&Self::eval(item, value)?).is_eq() {
+                        found = true;
+                        break;
+                    }
+                }
+                boolean(found)
+            }
+            Expr::IsMissing(expr) => boolean(match Self::eval(expr, value)? {
+                Value::Number(number) => number.is_none(),
+                Value::Char(str) => str.trim().is_empty(),
+            }),
+            Expr::And(left, right) => {
+                boolean(Self::eval(left, value)?.is_true() && Self::eval(right, value)?.is_true())
+            }
+            Expr::Or(left, right) => {
+                boolean(Self::eval(left, value)?.is_true() || Self::eval(right, value)?.is_true())
+            }
+        })
+    }
+}
+
+/// Writes the rows of `input` matching `expression` to `output`, returning
+/// how many were kept. V8 inputs are read twice to count the rows first.
+#[cfg(not(feature = "async"))]
+pub fn filter_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    expression: &str,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    data.filter(expression)?;
+    let mut header = MemberHeader::from_meta(&meta);
+    if header.version == crate::part::DocumentHeader::V8 {
+        let mut rows = 0;
+        while data.read_row()?.is_some() {
+            rows += 1;
+        }
+        header.observations = Some(rows);
+        file = std::fs::File::open(&input)?;
+        reader = Reader::new(&mut file, string_decoder);
+        data = reader.start()?.0;
+        data.filter(expression)?;
+    }
+    let layout = layout(&meta.columns);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(&mut out, header, meta.columns, string_encoder)?;
+    writer.start()?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row()? {
+        relayout(&row, &layout, &mut line);
+        writer.write_line(&line)?;
+    }
+    let rows = writer.rows();
+    writer.finish()?;
+    Ok(rows)
+}
+
+/// Writes the rows of `input` matching `expression` to `output`, returning
+/// how many were kept. V8 inputs are read twice to count the rows first.
+#[cfg(feature = "async")]
+pub async fn filter_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    expression: &str,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    data.filter(expression)?;
+    let mut header = MemberHeader::from_meta(&meta);
+    if header.version == crate::part::DocumentHeader::V8 {
+        let mut rows = 0;
+        while data.read_row().await?.is_some() {
+            rows += 1;
+        }
+        header.observations = Some(rows);
+        file = tokio::fs::File::open(&input).await?;
+        reader = Reader::new(&mut file, string_decoder);
+        data = reader.start().await?.0;
+        data.filter(expression)?;
+    }
+    let layout = layout(&meta.columns);
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(&mut out, header, meta.columns, string_encoder)?;
+    writer.start().await?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row().await? {
+        relayout(&row, &layout, &mut line);
+        writer.write_line(&line).await?;
+    }
+    let rows = writer.rows();
+    writer.finish().await?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filter::{compare_numbers, compare_padded, Filter};
+    use crate::part::ColumnType;
+    use crate::reader::Val;
+    use std::cmp::Ordering;
+
+    fn filter(expression: &str) -> Result<Filter, crate::error::XPTError> {
+        Filter::compile(expression, |name| {
+            match name.to_ascii_uppercase().as_str() {
+                "LBTESTCD" => Some((0, ColumnType::CHAR)),
+                "LBSTRESN" => Some((1, ColumnType::NUMERIC)),
+                "ULN" => Some((2, ColumnType::NUMERIC)),
+                _ => None,
+            }
+        })
+    }
+
+    fn row(test: &str, result: Option<f64>, uln: f64) -> Vec<Val> {
+        vec![
+            Val::Char(test.to_string()),
+            result.map_or(Val::Nil, Val::Number),
+            Val::Number(uln),
+        ]
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare_padded(b"AB", b"AB  "), Ordering::Equal);
+        assert_eq!(compare_padded(b"A", b"A\t"), Ordering::Greater);
+        assert_eq!(compare_padded(b"", b"A"), Ordering::Less);
+        assert_eq!(compare_numbers(None, Some(-1e300)), Ordering::Less);
+        assert_eq!(compare_numbers(None, None), Ordering::Equal);
+        assert_eq!(compare_numbers(Some(0.0), Some(-0.0)), Ordering::Equal);
+        assert_eq!(compare_numbers(Some(1.0), Some(2.0)), Ordering::Less);
+    }
+
+    #[test]
+    fn test_filter() {
+        let high = filter(r#"LBTESTCD = "ALT" and LBSTRESN > 3 * ULN"#).unwrap();
+        assert!(high.matches(&row("ALT", Some(130.0), 40.0)));
+        assert!(!high.matches(&row("ALT", Some(100.0), 40.0)));
+        assert!(!high.matches(&row("AST", Some(130.0), 40.0)));
+        // missing is lower than any number
+        assert!(!high.matches(&row("ALT", None, 40.0)));
+        assert!(filter("lbstresn < 0")
+            .unwrap()
+            .matches(&row("ALT", None, 40.0)));
+        assert!(filter("lbstresn = .")
+            .unwrap()
+            .matches(&row("ALT", None, 40.0)));
+        assert!(filter("LBSTRESN is missing")
+            .unwrap()
+            .matches(&row("ALT", None, 1.0)));
+        assert!(filter("LBSTRESN + 1 is missing")
+            .unwrap()
+            .matches(&row("ALT", None, 1.0)));
+        assert!(filter("not LBSTRESN is not missing")
+            .unwrap()
+            .matches(&row("ALT", None, 1.0)));
+        let tests = filter("LBTESTCD in ('ALT', 'AST') & ULN ge 40").unwrap();
+        assert!(tests.matches(&row("AST", None, 40.0)));
+        assert!(!tests.matches(&row("GLUC", None, 40.0)));
+        assert!(filter("LBTESTCD not in ('ALT')")
+            .unwrap()
+            .matches(&row("AST", None, 1.0)));
+        assert!(filter("LBTESTCD ^= 'ALT' | ULN / 0 = .")
+            .unwrap()
+            .matches(&row("ALT", None, 1.0)));
+        assert!(filter(r#"LBTESTCD = "it""s""#)
+            .unwrap()
+            .matches(&row("it\"s", None, 1.0)));
+        assert!(filter("-(ULN - 3) = -2e0")
+            .unwrap()
+            .matches(&row("ALT", None, 5.0)));
+
+        assert!(filter("LBTESTCD = 3").is_err());
+        assert!(filter("LBTESTCD * 2 > 1").is_err());
+        assert!(filter("UNKNOWN = 1").is_err());
+        assert!(filter("ULN = (1").is_err());
+        assert!(filter("ULN = 1 2").is_err());
+        assert!(filter("ULN = 'open").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_reader_filter() {
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, _) = reader.start().unwrap();
+        data.filter("lbtestcd = 'GLUC' or lbstresn < 1").unwrap();
+        let mut seqs = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            seqs.push(row[3].to_string());
+        }
+        // LBSEQ 307 has a missing result, lower than 1
+        assert_eq!(seqs, vec!["103", "205", "206", "307", "309"]);
+        assert!(data.filter("NOPE = 1").is_err());
+
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, _) = reader.start().unwrap();
+        data.filter("LBTESTCD = 'ALT'").unwrap();
+        let mut rows = 0;
+        while let Some(batch) = data.read_batch(4).unwrap() {
+            rows += batch.rows;
+        }
+        assert_eq!(rows, 3);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_filter_file() {
+        use crate::filter::filter_file;
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let output = std::env::temp_dir().join("xpt_r_filter_lb2.xpt");
+        let rows = filter_file(
+            "sample/LB2.xpt",
+            &output,
+            "USUBJID = '01-701-1016'",
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(rows, 3);
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (mut data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(meta.columns.len(), 9);
+        let mut seqs = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            seqs.push(row[3].to_string());
+        }
+        assert_eq!(seqs, vec!["204", "205", "206"]);
+        std::fs::remove_file(&output).unwrap();
+    }
+}
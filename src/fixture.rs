@@ -0,0 +1,92 @@
+//! Columns, metadata and files shared by the tests.
+use crate::part::{ColumnMeta, ColumnType, DocumentHeader, DocumentMeta};
+#[cfg(not(feature = "async"))]
+use {
+    crate::reader::Val,
+    crate::writer::{MemberHeader, Writer, UTF8_STRING_ENCODER},
+    std::path::Path,
+};
+
+pub(crate) fn column(name: &str, column_type: ColumnType, length: u16) -> ColumnMeta {
+    ColumnMeta {
+        column_type,
+        length,
+        var_count: 0,
+        name: name.to_string(),
+        label: String::new(),
+        format: (String::new(), 0, 0),
+        in_format: (String::new(), 0, 0),
+        position: 0,
+        justification: 0,
+        name_hash: 0,
+        namestr: Vec::new(),
+    }
+}
+
+pub(crate) fn labelled_column(
+    name: &str,
+    label: &str,
+    column_type: ColumnType,
+    length: u16,
+) -> ColumnMeta {
+    ColumnMeta {
+        label: label.to_string(),
+        ..column(name, column_type, length)
+    }
+}
+
+pub(crate) fn meta(
+    version: DocumentHeader,
+    dataset_name: &str,
+    columns: Vec<ColumnMeta>,
+) -> DocumentMeta {
+    DocumentMeta {
+        version,
+        doc_version: "9.4".to_string(),
+        operation_system: "X64_10PR".to_string(),
+        doc_update_time: String::new(),
+        lib_update_time: String::new(),
+        dataset_name: dataset_name.to_string(),
+        dataset_label: String::new(),
+        dataset_type: String::new(),
+        dataset_version: "9.4".to_string(),
+        dataset_operation_system: "X64_10PR".to_string(),
+        dataset_created: String::new(),
+        dataset_modified: String::new(),
+        member_meta_length: 140,
+        library: "SASLIB".to_string(),
+        columns,
+        encoding: None,
+    }
+}
+
+/// The `A`, `B`, `C` of a 4-byte CHAR column, rows much shorter than the
+/// 80-byte records.
+#[cfg(not(feature = "async"))]
+pub(crate) fn short_rows() -> (Vec<ColumnMeta>, Vec<Vec<Val>>) {
+    let rows = ["A", "B", "C"]
+        .iter()
+        .map(|arm| vec![Val::Char(arm.to_string())])
+        .collect();
+    (vec![column("ARM", ColumnType::CHAR, 4)], rows)
+}
+
+/// Writes `rows` to a new file at `path` as dataset `dataset_name`.
+#[cfg(not(feature = "async"))]
+pub(crate) fn write_file(
+    path: &Path,
+    version: DocumentHeader,
+    dataset_name: &str,
+    columns: Vec<ColumnMeta>,
+    rows: &[Vec<Val>],
+) {
+    let mut header = MemberHeader::new(version, dataset_name);
+    header.observations = Some(rows.len());
+    let mut out = std::fs::File::create(path).unwrap();
+    let mut writer = Writer::new(&mut out, header, columns, UTF8_STRING_ENCODER).unwrap();
+    writer.start().unwrap();
+    for row in rows {
+        writer.write_row(row).unwrap();
+    }
+    writer.finish().unwrap();
+}
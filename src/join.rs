@@ -0,0 +1,855 @@
+use crate::error::XPTError;
+use crate::number::byte2number;
+use crate::part::{
+    ColumnMeta, ColumnType, DocumentHeader, DocumentMeta, StringDecoder, StringEncoder,
+};
+use crate::reader::{RawReader, Reader};
+use crate::sort::compare_cells;
+use crate::view::RowView;
+use crate::writer::{layout, MemberHeader, Writer};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+/// Which key groups a join keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinKind {
+    /// Groups found in both inputs.
+    Inner,
+    /// Groups of the left input, with or without a match.
+    Left,
+    /// Every group, as a plain DATA step `MERGE` does.
+    #[default]
+    Full,
+}
+
+/// How the inputs are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinInput {
+    /// Both inputs are sorted by the keys and streamed, one group at a time.
+    #[default]
+    Sorted,
+    /// Both inputs are held in memory and matched by hash, in any order. The
+    /// output follows the left input, then the unmatched right groups.
+    Hashed,
+}
+
+/// Which input supplies a non-key column both inputs hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinConflict {
+    /// The right input, as in `MERGE left right`: its value stands while it
+    /// has observations in the group, then the left value.
+    #[default]
+    Right,
+    Left,
+    /// Fail before reading any observation.
+    Error,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JoinOptions {
+    pub kind: JoinKind,
+    pub input: JoinInput,
+    pub conflict: JoinConflict,
+}
+
+/// Outcome of [`join_files`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinReport {
+    pub columns: Vec<ColumnMeta>,
+    pub rows: usize,
+    /// Observations in key groups both inputs hold, per input.
+    pub matched: (usize, usize),
+    /// Observations in key groups only one input holds, per input.
+    pub unmatched: (usize, usize),
+}
+
+impl Display for JoinReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Left:   {} matched, {} unmatched observations",
+            self.matched.0, self.unmatched.0
+        )?;
+        writeln!(
+            f,
+            "Right:  {} matched, {} unmatched observations",
+            self.matched.1, self.unmatched.1
+        )?;
+        writeln!(
+            f,
+            "Joined: {} observations, {} variables",
+            self.rows,
+            self.columns.len()
+        )
+    }
+}
+
+/// Combines the columns of two datasets matched on key variables: the left
+/// columns in order, then those only the right input holds. A column both
+/// inputs hold appears once, with the attributes of the left input and the
+/// larger length.
+pub struct Joiner {
+    columns: Vec<ColumnMeta>,
+    layout: Vec<(u32, u16, u16)>,
+    // the left and right column of every key
+    keys: Vec<(usize, usize, ColumnType)>,
+    // the left and right column of every joined column, and whether it is a key
+    sources: Vec<(Option<usize>, Option<usize>, bool)>,
+    kind: JoinKind,
+    conflict: JoinConflict,
+}
+
+impl Joiner {
+    pub fn new(
+        left: &DocumentMeta,
+        right: &DocumentMeta,
+        keys: &[String],
+        options: &JoinOptions,
+    ) -> Result<Self, XPTError> {
+        let find = |meta: &DocumentMeta, name: &str| {
+            meta.columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(name))
+        };
+        if keys.is_empty() {
+            return Err(XPTError::ParseError("no key variables".to_string()));
+        }
+        let mut key_columns = Vec::with_capacity(keys.len());
+        for key in keys {
+            let (Some(l), Some(r)) = (find(left, key), find(right, key)) else {
+                return Err(XPTError::ParseError(format!(
+                    "key variable {} is not in both inputs",
+                    key
+                )));
+            };
+            key_columns.push((l, r, left.columns[l].column_type));
+        }
+        let mut columns = Vec::new();
+        let mut sources = Vec::new();
+        for (l, column) in left.columns.iter().enumerate() {
+            let mut column = column.clone();
+            let r = find(right, &column.name);
+            let key = key_columns.iter().any(|k| k.0 == l);
+            if let Some(r) = r {
+                let other = &right.columns[r];
+                if other.column_type != column.column_type {
+                    return Err(XPTError::EncodeError(format!(
+                        "column {} is {:?} on the left and {:?} on the right",
+                        column.name, column.column_type, other.column_type
+                    )));
+                }
+                if !key && options.conflict == JoinConflict::Error {
+                    return Err(XPTError::EncodeError(format!(
+                        "column {} is in both inputs",
+                        column.name
+                    )));
+                }
+                column.length = column.length.max(other.length);
+            }
+            columns.push(column);
+            sources.push((Some(l), r, key));
+        }
+        for (r, column) in right.columns.iter().enumerate() {
+            if find(left, &column.name).is_none() {
+                columns.push(column.clone());
+                sources.push((None, Some(r), false));
+            }
+        }
+        Ok(Joiner {
+            layout: layout(&columns),
+            columns,
+            keys: key_columns,
+            sources,
+            kind: options.kind,
+            conflict: options.conflict,
+        })
+    }
+
+    pub fn columns(&self) -> &[ColumnMeta] {
+        &self.columns
+    }
+
+    /// Compares the keys of a left and a right row in SAS collation.
+    pub fn compare_keys(&self, left: &RowView, right: &RowView) -> Ordering {
+        self.compare(left, false, right, true)
+    }
+
+    fn compare(&self, a: &RowView, a_right: bool, b: &RowView, b_right: bool) -> Ordering {
+        let column = |key: &(usize, usize, ColumnType), right: bool| {
+            if right {
+                key.1
+            } else {
+                key.0
+            }
+        };
+        self.keys
+            .iter()
+            .map(|key| {
+                compare_cells(
+                    key.2,
+                    a.get_bytes(column(key, a_right)),
+                    b.get_bytes(column(key, b_right)),
+                )
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Key of a row for hashing, with trailing blanks of CHAR keys removed
+    /// and every missing value code kept apart.
+    fn hash_key(&self, row: &RowView, right: bool) -> Vec<u8> {
+        let mut hash = Vec::new();
+        for (l, r, column_type) in &self.keys {
+            let bytes = row.get_bytes(if right { *r } else { *l });
+            match column_type {
+                ColumnType::CHAR => {
+                    let length = bytes.iter().rposition(|b| *b != b' ').map_or(0, |p| p + 1);
+                    hash.extend_from_slice(&(length as u32).to_le_bytes());
+                    hash.extend_from_slice(&bytes[..length]);
+                }
+                ColumnType::NUMERIC => match byte2number(bytes) {
+                    Some(number) => {
+                        hash.push(1);
+                        hash.extend_from_slice(&(number + 0.0).to_bits().to_le_bytes());
+                    }
+                    None => hash.extend_from_slice(&[0, bytes.first().copied().unwrap_or(b'.')]),
+                },
+            }
+        }
+        hash
+    }
+
+    /// Whether a key group with left and right observations is written.
+    pub fn keeps(&self, left: bool, right: bool) -> bool {
+        match self.kind {
+            JoinKind::Inner => left && right,
+            JoinKind::Left => left,
+            JoinKind::Full => left || right,
+        }
+    }
+
+    /// Observations written for a key group, as many as the larger input has.
+    pub fn group_rows(left: usize, right: usize) -> usize {
+        left.max(right)
+    }
+
+    /// Lays out observation `i` of a key group in the joined columns, the
+    /// way the DATA step `MERGE` does: an input out of observations keeps
+    /// its last one, and an input without any leaves its columns missing.
+    pub fn fill_group(&self, left: &[RowView], right: &[RowView], i: usize, line: &mut [u8]) {
+        let l_row = left.get(i).or(left.last());
+        let r_row = right.get(i).or(right.last());
+        let (l_read, r_read) = (i < left.len(), i < right.len());
+        for ((npos, nlng, ntype), (l, r, key)) in self.layout.iter().zip(&self.sources) {
+            let cell = &mut line[*npos as usize..*npos as usize + *nlng as usize];
+            let numeric = *ntype == ColumnType::NUMERIC as u16;
+            let l = l.zip(l_row);
+            let r = r.zip(r_row);
+            let source = match (l, r) {
+                (Some(l), Some(r)) if !key => match self.conflict {
+                    JoinConflict::Left if !l_read && r_read => Some(r),
+                    JoinConflict::Left => Some(l),
+                    _ if !r_read && l_read => Some(l),
+                    _ => Some(r),
+                },
+                (l, r) => l.or(r),
+            };
+            match source {
+                Some((i, row)) => {
+                    let bytes = row.get_bytes(i);
+                    let length = bytes.len().min(cell.len());
+                    cell[..length].copy_from_slice(&bytes[..length]);
+                    cell[length..].fill(if numeric { 0 } else { b' ' });
+                }
+                None if numeric => {
+                    cell.fill(0);
+                    cell[0] = b'.';
+                }
+                None => cell.fill(b' '),
+            }
+        }
+    }
+
+    /// Header for the joined file, taken from the left input and written as
+    /// V8 when either input is.
+    pub fn header(&self, left: &DocumentMeta, right: &DocumentMeta) -> MemberHeader {
+        let mut header = MemberHeader::from_meta(left);
+        if right.version == DocumentHeader::V8 {
+            header.version = DocumentHeader::V8;
+        }
+        header
+    }
+
+    fn report(&self) -> JoinReport {
+        JoinReport {
+            columns: self.columns.clone(),
+            rows: 0,
+            matched: (0, 0),
+            unmatched: (0, 0),
+        }
+    }
+}
+
+impl JoinReport {
+    fn count(&mut self, left: usize, right: usize) {
+        if left > 0 && right > 0 {
+            self.matched.0 += left;
+            self.matched.1 += right;
+        } else {
+            self.unmatched.0 += left;
+            self.unmatched.1 += right;
+        }
+    }
+}
+
+/// Observations of one input sharing their keys.
+type Group = Vec<Vec<u8>>;
+
+/// One input of a join read a key group at a time.
+struct Groups<'a> {
+    data: RawReader<'a>,
+    columns: Vec<(u32, u16, u16)>,
+    decoder: StringDecoder,
+    right: bool,
+    pending: Option<Vec<u8>>,
+}
+
+impl<'a> Groups<'a> {
+    fn new(data: RawReader<'a>, right: bool) -> Self {
+        Groups {
+            columns: data.columns().to_vec(),
+            decoder: data.string_decoder(),
+            data,
+            right,
+            pending: None,
+        }
+    }
+
+    fn view<'l>(&'l self, line: &'l [u8]) -> RowView<'l> {
+        RowView::new(line, &self.columns, self.decoder)
+    }
+
+    /// Adds `line` to `group` when it has the same keys, otherwise keeps it
+    /// for the next group.
+    fn accept(
+        &mut self,
+        joiner: &Joiner,
+        group: &mut Group,
+        line: Vec<u8>,
+    ) -> Result<bool, XPTError> {
+        let Some(first) = group.first() else {
+            group.push(line);
+            return Ok(true);
+        };
+        match joiner.compare(&self.view(first), self.right, &self.view(&line), self.right) {
+            Ordering::Equal => {
+                group.push(line);
+                Ok(true)
+            }
+            Ordering::Less => {
+                self.pending = Some(line);
+                Ok(false)
+            }
+            Ordering::Greater => Err(XPTError::ParseError(format!(
+                "the {} input is not sorted by the keys",
+                if self.right { "right" } else { "left" }
+            ))),
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn next_group(&mut self, joiner: &Joiner) -> Result<Group, XPTError> {
+        let mut group = Vec::from_iter(self.pending.take());
+        while let Some(row) = self.data.read_row()? {
+            let line = row.line().to_vec();
+            if !self.accept(joiner, &mut group, line)? {
+                break;
+            }
+        }
+        Ok(group)
+    }
+
+    #[cfg(feature = "async")]
+    async fn next_group(&mut self, joiner: &Joiner) -> Result<Group, XPTError> {
+        let mut group = Vec::from_iter(self.pending.take());
+        while let Some(row) = self.data.read_row().await? {
+            let line = row.line().to_vec();
+            if !self.accept(joiner, &mut group, line)? {
+                break;
+            }
+        }
+        Ok(group)
+    }
+
+    /// Every key group, in order of first appearance.
+    #[cfg(not(feature = "async"))]
+    fn all_groups(&mut self, joiner: &Joiner) -> Result<HashedGroups, XPTError> {
+        let mut groups = HashedGroups::default();
+        while let Some(row) = self.data.read_row()? {
+            groups.push(joiner.hash_key(&row, self.right), row.line().to_vec());
+        }
+        Ok(groups)
+    }
+
+    /// Every key group, in order of first appearance.
+    #[cfg(feature = "async")]
+    async fn all_groups(&mut self, joiner: &Joiner) -> Result<HashedGroups, XPTError> {
+        let mut groups = HashedGroups::default();
+        while let Some(row) = self.data.read_row().await? {
+            groups.push(joiner.hash_key(&row, self.right), row.line().to_vec());
+        }
+        Ok(groups)
+    }
+
+    fn views<'l>(&'l self, group: &'l [Vec<u8>]) -> Vec<RowView<'l>> {
+        group.iter().map(|line| self.view(line)).collect()
+    }
+}
+
+#[derive(Default)]
+struct HashedGroups {
+    index: HashMap<Vec<u8>, usize>,
+    groups: Vec<Group>,
+}
+
+impl HashedGroups {
+    fn push(&mut self, key: Vec<u8>, line: Vec<u8>) {
+        let next = self.groups.len();
+        let i = *self.index.entry(key).or_insert(next);
+        if i == next {
+            self.groups.push(Vec::new());
+        }
+        self.groups[i].push(line);
+    }
+}
+
+/// Pairs the key groups of both inputs, in output order.
+fn pair_hashed(left: &mut HashedGroups, right: &mut HashedGroups) -> Vec<(Group, Group)> {
+    let mut matched = vec![false; right.groups.len()];
+    let mut right_groups = std::mem::take(&mut right.groups);
+    let mut pairs = Vec::new();
+    for (key, i) in {
+        let mut keys: Vec<_> = left.index.drain().collect();
+        keys.sort_by_key(|(_, i)| *i);
+        keys
+    } {
+        let group = std::mem::take(&mut left.groups[i]);
+        let other = match right.index.get(&key) {
+            Some(r) => {
+                matched[*r] = true;
+                std::mem::take(&mut right_groups[*r])
+            }
+            None => Vec::new(),
+        };
+        pairs.push((group, other));
+    }
+    for (group, matched) in right_groups.into_iter().zip(matched) {
+        if !matched {
+            pairs.push((Vec::new(), group));
+        }
+    }
+    pairs
+}
+
+/// Joins the files at `left` and `right` on the `keys` variables into
+/// `output`, with the one-to-one and one-to-many semantics of the DATA step
+/// `MERGE` statement.
+#[cfg(not(feature = "async"))]
+pub fn join_files<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+    left: P,
+    right: Q,
+    output: R,
+    keys: &[String],
+    options: &JoinOptions,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<JoinReport, XPTError> {
+    let (left, right) = (left.as_ref(), right.as_ref());
+    let (joiner, mut header) = {
+        let mut left_file = std::fs::File::open(left)?;
+        let mut right_file = std::fs::File::open(right)?;
+        let (_, left_meta) = Reader::new(&mut left_file, string_decoder).start()?;
+        let (_, right_meta) = Reader::new(&mut right_file, string_decoder).start()?;
+        let joiner = Joiner::new(&left_meta, &right_meta, keys, options)?;
+        let header = joiner.header(&left_meta, &right_meta);
+        (joiner, header)
+    };
+    if header.version == DocumentHeader::V8 {
+        // OBSV8 precedes the observations, so they are counted first
+        let report = join_pass(left, right, &joiner, options, string_decoder, None)?;
+        header.observations = Some(report.rows);
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(&mut out, header, joiner.columns.clone(), string_encoder)?;
+    writer.start()?;
+    let report = join_pass(
+        left,
+        right,
+        &joiner,
+        options,
+        string_decoder,
+        Some(&mut writer),
+    )?;
+    writer.finish()?;
+    Ok(report)
+}
+
+/// Reads both inputs once and matches their key groups, writing the joined
+/// observations to `writer` or only counting them when it is `None`.
+#[cfg(not(feature = "async"))]
+fn join_pass(
+    left: &Path,
+    right: &Path,
+    joiner: &Joiner,
+    options: &JoinOptions,
+    string_decoder: StringDecoder,
+    mut writer: Option<&mut Writer<'_>>,
+) -> Result<JoinReport, XPTError> {
+    let mut left_file = std::fs::File::open(left)?;
+    let mut right_file = std::fs::File::open(right)?;
+    let (left_data, _) = Reader::new(&mut left_file, string_decoder).start()?;
+    let (right_data, _) = Reader::new(&mut right_file, string_decoder).start()?;
+    let mut left = Groups::new(left_data, false);
+    let mut right = Groups::new(right_data, true);
+    let mut line = vec![b' '; writer.as_ref().map_or(0, |writer| writer.line_length())];
+    let mut report = joiner.report();
+    let pairs = match options.input {
+        JoinInput::Sorted => None,
+        JoinInput::Hashed => {
+            let mut left_groups = left.all_groups(joiner)?;
+            let mut right_groups = right.all_groups(joiner)?;
+            Some(pair_hashed(&mut left_groups, &mut right_groups).into_iter())
+        }
+    };
+    let (mut l, mut r) = match pairs {
+        Some(_) => (Vec::new(), Vec::new()),
+        None => (left.next_group(joiner)?, right.next_group(joiner)?),
+    };
+    let mut pairs = pairs;
+    loop {
+        // the next pair of key groups, and which inputs move on after it
+        let (ordering, pair) = match pairs.as_mut() {
+            Some(pairs) => match pairs.next() {
+                Some(pair) => (None, pair),
+                None => break,
+            },
+            None if l.is_empty() && r.is_empty() => break,
+            None => {
+                let ordering = match (l.first(), r.first()) {
+                    (Some(a), Some(b)) => joiner.compare_keys(&left.view(a), &right.view(b)),
+                    (Some(_), None) => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let pair = match ordering {
+                    Ordering::Less => (std::mem::take(&mut l), Vec::new()),
+                    Ordering::Greater => (Vec::new(), std::mem::take(&mut r)),
+                    Ordering::Equal => (std::mem::take(&mut l), std::mem::take(&mut r)),
+                };
+                (Some(ordering), pair)
+            }
+        };
+        report.count(pair.0.len(), pair.1.len());
+        if joiner.keeps(!pair.0.is_empty(), !pair.1.is_empty()) {
+            let rows = Joiner::group_rows(pair.0.len(), pair.1.len());
+            report.rows += rows;
+            if let Some(writer) = writer.as_mut() {
+                let (lv, rv) = (left.views(&pair.0), right.views(&pair.1));
+                for i in 0..rows {
+                    joiner.fill_group(&lv, &rv, i, &mut line);
+                    writer.write_line(&line)?;
+                }
+            }
+        }
+        if let Some(ordering) = ordering {
+            if ordering.is_le() {
+                l = left.next_group(joiner)?;
+            }
+            if ordering.is_ge() {
+                r = right.next_group(joiner)?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Joins the files at `left` and `right` on the `keys` variables into
+/// `output`, with the one-to-one and one-to-many semantics of the DATA step
+/// `MERGE` statement.
+#[cfg(feature = "async")]
+pub async fn join_files<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+    left: P,
+    right: Q,
+    output: R,
+    keys: &[String],
+    options: &JoinOptions,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<JoinReport, XPTError> {
+    let (left, right) = (left.as_ref(), right.as_ref());
+    let (joiner, mut header) = {
+        let mut left_file = tokio::fs::File::open(left).await?;
+        let mut right_file = tokio::fs::File::open(right).await?;
+        let (_, left_meta) = Reader::new(&mut left_file, string_decoder).start().await?;
+        let (_, right_meta) = Reader::new(&mut right_file, string_decoder).start().await?;
+        let joiner = Joiner::new(&left_meta, &right_meta, keys, options)?;
+        let header = joiner.header(&left_meta, &right_meta);
+        (joiner, header)
+    };
+    if header.version == DocumentHeader::V8 {
+        // OBSV8 precedes the observations, so they are counted first
+        let report = join_pass(left, right, &joiner, options, string_decoder, None).await?;
+        header.observations = Some(report.rows);
+    }
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(&mut out, header, joiner.columns.clone(), string_encoder)?;
+    writer.start().await?;
+    let report = join_pass(
+        left,
+        right,
+        &joiner,
+        options,
+        string_decoder,
+        Some(&mut writer),
+    )
+    .await?;
+    writer.finish().await?;
+    Ok(report)
+}
+
+/// Reads both inputs once and matches their key groups, writing the joined
+/// observations to `writer` or only counting them when it is `None`.
+#[cfg(feature = "async")]
+async fn join_pass(
+    left: &Path,
+    right: &Path,
+    joiner: &Joiner,
+    options: &JoinOptions,
+    string_decoder: StringDecoder,
+    mut writer: Option<&mut Writer<'_>>,
+) -> Result<JoinReport, XPTError> {
+    let mut left_file = tokio::fs::File::open(left).await?;
+    let mut right_file = tokio::fs::File::open(right).await?;
+    let (left_data, _) = Reader::new(&mut left_file, string_decoder).start().await?;
+    let (right_data, _) = Reader::new(&mut right_file, string_decoder).start().await?;
+    let mut left = Groups::new(left_data, false);
+    let mut right = Groups::new(right_data, true);
+    let mut line = vec![b' '; writer.as_ref().map_or(0, |writer| writer.line_length())];
+    let mut report = joiner.report();
+    let pairs = match options.input {
+        JoinInput::Sorted => None,
+        JoinInput::Hashed => {
+            let mut left_groups = left.all_groups(joiner).await?;
+            let mut right_groups = right.all_groups(joiner).await?;
+            Some(pair_hashed(&mut left_groups, &mut right_groups).into_iter())
+        }
+    };
+    let (mut l, mut r) = match pairs {
+        Some(_) => (Vec::new(), Vec::new()),
+        None => (
+            left.next_group(joiner).await?,
+            right.next_group(joiner).await?,
+        ),
+    };
+    let mut pairs = pairs;
+    loop {
+        // the next pair of key groups, and which inputs move on after it
+        let (ordering, pair) = match pairs.as_mut() {
+            Some(pairs) => match pairs.next() {
+                Some(pair) => (None, pair),
+                None => break,
+            },
+            None if l.is_empty() && r.is_empty() => break,
+            None => {
+                let ordering = match (l.first(), r.first()) {
+                    (Some(a), Some(b)) => joiner.compare_keys(&left.view(a), &right.view(b)),
+                    (Some(_), None) => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let pair = match ordering {
+                    Ordering::Less => (std::mem::take(&mut l), Vec::new()),
+                    Ordering::Greater => (Vec::new(), std::mem::take(&mut r)),
+                    Ordering::Equal => (std::mem::take(&mut l), std::mem::take(&mut r)),
+                };
+                (Some(ordering), pair)
+            }
+        };
+        report.count(pair.0.len(), pair.1.len());
+        if joiner.keeps(!pair.0.is_empty(), !pair.1.is_empty()) {
+            let rows = Joiner::group_rows(pair.0.len(), pair.1.len());
+            report.rows += rows;
+            if let Some(writer) = writer.as_mut() {
+                let (lv, rv) = (left.views(&pair.0), right.views(&pair.1));
+                for i in 0..rows {
+                    joiner.fill_group(&lv, &rv, i, &mut line);
+                    writer.write_line(&line).await?;
+                }
+            }
+        }
+        if let Some(ordering) = ordering {
+            if ordering.is_le() {
+                l = left.next_group(joiner).await?;
+            }
+            if ordering.is_ge() {
+                r = right.next_group(joiner).await?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(not(feature = "async"))]
+    fn write_dm(path: &std::path::Path, version: crate::part::DocumentHeader) {
+        use crate::fixture::{column, write_file};
+        use crate::part::ColumnType;
+        use crate::reader::Val;
+
+        let columns = vec![
+            column("USUBJID", ColumnType::CHAR, 11),
+            column("DOMAIN", ColumnType::CHAR, 2),
+            column("AGE", ColumnType::NUMERIC, 8),
+        ];
+        // unsorted, for the hashed join
+        let rows: Vec<Vec<Val>> = [
+            ("01-701-1099", 50.0),
+            ("01-701-1017", 70.0),
+            ("01-701-1015", 63.0),
+        ]
+        .iter()
+        .map(|(usubjid, age)| {
+            vec![
+                Val::Char(usubjid.to_string()),
+                Val::Char("DM".to_string()),
+                Val::Number(*age),
+            ]
+        })
+        .collect();
+        write_file(path, version, "DM", columns, &rows);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_join_files() {
+        use crate::join::{join_files, JoinConflict, JoinInput, JoinKind, JoinOptions};
+        use crate::part::DocumentHeader;
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::sort::{sort_file, SortKey, SortOptions};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let directory = std::env::temp_dir().join("xpt_r_join_lb2");
+        std::fs::create_dir_all(&directory).unwrap();
+        let dm = directory.join("dm.xpt");
+        let sorted_dm = directory.join("dm_sorted.xpt");
+        let output = directory.join("joined.xpt");
+        write_dm(&dm, DocumentHeader::V5);
+        let keys = vec!["USUBJID".to_string()];
+        sort_file(
+            &dm,
+            &sorted_dm,
+            &[SortKey::ascending("USUBJID")],
+            &SortOptions::default(),
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        let read = |path: &std::path::Path| {
+            let mut file = std::fs::File::open(path).unwrap();
+            let (mut data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+            let mut rows = Vec::new();
+            while let Some(row) = data.read_line().unwrap() {
+                rows.push(row);
+            }
+            (meta, rows)
+        };
+
+        for (input, right) in [(JoinInput::Sorted, &sorted_dm), (JoinInput::Hashed, &dm)] {
+            let options = |kind, conflict| JoinOptions {
+                kind,
+                input,
+                conflict,
+            };
+            let join = |kind, conflict| {
+                join_files(
+                    "sample/LB2.xpt",
+                    right,
+                    &output,
+                    &keys,
+                    &options(kind, conflict),
+                    UTF8_STRING_DECODER,
+                    UTF8_STRING_ENCODER,
+                )
+            };
+
+            let report = join(JoinKind::Inner, JoinConflict::Right).unwrap();
+            assert_eq!(
+                (report.rows, report.matched, report.unmatched),
+                (6, (6, 2), (3, 1))
+            );
+            let (meta, rows) = read(&output);
+            assert_eq!(meta.columns.len(), 10);
+            assert_eq!(meta.columns[9].name, "AGE");
+            // one-to-many: the DM observation is kept across the LB group,
+            // while DOMAIN comes from the input read last
+            assert_eq!(rows[2][9], Val::Number(63.0));
+            assert_eq!(rows[0][1], Val::Char("DM".to_string()));
+            assert_eq!(rows[2][1], Val::Char("LB".to_string()));
+            assert_eq!(rows[5][9], Val::Number(70.0));
+
+            let report = join(JoinKind::Left, JoinConflict::Left).unwrap();
+            assert_eq!(report.rows, 9);
+            let (_, rows) = read(&output);
+            assert_eq!(rows[3][2], Val::Char("01-701-1016".to_string()));
+            assert_eq!(rows[3][9], Val::Nil);
+            assert!(rows.iter().all(|row| row[1] == Val::Char("LB".to_string())));
+
+            let report = join(JoinKind::Full, JoinConflict::Right).unwrap();
+            assert_eq!(report.rows, 10);
+            let (_, rows) = read(&output);
+            let extra = &rows[9];
+            assert_eq!(extra[2], Val::Char("01-701-1099".to_string()));
+            assert_eq!(extra[3], Val::Nil);
+            assert_eq!(rows[3][1], Val::Char("LB".to_string()));
+
+            assert!(join(JoinKind::Full, JoinConflict::Error).is_err());
+        }
+
+        // the unsorted DM cannot be merged as sorted input
+        let error = join_files(
+            "sample/LB2.xpt",
+            &dm,
+            &output,
+            &keys,
+            &JoinOptions::default(),
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        );
+        assert!(error.is_err());
+
+        // a V8 input makes the output V8, with the joined count in OBSV8
+        let dm8 = directory.join("dm8.xpt");
+        write_dm(&dm8, DocumentHeader::V8);
+        let options = JoinOptions {
+            input: JoinInput::Hashed,
+            ..JoinOptions::default()
+        };
+        let report = join_files(
+            "sample/LB2.xpt",
+            &dm8,
+            &output,
+            &keys,
+            &options,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(report.rows, 10);
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(meta.version, DocumentHeader::V8);
+        assert_eq!(data.observations(), 10);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
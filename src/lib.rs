@@ -1,5 +1,28 @@
+pub mod batch;
+pub mod cdisc;
+pub mod compare;
+pub mod convert;
+pub mod define;
 pub mod deserialize;
+pub mod detect;
 pub mod error;
+pub mod filter;
+#[cfg(test)]
+mod fixture;
+pub mod join;
 mod macros;
+pub mod migrate;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod number;
 pub mod part;
+pub mod profile;
 pub mod reader;
+pub mod shrink;
+pub mod sort;
+pub mod split;
+pub mod stack;
+pub mod transform;
+pub mod validate;
+pub mod view;
+pub mod writer;
@@ -0,0 +1,408 @@
+use crate::define::display_format;
+use crate::error::XPTError;
+use crate::part::{ColumnMeta, ColumnType, DocumentHeader, StringDecoder, StringEncoder};
+use crate::reader::Reader;
+use crate::writer::{layout, relayout, MemberHeader, Writer};
+use std::fmt::Display;
+use std::path::Path;
+
+/// What a downgrade to V5 does with names, labels and lengths V5 cannot hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LongValues {
+    /// Fail on the first one, before writing anything.
+    #[default]
+    Fail,
+    /// Cut them to the V5 limits and report every cut. Names cut to the same
+    /// 8 characters get a numeric suffix; formats and informats with longer
+    /// names are dropped.
+    Truncate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateAttribute {
+    DatasetName,
+    Name,
+    Label,
+    Length,
+    Format,
+    Informat,
+}
+
+/// A name, label or length cut by a downgrade, written out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrateChange {
+    /// The column as named in the input, empty for the dataset.
+    pub column: String,
+    pub attribute: MigrateAttribute,
+    pub old: String,
+    pub new: String,
+}
+
+/// Outcome of [`migrate_file`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrateReport {
+    pub from: DocumentHeader,
+    pub to: DocumentHeader,
+    pub rows: usize,
+    pub changes: Vec<MigrateChange>,
+}
+
+impl Display for MigrateReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:?} -> {:?}, {} observations",
+            self.from, self.to, self.rows
+        )?;
+        for change in &self.changes {
+            let what = match change.attribute {
+                MigrateAttribute::DatasetName => "Dataset name",
+                MigrateAttribute::Name => "Name",
+                MigrateAttribute::Label => "Label",
+                MigrateAttribute::Length => "Length",
+                MigrateAttribute::Format => "Format",
+                MigrateAttribute::Informat => "Informat",
+            };
+            writeln!(
+                f,
+                "  {:<8} {:<12} {:?} -> {:?}",
+                change.column, what, change.old, change.new
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The longest prefix of `str` of at most `bytes` bytes.
+fn truncate(str: &str, bytes: usize) -> String {
+    let mut end = bytes.min(str.len());
+    while !str.is_char_boundary(end) {
+        end -= 1;
+    }
+    str[..end].to_string()
+}
+
+/// Columns and member header rewritten for version `to`. Upgrades keep
+/// everything; downgrades to V5 cut names to 8 bytes, labels to 40 and CHAR
+/// lengths to 200 and drop formats named in more than 8, or fail, as
+/// `long_values` says.
+pub fn migrate_columns(
+    header: &mut MemberHeader,
+    columns: &[ColumnMeta],
+    to: DocumentHeader,
+    long_values: LongValues,
+) -> Result<(Vec<ColumnMeta>, Vec<MigrateChange>), XPTError> {
+    header.version = to;
+    let mut columns = columns.to_vec();
+    let mut changes = Vec::new();
+    if to == DocumentHeader::V8 {
+        return Ok((columns, changes));
+    }
+    let mut change = |column: &str, attribute, old: String, new: String| {
+        if long_values == LongValues::Fail {
+            return Err(XPTError::EncodeError(format!(
+                "{:?} of {} is too long for V5: {:?}",
+                attribute,
+                if column.is_empty() {
+                    "the dataset"
+                } else {
+                    column
+                },
+                old
+            )));
+        }
+        changes.push(MigrateChange {
+            column: column.to_string(),
+            attribute,
+            old,
+            new,
+        });
+        Ok(())
+    };
+    if header.dataset_name.len() > 8 {
+        let name = truncate(&header.dataset_name, 8);
+        change(
+            "",
+            MigrateAttribute::DatasetName,
+            header.dataset_name.clone(),
+            name.clone(),
+        )?;
+        header.dataset_name = name;
+    }
+    let mut taken: Vec<String> = columns
+        .iter()
+        .filter(|c| c.name.len() <= 8)
+        .map(|c| c.name.to_ascii_uppercase())
+        .collect();
+    for column in columns.iter_mut() {
+        let input_name = column.name.clone();
+        if column.name.len() > 8 {
+            let mut name = truncate(&column.name, 8);
+            let mut suffix = 1;
+            while taken.contains(&name.to_ascii_uppercase()) {
+                let digits = suffix.to_string();
+                name = truncate(&column.name, 8 - digits.len()) + &digits;
+                suffix += 1;
+            }
+            taken.push(name.to_ascii_uppercase());
+            change(
+                &input_name,
+                MigrateAttribute::Name,
+                column.name.clone(),
+                name.clone(),
+            )?;
+            column.name = name;
+        }
+        if column.label.len() > 40 {
+            let label = truncate(&column.label, 40);
+            change(
+                &input_name,
+                MigrateAttribute::Label,
+                column.label.clone(),
+                label.clone(),
+            )?;
+            column.label = label;
+        }
+        if column.column_type == ColumnType::CHAR && column.length > 200 {
+            change(
+                &input_name,
+                MigrateAttribute::Length,
+                column.length.to_string(),
+                "200".to_string(),
+            )?;
+            column.length = 200;
+        }
+        for (attribute, format) in [
+            (MigrateAttribute::Format, &mut column.format),
+            (MigrateAttribute::Informat, &mut column.in_format),
+        ] {
+            if format.0.len() > 8 {
+                let old = display_format(format).unwrap_or_default();
+                change(&input_name, attribute, old, String::new())?;
+                *format = (String::new(), 0, 0);
+            }
+        }
+    }
+    Ok((columns, changes))
+}
+
+/// Rewrites the file at `input` as version `to`. An upgrade to V8 reads the
+/// input twice when it does not record its observation count.
+#[cfg(not(feature = "async"))]
+pub fn migrate_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    to: DocumentHeader,
+    long_values: LongValues,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<MigrateReport, XPTError> {
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut header = MemberHeader::from_meta(&meta);
+    let (columns, changes) = migrate_columns(&mut header, &meta.columns, to, long_values)?;
+    if to == DocumentHeader::V8 {
+        let mut rows = data.observations();
+        if rows == 0 {
+            while let Some(batch) = data.read_batch(4096)? {
+                rows += batch.rows;
+            }
+            file = std::fs::File::open(&input)?;
+            reader = Reader::new(&mut file, string_decoder);
+            data = reader.start()?.0;
+        }
+        header.observations = Some(rows);
+    }
+    let layout = layout(&columns);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(&mut out, header, columns, string_encoder)?;
+    writer.start()?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row()? {
+        relayout(&row, &layout, &mut line);
+        writer.write_line(&line)?;
+    }
+    let rows = writer.rows();
+    writer.finish()?;
+    Ok(MigrateReport {
+        from: meta.version,
+        to,
+        rows,
+        changes,
+    })
+}
+
+/// Rewrites the file at `input` as version `to`. An upgrade to V8 reads the
+/// input twice when it does not record its observation count.
+#[cfg(feature = "async")]
+pub async fn migrate_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    to: DocumentHeader,
+    long_values: LongValues,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<MigrateReport, XPTError> {
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut header = MemberHeader::from_meta(&meta);
+    let (columns, changes) = migrate_columns(&mut header, &meta.columns, to, long_values)?;
+    if to == DocumentHeader::V8 {
+        let mut rows = data.observations();
+        if rows == 0 {
+            while let Some(batch) = data.read_batch(4096).await? {
+                rows += batch.rows;
+            }
+            file = tokio::fs::File::open(&input).await?;
+            reader = Reader::new(&mut file, string_decoder);
+            data = reader.start().await?.0;
+        }
+        header.observations = Some(rows);
+    }
+    let layout = layout(&columns);
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(&mut out, header, columns, string_encoder)?;
+    writer.start().await?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row().await? {
+        relayout(&row, &layout, &mut line);
+        writer.write_line(&line).await?;
+    }
+    let rows = writer.rows();
+    writer.finish().await?;
+    Ok(MigrateReport {
+        from: meta.version,
+        to,
+        rows,
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_migrate_file() {
+        use crate::migrate::{migrate_file, LongValues, MigrateAttribute};
+        use crate::part::DocumentHeader;
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::transform::{transform_file, Transform};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let directory = std::env::temp_dir().join("xpt_r_migrate_lb2");
+        std::fs::create_dir_all(&directory).unwrap();
+        let v8 = directory.join("lb_v8.xpt");
+        let long = directory.join("lb_long.xpt");
+        let v5 = directory.join("lb_v5.xpt");
+
+        let report = migrate_file(
+            "sample/LB2.xpt",
+            &v8,
+            DocumentHeader::V8,
+            LongValues::Fail,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!((report.from, report.rows), (DocumentHeader::V5, 9));
+        assert!(report.changes.is_empty());
+
+        let rename = |from: &str, to: &str| Transform::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        transform_file(
+            &v8,
+            &long,
+            &[
+                rename("LBTESTCD", "LBTESTCODE"),
+                rename("LBTEST", "LBTESTCOLLECTED"),
+                Transform::Relabel {
+                    name: "LBTESTCODE".to_string(),
+                    label: "Lab Test or Examination Short Name, as collected".to_string(),
+                },
+            ],
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        let migrate = |long_values| {
+            migrate_file(
+                &long,
+                &v5,
+                DocumentHeader::V5,
+                long_values,
+                UTF8_STRING_DECODER,
+                UTF8_STRING_ENCODER,
+            )
+        };
+        assert!(migrate(LongValues::Fail).is_err());
+        let report = migrate(LongValues::Truncate).unwrap();
+        assert_eq!((report.to, report.rows), (DocumentHeader::V5, 9));
+        let changes: Vec<_> = report
+            .changes
+            .iter()
+            .map(|c| (c.attribute, c.new.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (MigrateAttribute::Name, "LBTESTCO"),
+                (
+                    MigrateAttribute::Label,
+                    "Lab Test or Examination Short Name, as c"
+                ),
+                (MigrateAttribute::Name, "LBTESTC1"),
+            ]
+        );
+
+        let mut file = std::fs::File::open(&v5).unwrap();
+        let (mut data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(meta.version, DocumentHeader::V5);
+        assert_eq!(meta.columns[4].name, "LBTESTCO");
+        assert_eq!(meta.columns[5].name, "LBTESTC1");
+        let row = data.read_line().unwrap().unwrap();
+        assert_eq!(row[5], Val::Char("Alanine Aminotransferase".to_string()));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_migrate_short_v5_rows() {
+        use crate::fixture::{short_rows, write_file};
+        use crate::migrate::{migrate_file, LongValues};
+        use crate::part::DocumentHeader;
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        // the count written to OBSV8 must leave out the padding of the V5 input
+        let directory = std::env::temp_dir().join("xpt_r_migrate_short");
+        std::fs::create_dir_all(&directory).unwrap();
+        let v5 = directory.join("short.xpt");
+        let v8 = directory.join("short_v8.xpt");
+        let (columns, rows) = short_rows();
+        write_file(&v5, DocumentHeader::V5, "SHORT", columns, &rows);
+        let report = migrate_file(
+            &v5,
+            &v8,
+            DocumentHeader::V8,
+            LongValues::Fail,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(report.rows, 3);
+
+        let mut file = std::fs::File::open(&v8).unwrap();
+        let (mut data, _) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(data.observations(), 3);
+        let mut copied = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            copied.push(row);
+        }
+        assert_eq!(copied, rows);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::batch::Batch;
+use crate::error::XPTError;
+use crate::part::{DocumentMeta, StringDecoder};
+use crate::reader::{trim_record_padding, Reader};
+use crate::view::RowView;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use std::fs::File;
+use std::path::Path;
+
+/// Reads a memory-mapped XPT file.
+///
+/// Observations have a fixed width, so once the header is parsed any row
+/// can be located directly and ranges of rows decoded on several threads.
+pub struct MmapReader {
+    mmap: Mmap,
+    meta: DocumentMeta,
+    data_offset: usize,
+    line_length: usize,
+    rows: usize,
+    columns: Vec<(u32, u16, u16)>,
+    string_decoder: StringDecoder,
+}
+
+impl MmapReader {
+    pub fn open<P: AsRef<Path>>(path: P, string_decoder: StringDecoder) -> Result<Self, XPTError> {
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only; callers must not truncate the file
+        // while the reader is alive, as with any memory-mapped input.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_mmap(mmap, string_decoder)
+    }
+
+    pub fn from_mmap(mmap: Mmap, string_decoder: StringDecoder) -> Result<Self, XPTError> {
+        let mut input: &[u8] = &mmap;
+        let (raw_reader, meta) = start(&mut input, string_decoder)?;
+        let line_length = raw_reader.line_length() as usize;
+        let columns = raw_reader.columns().to_vec();
+        let observations = raw_reader.observations();
+        drop(raw_reader);
+        let data_offset = mmap.len() - input.len();
+        let data_length = input.len();
+        let rows = if line_length == 0 {
+            0
+        } else if observations != 0 {
+            observations.min(data_length / line_length)
+        } else {
+            trim_record_padding(input, data_length / line_length, line_length, data_length)
+        };
+        Ok(MmapReader {
+            mmap,
+            meta,
+            data_offset,
+            line_length,
+            rows,
+            columns,
+            string_decoder,
+        })
+    }
+
+    pub fn meta(&self) -> &DocumentMeta {
+        &self.meta
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn row(&self, i: usize) -> RowView<'_> {
+        let start = self.data_offset + i * self.line_length;
+        RowView::new(
+            &self.mmap[start..start + self.line_length],
+            &self.columns,
+            self.string_decoder,
+        )
+    }
+
+    pub fn par_rows(&self) -> impl IndexedParallelIterator<Item = RowView<'_>> {
+        (0..self.rows).into_par_iter().map(|i| self.row(i))
+    }
+
+    /// Decodes the file in blocks of `batch_rows` rows, one block per task.
+    pub fn par_batches(
+        &self,
+        batch_rows: usize,
+    ) -> impl IndexedParallelIterator<Item = Batch> + '_ {
+        let data = &self.mmap[self.data_offset..self.data_offset + self.rows * self.line_length];
+        data.par_chunks(batch_rows.max(1) * self.line_length.max(1))
+            .map(|lines| {
+                Batch::from_lines(
+                    lines,
+                    lines.len() / self.line_length,
+                    self.line_length,
+                    &self.columns,
+                )
+            })
+    }
+}
+
+#[cfg(not(feature = "async"))]
+fn start<'a>(
+    input: &'a mut &[u8],
+    string_decoder: StringDecoder,
+) -> Result<(crate::reader::RawReader<'a>, DocumentMeta), XPTError> {
+    Reader::new(input, string_decoder).start()
+}
+
+/// Parses the header without a runtime: reading from a slice never waits,
+/// so the future completes on its first poll, inside or outside of tokio.
+#[cfg(feature = "async")]
+fn start<'a>(
+    input: &'a mut &[u8],
+    string_decoder: StringDecoder,
+) -> Result<(crate::reader::RawReader<'a>, DocumentMeta), XPTError> {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+    let mut reader = Reader::new(input, string_decoder);
+    let mut future = std::pin::pin!(reader.start());
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(result) => result,
+        Poll::Pending => Err(XPTError::ParseError(
+            "header of a mapped file did not parse in one pass".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::batch::BatchColumn;
+    use crate::mmap::MmapReader;
+    use crate::reader::UTF8_STRING_DECODER;
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_mmap_reader() {
+        let reader = MmapReader::open("sample/LB2.xpt", UTF8_STRING_DECODER).unwrap();
+        assert_eq!(reader.rows(), 9);
+        assert_eq!(reader.meta().columns.len(), 9);
+        assert_eq!(reader.row(2).get_str(5).unwrap(), "Glucose");
+        let total: f64 = reader.par_rows().filter_map(|row| row.get_f64(7)).sum();
+        assert!((total - 120.56).abs() < 1e-9);
+        let batches: Vec<_> = reader.par_batches(4).collect();
+        assert_eq!(
+            batches.iter().map(|b| b.rows).collect::<Vec<_>>(),
+            vec![4, 4, 1]
+        );
+        if let BatchColumn::Numeric(values) = &batches[1].columns[3] {
+            assert_eq!(values[0], Some(205.0));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_mmap_reader_in_runtime() {
+        let reader = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async { MmapReader::open("sample/LB2.xpt", UTF8_STRING_DECODER) })
+            .unwrap();
+        assert_eq!(reader.rows(), 9);
+        assert_eq!(reader.meta().dataset_name, "LB");
+    }
+}
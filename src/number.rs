@@ -0,0 +1,253 @@
+use crate::error::XPTError;
+
+// IBM mainframe: sign * 0.mantissa * 16 ** (exponent - 64), 7-bit exponent, 56-bit mantissa
+// IEEE 754: sign * 1.mantissa * 2 ** (exponent - 1023), 11-bit exponent, 52-bit mantissa
+
+const MANTISSA_MASK: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// Exact power of two for exponents in the normal IEEE range.
+fn pow2(exponent: i32) -> f64 {
+    f64::from_bits(((exponent + 1023) as u64) << 52)
+}
+
+/// True when `first` is the leading byte of a SAS missing value: `.`, `._`
+/// or one of the special missing values `.A` to `.Z`.
+pub fn is_missing_code(first: u8) -> bool {
+    first == b'.' || first == b'_' || first.is_ascii_uppercase()
+}
+
+/// Converts an IBM double of 1 to 8 bytes to IEEE, `None` for missing values.
+///
+/// Short numerics keep the most significant bytes, so the value is read as if
+/// padded with zero bytes. Every IBM value fits the IEEE exponent range, and
+/// mantissas wider than 53 bits are rounded to nearest, ties to even.
+pub fn byte2number(bytearray: &[u8]) -> Option<f64> {
+    let mut array = [0u8; 8];
+    let length = bytearray.len().min(8);
+    array[..length].copy_from_slice(&bytearray[..length]);
+    let val = u64::from_be_bytes(array);
+    let mantissa = val & MANTISSA_MASK;
+    let negative = array[0] & 0x80 != 0;
+    if mantissa == 0 {
+        if is_missing_code(array[0]) {
+            return None;
+        }
+        return Some(if negative { -0f64 } else { 0f64 });
+    }
+    let exponent = ((array[0] & 0x7f) as i32 - 64) * 4 - 56;
+    // the u64 to f64 cast rounds to nearest, scaling by a power of two is exact
+    let magnitude = mantissa as f64 * pow2(exponent);
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// Writes the SAS missing value `code` (`.`, `_` or `A` to `Z`) into `output`.
+pub fn missing2byte(code: u8, output: &mut [u8]) -> Result<(), XPTError> {
+    if !is_missing_code(code) {
+        return Err(XPTError::EncodeError(format!(
+            "{:?} is not a missing value code",
+            code as char
+        )));
+    }
+    check_length(output)?;
+    output.fill(0);
+    output[0] = code;
+    Ok(())
+}
+
+/// Converts `value` to an IBM double and writes it into `output`, whose
+/// length of 3 to 8 bytes is the numeric variable length.
+///
+/// `None` and NaN are written as the standard missing value `.`. Lengths
+/// below 8 keep the leading bytes, rounding the mantissa to nearest with ties
+/// to even. Values outside the IBM range are reported as
+/// [`XPTError::Overflow`] or [`XPTError::Underflow`].
+pub fn number2byte(value: Option<f64>, output: &mut [u8]) -> Result<(), XPTError> {
+    let value = match value {
+        Some(value) if !value.is_nan() => value,
+        _ => return missing2byte(b'.', output),
+    };
+    check_length(output)?;
+    if value.is_infinite() {
+        return Err(XPTError::Overflow(value));
+    }
+    let sign: u64 = if value.is_sign_negative() { 0x80 } else { 0 };
+    if value == 0.0 {
+        output.fill(0);
+        output[0] = sign as u8;
+        return Ok(());
+    }
+    let bits = value.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & 0x000f_ffff_ffff_ffff;
+    // value = mantissa * 2 ** exponent
+    let (mantissa, exponent) = if biased == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | 1 << 52, biased - 1075)
+    };
+    // value lies in [2 ** (top - 1), 2 ** top) and so in [16 ** (hex - 1), 16 ** hex)
+    let top = exponent + (64 - mantissa.leading_zeros() as i32);
+    let mut hex_exponent = (top + 3).div_euclid(4);
+    // at most 53 significant bits moved up to fill 56, so never negative
+    let mut ibm = mantissa << (exponent + 56 - 4 * hex_exponent);
+    let dropped = 8 * (8 - output.len());
+    if dropped > 0 {
+        let half = 1u64 << (dropped - 1);
+        let rest = ibm & ((1 << dropped) - 1);
+        ibm >>= dropped;
+        if rest > half || (rest == half && ibm & 1 == 1) {
+            ibm += 1;
+        }
+        ibm <<= dropped;
+        if ibm & !MANTISSA_MASK != 0 {
+            ibm >>= 4;
+            hex_exponent += 1;
+        }
+    }
+    let biased_hex = hex_exponent + 64;
+    if biased_hex > 127 {
+        return Err(XPTError::Overflow(value));
+    }
+    if biased_hex < 0 {
+        return Err(XPTError::Underflow(value));
+    }
+    let bytes = (((sign | biased_hex as u64) << 56) | ibm).to_be_bytes();
+    output.copy_from_slice(&bytes[..output.len()]);
+    Ok(())
+}
+
+fn check_length(output: &[u8]) -> Result<(), XPTError> {
+    if !(3..=8).contains(&output.len()) {
+        return Err(XPTError::EncodeError(format!(
+            "numeric length {} is outside 3 to 8",
+            output.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::XPTError;
+    use crate::number::{byte2number, missing2byte, number2byte};
+    use proptest::prelude::*;
+
+    fn encode(value: Option<f64>, length: usize) -> Result<Vec<u8>, XPTError> {
+        let mut bytes = vec![0; length];
+        number2byte(value, &mut bytes).map(|_| bytes)
+    }
+
+    #[test]
+    fn test_byte2number() {
+        assert_eq!(
+            byte2number(&[63, 245, 194, 143, 92, 0]).unwrap(),
+            0.059999999997671694
+        );
+        assert_eq!(
+            encode(Some(0.059999999997671694), 6).unwrap(),
+            [63, 245, 194, 143, 92, 0]
+        );
+        // leading hex digits that are not a power of two
+        assert_eq!(byte2number(&[0x41, 0x30, 0, 0, 0, 0, 0, 0]), Some(3.0));
+        assert_eq!(byte2number(&[0x41, 0x50, 0, 0]), Some(5.0));
+        assert_eq!(byte2number(&[0x41, 0x60, 0]), Some(6.0));
+        assert_eq!(byte2number(&[0xc1, 0x70, 0, 0, 0, 0, 0]), Some(-7.0));
+        assert_eq!(byte2number(&[0x42, 0x64, 0, 0, 0, 0, 0, 0]), Some(100.0));
+        // denormalized: leading hex digit zero
+        assert_eq!(byte2number(&[0x42, 0x01, 0, 0, 0, 0, 0, 0]), Some(1.0));
+        assert_eq!(byte2number(&[0x00, 0, 0, 0, 0, 0, 0, 0]), Some(0.0));
+        assert!(byte2number(&[0x80, 0, 0, 0, 0, 0, 0, 0])
+            .unwrap()
+            .is_sign_negative());
+        assert_eq!(byte2number(&[b'.', 0, 0, 0, 0, 0, 0, 0]), None);
+        assert_eq!(byte2number(&[b'A', 0, 0]), None);
+        assert_eq!(byte2number(&[b'_', 0, 0, 0, 0, 0, 0, 0]), None);
+        // smallest and largest normalized magnitudes
+        assert_eq!(
+            byte2number(&[0x00, 0x10, 0, 0, 0, 0, 0, 0]),
+            Some(16f64.powi(-65))
+        );
+        assert_eq!(
+            byte2number(&[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Some(16f64.powi(63))
+        );
+    }
+
+    #[test]
+    fn test_number2byte() {
+        assert_eq!(
+            encode(Some(0.1), 8).unwrap(),
+            [0x40, 0x19, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]
+        );
+        // rounds instead of truncating
+        assert_eq!(encode(Some(0.1), 4).unwrap(), [0x40, 0x19, 0x99, 0x9a]);
+        assert_eq!(encode(Some(-3.0), 3).unwrap(), [0xc1, 0x30, 0]);
+        // a carry out of the mantissa moves to the next hex exponent
+        assert_eq!(encode(Some(15.999999999), 3).unwrap(), [0x42, 0x10, 0x00]);
+        assert_eq!(encode(Some(0.0), 8).unwrap(), [0; 8]);
+        assert_eq!(encode(None, 8).unwrap(), [b'.', 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encode(Some(f64::NAN), 5).unwrap(), [b'.', 0, 0, 0, 0]);
+        let mut bytes = [0; 8];
+        missing2byte(b'A', &mut bytes).unwrap();
+        assert_eq!(byte2number(&bytes), None);
+        assert!(missing2byte(b'a', &mut bytes).is_err());
+        assert!(matches!(
+            encode(Some(f64::INFINITY), 8),
+            Err(XPTError::Overflow(_))
+        ));
+        assert!(matches!(encode(Some(1e76), 8), Err(XPTError::Overflow(_))));
+        assert!(matches!(
+            encode(Some(-1e-80), 8),
+            Err(XPTError::Underflow(_))
+        ));
+        assert!(matches!(
+            encode(Some(1.0), 2),
+            Err(XPTError::EncodeError(_))
+        ));
+        assert!(matches!(
+            encode(Some(1.0), 9),
+            Err(XPTError::EncodeError(_))
+        ));
+    }
+
+    // biased IEEE exponents from 2 ** -260 up to 2 ** 251
+    fn ieee_in_ibm_range() -> impl Strategy<Value = f64> {
+        (any::<bool>(), 763u64..1275, 0u64..1 << 52).prop_map(|(sign, exponent, fraction)| {
+            f64::from_bits(((sign as u64) << 63) | (exponent << 52) | fraction)
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(20000))]
+
+        #[test]
+        fn test_ieee_round_trip(x in ieee_in_ibm_range()) {
+            let bytes = encode(Some(x), 8).unwrap();
+            prop_assert_eq!(byte2number(&bytes).unwrap().to_bits(), x.to_bits());
+        }
+
+        #[test]
+        fn test_short_lengths(x in ieee_in_ibm_range(), length in 3usize..8) {
+            // rounding to fewer bytes is off by at most half a unit in the last kept place
+            if let Ok(bytes) = encode(Some(x), length) {
+                let exact = encode(Some(x), 8).unwrap();
+                let ulp = byte2number(&[exact[0] & 0x7f, 0, 0, 0, 0, 0, 0, 1]).unwrap()
+                    * 256f64.powi(8 - length as i32);
+                prop_assert!((byte2number(&bytes).unwrap() - x).abs() <= ulp / 2.0);
+            }
+        }
+
+        #[test]
+        fn test_ibm_round_trip(
+            sign in any::<bool>(),
+            exponent in 0u64..128,
+            mantissa in 1u64 << 52..1u64 << 53,
+            shift in 0u32..4,
+        ) {
+            // mantissas of at most 53 significant bits are exact in IEEE
+            let bytes = (((sign as u64) << 63) | (exponent << 56) | (mantissa << shift)).to_be_bytes();
+            let number = byte2number(&bytes).unwrap();
+            prop_assert_eq!(encode(Some(number), 8).unwrap(), bytes);
+        }
+    }
+}
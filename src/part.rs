@@ -1,5 +1,6 @@
 use crate::deserialize::{FromBytes, U8Array};
 use crate::deserialize_in_order;
+use crate::detect::EncodingGuess;
 use crate::error::XPTError;
 
 #[derive(Debug)]
@@ -28,7 +29,19 @@ pub trait XptHeader: Sized {
     fn from_raw(header: &str, body: &str) -> Result<Self, XPTError>;
 }
 
-#[derive(Debug)]
+/// The number in `range` of a header record body, such as the length of the
+/// NAMESTR records.
+fn header_number<T: std::str::FromStr>(
+    header: &str,
+    body: &str,
+    range: impl std::slice::SliceIndex<str, Output = str>,
+) -> Result<T, XPTError> {
+    body.get(range)
+        .and_then(|number| number.trim().parse().ok())
+        .ok_or_else(|| XPTError::ParseError(format!("bad {} body {:?}", header, body)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DocumentHeader {
     V5,
     V8,
@@ -67,9 +80,7 @@ impl XptHeader for V5MemberTitleHeader {
                 body
             )));
         }
-        Ok(V5MemberTitleHeader(
-            u16::from_str_radix(&body[26..].trim(), 10).unwrap(),
-        ))
+        Ok(V5MemberTitleHeader(header_number(header, body, 26..)?))
     }
 }
 #[derive(Debug)]
@@ -89,9 +100,7 @@ impl XptHeader for V8MemberTitleHeader {
                 body
             )));
         }
-        Ok(V8MemberTitleHeader(
-            u16::from_str_radix(&body[26..].trim(), 10).unwrap(),
-        ))
+        Ok(V8MemberTitleHeader(header_number(header, body, 26..)?))
     }
 }
 
@@ -106,9 +115,7 @@ impl XptHeader for V5NameStrTitleHeader {
                 header
             )));
         }
-        Ok(V5NameStrTitleHeader(
-            u16::from_str_radix(&body[6..10].trim(), 10).unwrap(),
-        ))
+        Ok(V5NameStrTitleHeader(header_number(header, body, 6..10)?))
     }
 }
 
@@ -123,44 +130,43 @@ impl XptHeader for V8NameStrTitleHeader {
                 header
             )));
         }
-        Ok(V8NameStrTitleHeader(
-            u16::from_str_radix(&body[6..10].trim(), 10).unwrap(),
-        ))
+        Ok(V8NameStrTitleHeader(header_number(header, body, 6..10)?))
     }
 }
-#[derive(Debug)]
-pub struct V8ObsHeaderRecord(pub u64);
-
-impl XptHeader for V8ObsHeaderRecord {
-    fn from_raw(header: &str, body: &str) -> Result<Self, XPTError> {
-        if header != "OBSV8   HEADER RECORD" {
-            return Err(XPTError::ParseError(format!(
-                "unknown NAMESTR header {}",
-                header
-            )));
-        }
-        Ok(V8ObsHeaderRecord(
-            u64::from_str_radix(&body[..].trim(), 10).unwrap(),
-        ))
-    }
+/// The header record after the V8 NAMESTRs. `LABELV8` records carry names
+/// and labels too long for the NAMESTR, `LABELV9` records also carry format
+/// and informat names longer than 8; either is followed by `OBSV8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum V8ExtensionHeader {
+    LabelV8(u16),
+    LabelV9(u16),
+    Observations(u64),
 }
 
-#[derive(Debug)]
-pub struct V8LabelStrTitleHeader(pub u16);
-
-impl XptHeader for V8LabelStrTitleHeader {
+impl XptHeader for V8ExtensionHeader {
     fn from_raw(header: &str, body: &str) -> Result<Self, XPTError> {
-        if header != "LABELV8 HEADER RECORD" {
-            return Err(XPTError::ParseError(format!(
-                "unknown NAMESTR header {}",
+        let count = || {
+            body.trim()
+                .parse::<u64>()
+                .map_err(|_| XPTError::ParseError(format!("bad {} count {:?}", header, body)))
+        };
+        let label_count = || {
+            u16::try_from(count()?).map_err(|_| {
+                XPTError::ParseError(format!("{} count {} is too large", header, body.trim()))
+            })
+        };
+        match header {
+            "LABELV8 HEADER RECORD" => Ok(V8ExtensionHeader::LabelV8(label_count()?)),
+            "LABELV9 HEADER RECORD" => Ok(V8ExtensionHeader::LabelV9(label_count()?)),
+            "OBSV8   HEADER RECORD" => Ok(V8ExtensionHeader::Observations(count()?)),
+            _ => Err(XPTError::ParseError(format!(
+                "unknown header after NAMESTR {}",
                 header
-            )));
+            ))),
         }
-        Ok(V8LabelStrTitleHeader(
-            u16::from_str_radix(&body[..].trim(), 10).unwrap(),
-        ))
     }
 }
+
 #[derive(Debug)]
 pub struct V5NameSt {
     pub ntype: u16,
@@ -179,6 +185,8 @@ pub struct V5NameSt {
     pub nifd: u16,
     pub npos: u32,
     pub rest: String,
+    /// The record as read.
+    pub raw: Vec<u8>,
 }
 #[derive(Debug)]
 
@@ -201,6 +209,8 @@ pub struct V8NameSt {
     pub rest: String,
     pub nlname: U8Array<32>,
     pub lablen: u16,
+    /// The record as read.
+    pub raw: Vec<u8>,
 }
 
 impl FromBytes for DocumentBase {
@@ -225,13 +235,13 @@ impl FromBytes for DocumentBase {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColumnType {
     NUMERIC = 1,
     CHAR = 2,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ColumnMeta {
     pub column_type: ColumnType,
     pub length: u16,
@@ -240,10 +250,22 @@ pub struct ColumnMeta {
     pub label: String,
     pub format: (String, u16, u16),
     pub in_format: (String, u16, u16),
+    /// Byte offset in the observation, `npos`, as read. The writer lays
+    /// columns out again in order.
+    pub position: u32,
+    /// Format justification, `nfj`: 0 left, 1 right.
+    pub justification: u16,
+    /// Name hash, `nhfun`, which SAS always writes as 0.
+    pub name_hash: u16,
+    /// The NAMESTR record the column was read from, as many bytes as
+    /// `DocumentMeta::member_meta_length`; empty for columns made up in code.
+    pub namestr: Vec<u8>,
 }
 
 pub type StringDecoder = fn(&[u8]) -> Result<String, XPTError>;
 
+pub type StringEncoder = fn(&str) -> Result<Vec<u8>, XPTError>;
+
 impl ColumnMeta {
     pub fn from_v5(name_st: &V5NameSt, decode: StringDecoder) -> Result<Self, XPTError> {
         Ok(Self {
@@ -258,6 +280,10 @@ impl ColumnMeta {
             label: decode(&name_st.nlabel.inner)?,
             format: (decode(&name_st.nform.inner)?, name_st.nfl, name_st.nfd),
             in_format: (decode(&name_st.niform.inner)?, name_st.nifl, name_st.nifd),
+            position: name_st.npos,
+            justification: name_st.nfj,
+            name_hash: name_st.nhfun,
+            namestr: name_st.raw.clone(),
         })
     }
 
@@ -274,19 +300,110 @@ impl ColumnMeta {
             label: decode(&name_st.nlabel.inner)?,
             format: (decode(&name_st.nform.inner)?, name_st.nfl, name_st.nfd),
             in_format: (decode(&name_st.niform.inner)?, name_st.nifl, name_st.nifd),
+            position: name_st.npos,
+            justification: name_st.nfj,
+            name_hash: name_st.nhfun,
+            namestr: name_st.raw.clone(),
         })
     }
 }
 
-#[derive(Debug)]
+/// Splits a format written out, such as `E8601DA10.` or `8.2`, into its
+/// name, width and decimals.
+pub fn parse_format(format: &str) -> Result<(String, u16, u16), XPTError> {
+    let format = format.trim().to_ascii_uppercase();
+    if format.is_empty() {
+        return Ok((String::new(), 0, 0));
+    }
+    let fail = || XPTError::ParseError(format!("bad format {:?}", format));
+    let name_end = format
+        .rfind(|c: char| c.is_ascii_alphabetic() || c == '$' || c == '_')
+        .map_or(0, |i| i + 1);
+    let (name, rest) = format.split_at(name_end);
+    let (width, decimals) = rest.split_once('.').ok_or_else(fail)?;
+    let number = |str: &str| -> Result<u16, XPTError> {
+        if str.is_empty() {
+            Ok(0)
+        } else {
+            str.parse().map_err(|_| fail())
+        }
+    };
+    Ok((name.to_string(), number(width)?, number(decimals)?))
+}
+
+#[derive(Debug, Clone)]
 pub struct DocumentMeta {
     pub version: DocumentHeader,
+    /// SAS version and operating system of the library header.
     pub doc_version: String,
     pub operation_system: String,
+    /// Created and modified times of the library header, `ddMMMyy:hh:mm:ss`.
     pub doc_update_time: String,
-    pub dataset_name: String,
     pub lib_update_time: String,
+    /// Member name, from the member descriptor records.
+    pub dataset_name: String,
+    /// Dataset label, such as `Laboratory Test Results`.
+    pub dataset_label: String,
+    /// Dataset type, usually blank or `DATA`.
+    pub dataset_type: String,
+    /// SAS version and operating system that wrote the member.
+    pub dataset_version: String,
+    pub dataset_operation_system: String,
+    /// Created and modified times of the member.
+    pub dataset_created: String,
+    pub dataset_modified: String,
+    /// NAMESTR size the member header declares: 140, or 136 as VAX/VMS
+    /// writes it.
     pub member_meta_length: u16,
+    /// Library name of the library header, `SASLIB` as SAS writes it.
     pub library: String,
     pub columns: Vec<ColumnMeta>,
+    /// Guess made when the reader was asked to detect the encoding.
+    pub encoding: Option<EncodingGuess>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::part::{V5MemberTitleHeader, V5NameStrTitleHeader, V8ExtensionHeader, XptHeader};
+
+    #[test]
+    fn test_header_numbers() {
+        let record =
+            |title: &str, body: &str| format!("HEADER RECORD*******{:!<28}{:<32}", title, body);
+        let namestr = record("NAMESTR HEADER RECORD", "000000000900000000000000000000");
+        assert_eq!(
+            V5NameStrTitleHeader::new(namestr.as_bytes().try_into().unwrap())
+                .unwrap()
+                .0,
+            9
+        );
+        let namestr = record("NAMESTR HEADER RECORD", "0000000X0900000000000000000000");
+        assert!(V5NameStrTitleHeader::new(namestr.as_bytes().try_into().unwrap()).is_err());
+        let member = record("MEMBER  HEADER RECORD", "000000000000000001600000000140");
+        assert_eq!(
+            V5MemberTitleHeader::new(member.as_bytes().try_into().unwrap())
+                .unwrap()
+                .0,
+            140
+        );
+        let member = record("MEMBER  HEADER RECORD", "0000000000000000016000000001a0");
+        assert!(V5MemberTitleHeader::new(member.as_bytes().try_into().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_extension_header() {
+        let header = |title: &str, count: &str| {
+            let record = format!("HEADER RECORD*******{:!<28}{:<32}", title, count);
+            V8ExtensionHeader::new(record.as_bytes().try_into().unwrap())
+        };
+        assert_eq!(
+            header("LABELV8 HEADER RECORD", "3").unwrap(),
+            V8ExtensionHeader::LabelV8(3)
+        );
+        assert!(header("LABELV9 HEADER RECORD", "70000").is_err());
+        assert_eq!(
+            header("OBSV8   HEADER RECORD", "70000").unwrap(),
+            V8ExtensionHeader::Observations(70000)
+        );
+    }
 }
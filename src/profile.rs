@@ -0,0 +1,567 @@
+use crate::error::XPTError;
+use crate::number::byte2number;
+use crate::part::{ColumnType, DocumentMeta, StringDecoder};
+use crate::reader::Reader;
+use crate::view::RowView;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct ProfileOptions {
+    /// Probabilities of the NUMERIC quantiles, between 0 and 1.
+    pub quantiles: Vec<f64>,
+    /// Compute the quantiles exactly rather than estimate them with the P²
+    /// algorithm. Exact quantiles keep every value, eight bytes per NUMERIC
+    /// value, so memory grows with the file.
+    pub exact_quantiles: bool,
+    /// Most frequent CHAR values kept per column.
+    pub top_n: usize,
+    /// Distinct CHAR values counted per column before counting stops, which
+    /// bounds a column to about `max_distinct` times its length plus 64
+    /// bytes.
+    pub max_distinct: usize,
+}
+
+impl Default for ProfileOptions {
+    fn default() -> Self {
+        ProfileOptions {
+            quantiles: vec![0.25, 0.5, 0.75],
+            exact_quantiles: false,
+            top_n: 10,
+            max_distinct: 100_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NumericStats {
+    /// Missing values by code: `.`, `_` or `A` to `Z`.
+    pub missing_codes: BTreeMap<char, usize>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// Sample standard deviation, `None` below two values.
+    pub std: Option<f64>,
+    /// `(probability, value)` pairs, following SAS's default `PCTLDEF=5`
+    /// when exact, P² estimates otherwise.
+    pub quantiles: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CharStats {
+    pub distinct: usize,
+    /// False when `max_distinct` was reached and `distinct` is a lower bound.
+    pub distinct_exact: bool,
+    /// Most frequent non-blank values, most frequent first.
+    pub top: Vec<(String, usize)>,
+    /// Longest value in bytes, trailing blanks removed.
+    pub max_length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileStats {
+    Numeric(NumericStats),
+    Char(CharStats),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub length: u16,
+    pub rows: usize,
+    /// Rows with a value: not missing, or not blank for CHAR columns.
+    pub count: usize,
+    pub missing: usize,
+    pub stats: ProfileStats,
+}
+
+/// Estimates one quantile in constant memory with the P² algorithm of Jain
+/// and Chlamtac, which moves five markers towards the minimum, the quantile,
+/// the maximum and the midpoints between them.
+#[derive(Debug, Clone)]
+struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    fn new(p: f64) -> Self {
+        P2Quantile {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + 2.0 * p, 4.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+        let q = &mut self.heights;
+        let k = if value < q[0] {
+            q[0] = value;
+            0
+        } else if value >= q[4] {
+            q[4] = value;
+            3
+        } else {
+            (0..4).rfind(|&i| q[i] <= value).unwrap_or(0)
+        };
+        for position in &mut self.positions[k + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+        let n = &mut self.positions;
+        for i in 1..4 {
+            let d = self.desired[i] - n[i];
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = q[i]
+                    + d / (n[i + 1] - n[i - 1])
+                        * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                            + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+                q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    let j = if d > 0.0 { i + 1 } else { i - 1 };
+                    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+                };
+                n[i] += d;
+            }
+        }
+    }
+
+    fn estimate(&self) -> Option<f64> {
+        if self.count < 5 {
+            let mut values = self.heights[..self.count].to_vec();
+            values.sort_by(f64::total_cmp);
+            return quantile(&values, self.p);
+        }
+        Some(match self.p {
+            p if p <= 0.0 => self.heights[0],
+            p if p >= 1.0 => self.heights[4],
+            _ => self.heights[2],
+        })
+    }
+}
+
+enum Accumulator {
+    Numeric {
+        missing_codes: BTreeMap<char, usize>,
+        count: usize,
+        min: f64,
+        max: f64,
+        mean: f64,
+        m2: f64,
+        /// Every value, when the quantiles are exact.
+        values: Vec<f64>,
+        sketches: Vec<P2Quantile>,
+    },
+    Char {
+        counts: HashMap<Vec<u8>, usize>,
+        overflow: bool,
+        max_length: usize,
+        blank: usize,
+    },
+}
+
+/// Computes per-column statistics in a single pass over the rows, in memory
+/// bounded by the number of columns and `max_distinct`, unless
+/// [`ProfileOptions::exact_quantiles`] keeps every NUMERIC value.
+pub struct Profiler {
+    names: Vec<(String, u16)>,
+    accumulators: Vec<Accumulator>,
+    rows: usize,
+    options: ProfileOptions,
+    string_decoder: StringDecoder,
+}
+
+impl Profiler {
+    pub fn new(
+        meta: &DocumentMeta,
+        string_decoder: StringDecoder,
+        options: &ProfileOptions,
+    ) -> Self {
+        Profiler {
+            names: meta
+                .columns
+                .iter()
+                .map(|c| (c.name.clone(), c.length))
+                .collect(),
+            accumulators: meta
+                .columns
+                .iter()
+                .map(|c| match c.column_type {
+                    ColumnType::NUMERIC => Accumulator::Numeric {
+                        missing_codes: BTreeMap::new(),
+                        count: 0,
+                        min: f64::INFINITY,
+                        max: f64::NEG_INFINITY,
+                        mean: 0.0,
+                        m2: 0.0,
+                        values: Vec::new(),
+                        sketches: if options.exact_quantiles {
+                            Vec::new()
+                        } else {
+                            options
+                                .quantiles
+                                .iter()
+                                .map(|p| P2Quantile::new(*p))
+                                .collect()
+                        },
+                    },
+                    ColumnType::CHAR => Accumulator::Char {
+                        counts: HashMap::new(),
+                        overflow: false,
+                        max_length: 0,
+                        blank: 0,
+                    },
+                })
+                .collect(),
+            rows: 0,
+            options: options.clone(),
+            string_decoder,
+        }
+    }
+
+    pub fn check_row(&mut self, row: &RowView<'_>) {
+        self.rows += 1;
+        for (i, accumulator) in self.accumulators.iter_mut().enumerate() {
+            let bytes = row.get_bytes(i);
+            match accumulator {
+                Accumulator::Numeric {
+                    missing_codes,
+                    count,
+                    min,
+                    max,
+                    mean,
+                    m2,
+                    values,
+                    sketches,
+                } => match byte2number(bytes) {
+                    Some(value) => {
+                        // Welford's update keeps the variance stable
+                        *count += 1;
+                        *min = min.min(value);
+                        *max = max.max(value);
+                        let delta = value - *mean;
+                        *mean += delta / *count as f64;
+                        *m2 += delta * (value - *mean);
+                        if self.options.exact_quantiles {
+                            values.push(value);
+                        }
+                        for sketch in sketches.iter_mut() {
+                            sketch.add(value);
+                        }
+                    }
+                    None => *missing_codes.entry(bytes[0] as char).or_default() += 1,
+                },
+                Accumulator::Char {
+                    counts,
+                    overflow,
+                    max_length,
+                    blank,
+                } => {
+                    let length = bytes.iter().rposition(|b| *b != b' ').map_or(0, |i| i + 1);
+                    if length == 0 {
+                        *blank += 1;
+                        continue;
+                    }
+                    *max_length = (*max_length).max(length);
+                    let value = &bytes[..length];
+                    if let Some(count) = counts.get_mut(value) {
+                        *count += 1;
+                    } else if counts.len() < self.options.max_distinct {
+                        counts.insert(value.to_vec(), 1);
+                    } else {
+                        *overflow = true;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<ColumnProfile> {
+        let rows = self.rows;
+        let options = self.options;
+        let decoder = self.string_decoder;
+        self.names
+            .into_iter()
+            .zip(self.accumulators)
+            .map(|((name, length), accumulator)| match accumulator {
+                Accumulator::Numeric {
+                    missing_codes,
+                    count: n,
+                    min,
+                    max,
+                    mean,
+                    m2,
+                    mut values,
+                    sketches,
+                } => {
+                    let quantiles = if options.exact_quantiles {
+                        values.sort_by(f64::total_cmp);
+                        options
+                            .quantiles
+                            .iter()
+                            .filter_map(|p| Some((*p, quantile(&values, *p)?)))
+                            .collect()
+                    } else {
+                        sketches
+                            .iter()
+                            .filter_map(|sketch| Some((sketch.p, sketch.estimate()?)))
+                            .collect()
+                    };
+                    ColumnProfile {
+                        name,
+                        length,
+                        rows,
+                        count: n,
+                        missing: rows - n,
+                        stats: ProfileStats::Numeric(NumericStats {
+                            missing_codes,
+                            min: (n > 0).then_some(min),
+                            max: (n > 0).then_some(max),
+                            mean: (n > 0).then_some(mean),
+                            std: (n > 1).then(|| (m2 / (n - 1) as f64).sqrt()),
+                            quantiles,
+                        }),
+                    }
+                }
+                Accumulator::Char {
+                    counts,
+                    overflow,
+                    max_length,
+                    blank,
+                } => {
+                    let mut top: Vec<_> = counts.iter().collect();
+                    top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+                    ColumnProfile {
+                        name,
+                        length,
+                        rows,
+                        count: rows - blank,
+                        missing: blank,
+                        stats: ProfileStats::Char(CharStats {
+                            distinct: counts.len(),
+                            distinct_exact: !overflow,
+                            top: top
+                                .into_iter()
+                                .take(options.top_n)
+                                .map(|(value, count)| {
+                                    let value = decoder(value).unwrap_or_else(|_| {
+                                        String::from_utf8_lossy(value).into_owned()
+                                    });
+                                    (value, *count)
+                                })
+                                .collect(),
+                            max_length,
+                        }),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Quantile `p` of sorted `values` by SAS's `PCTLDEF=5`: the mean of the two
+/// middle order statistics when `n * p` is whole, the next one above otherwise.
+pub fn quantile(values: &[f64], p: f64) -> Option<f64> {
+    let n = values.len();
+    if n == 0 || !(0.0..=1.0).contains(&p) {
+        return None;
+    }
+    let np = n as f64 * p;
+    let j = np.floor() as usize;
+    Some(if np == np.floor() {
+        if j == 0 {
+            values[0]
+        } else if j >= n {
+            values[n - 1]
+        } else {
+            (values[j - 1] + values[j]) / 2.0
+        }
+    } else {
+        values[j.min(n - 1)]
+    })
+}
+
+impl Display for ColumnProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let optional = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+        match &self.stats {
+            ProfileStats::Numeric(stats) => {
+                writeln!(f, "{} (Num {})", self.name, self.length)?;
+                writeln!(f, "  count     {}", self.count)?;
+                write!(f, "  missing   {}", self.missing)?;
+                if !stats.missing_codes.is_empty() {
+                    let codes: Vec<_> = stats
+                        .missing_codes
+                        .iter()
+                        .map(|(code, count)| {
+                            let code = if *code == '.' {
+                                String::new()
+                            } else {
+                                code.to_string()
+                            };
+                            format!(".{}={}", code, count)
+                        })
+                        .collect();
+                    write!(f, " ({})", codes.join(" "))?;
+                }
+                writeln!(f)?;
+                writeln!(f, "  min       {}", optional(stats.min))?;
+                writeln!(f, "  max       {}", optional(stats.max))?;
+                writeln!(f, "  mean      {}", optional(stats.mean))?;
+                writeln!(f, "  std       {}", optional(stats.std))?;
+                for (p, value) in &stats.quantiles {
+                    writeln!(f, "  {:<9} {}", format!("p{}", p * 100.0), value)?;
+                }
+            }
+            ProfileStats::Char(stats) => {
+                writeln!(f, "{} (Char {})", self.name, self.length)?;
+                writeln!(f, "  count     {}", self.count)?;
+                writeln!(f, "  blank     {}", self.missing)?;
+                writeln!(
+                    f,
+                    "  distinct  {}{}",
+                    stats.distinct,
+                    if stats.distinct_exact { "" } else { "+" }
+                )?;
+                writeln!(f, "  max len   {}", stats.max_length)?;
+                for (value, count) in &stats.top {
+                    writeln!(f, "  {:>8}  {}", count, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Profiles every column of the file at `path`.
+#[cfg(not(feature = "async"))]
+pub fn profile_file<P: AsRef<Path>>(
+    path: P,
+    string_decoder: StringDecoder,
+    options: &ProfileOptions,
+) -> Result<Vec<ColumnProfile>, XPTError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut profiler = Profiler::new(&meta, data.string_decoder(), options);
+    while let Some(row) = data.read_row()? {
+        profiler.check_row(&row);
+    }
+    Ok(profiler.finish())
+}
+
+/// Profiles every column of the file at `path`.
+#[cfg(feature = "async")]
+pub async fn profile_file<P: AsRef<Path>>(
+    path: P,
+    string_decoder: StringDecoder,
+    options: &ProfileOptions,
+) -> Result<Vec<ColumnProfile>, XPTError> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut profiler = Profiler::new(&meta, data.string_decoder(), options);
+    while let Some(row) = data.read_row().await? {
+        profiler.check_row(&row);
+    }
+    Ok(profiler.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::profile::{quantile, P2Quantile};
+
+    #[test]
+    fn test_quantile() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(quantile(&values, 0.5), Some(2.5));
+        assert_eq!(quantile(&values, 0.25), Some(1.5));
+        assert_eq!(quantile(&values, 0.3), Some(2.0));
+        assert_eq!(quantile(&values, 0.0), Some(1.0));
+        assert_eq!(quantile(&values, 1.0), Some(4.0));
+        assert_eq!(quantile(&[7.0], 0.5), Some(7.0));
+        assert_eq!(quantile(&[], 0.5), None);
+    }
+
+    #[test]
+    fn test_p2_quantile() {
+        // 1 to 10000 in a scrambled order
+        let values: Vec<f64> = (0..10_000u64)
+            .map(|i| (i * 7919 % 10_000 + 1) as f64)
+            .collect();
+        for p in [0.1, 0.5, 0.9] {
+            let mut sketch = P2Quantile::new(p);
+            for value in &values {
+                sketch.add(*value);
+            }
+            let estimate = sketch.estimate().unwrap();
+            assert!(
+                (estimate - p * 10_000.0).abs() < 100.0,
+                "p{} is {}",
+                p,
+                estimate
+            );
+        }
+        let mut few = P2Quantile::new(0.5);
+        for value in [4.0, 1.0, 3.0, 2.0] {
+            few.add(value);
+        }
+        assert_eq!(few.estimate(), Some(2.5));
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_profile_file() {
+        use crate::profile::{profile_file, ProfileOptions, ProfileStats};
+        use crate::reader::UTF8_STRING_DECODER;
+
+        let options = ProfileOptions {
+            exact_quantiles: true,
+            ..ProfileOptions::default()
+        };
+        let profiles = profile_file("sample/LB2.xpt", UTF8_STRING_DECODER, &options).unwrap();
+        assert_eq!(profiles.len(), 9);
+        let stresn = &profiles[7];
+        assert_eq!((stresn.count, stresn.missing), (8, 1));
+        let ProfileStats::Numeric(stats) = &stresn.stats else {
+            panic!("LBSTRESN is numeric");
+        };
+        assert_eq!(stats.missing_codes.get(&'.'), Some(&1));
+        assert_eq!(stats.min, Some(0.06));
+        assert_eq!(stats.max, Some(35.0));
+        assert!((stats.mean.unwrap() - 120.56 / 8.0).abs() < 1e-9);
+        assert_eq!(stats.quantiles[1], (0.5, (7.0 + 18.0) / 2.0));
+
+        let testcd = &profiles[4];
+        let ProfileStats::Char(stats) = &testcd.stats else {
+            panic!("LBTESTCD is char");
+        };
+        assert_eq!(stats.distinct, 3);
+        assert_eq!(stats.max_length, 4);
+        assert_eq!(stats.top[0], ("ALT".to_string(), 3));
+        let orres = &profiles[6];
+        assert_eq!(orres.missing, 1);
+        assert!(testcd.to_string().starts_with("LBTESTCD (Char 8)"));
+    }
+}
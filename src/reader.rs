@@ -1,16 +1,20 @@
-use crate::deserialize::{BufferFromByteArray, FromBytes};
-use crate::deserialize_in_order;
+use crate::batch::Batch;
+use crate::define::Overlay;
+use crate::deserialize::FromBytes;
+use crate::detect::{EncodingDetector, EncodingGuess};
 use crate::error::XPTError;
+use crate::filter::Filter;
+use crate::number::byte2number;
 use crate::part::{
-    ColumnMeta, DocumentBase, DocumentHeader, DocumentMeta, StringDecoder, V5MemberTitleHeader,
-    V5NameSt, V5NameStrTitleHeader, V8LabelStrTitleHeader, V8MemberTitleHeader, V8NameSt,
-    V8NameStrTitleHeader, V8ObsHeaderRecord,
+    parse_format, ColumnMeta, ColumnType, DocumentBase, DocumentHeader, DocumentMeta,
+    StringDecoder, V5MemberTitleHeader, V5NameSt, V5NameStrTitleHeader, V8ExtensionHeader,
+    V8MemberTitleHeader, V8NameSt, V8NameStrTitleHeader, XptHeader,
 };
+use crate::view::RowView;
 #[cfg(feature = "multi_encoding")]
-use encoding::all::GBK;
+use encoding::all::{GBK, WINDOWS_31J};
 #[cfg(feature = "multi_encoding")]
 use encoding::{DecoderTrap, Encoding};
-use std::collections::HashMap;
 use std::fmt::Display;
 #[cfg(not(feature = "async"))]
 use {std::cell::RefCell, std::io::Read, std::rc::Rc};
@@ -22,75 +26,123 @@ pub(crate) struct ReaderWrap<'a> {
     reader: &'a mut dyn Read,
     #[cfg(feature = "async")]
     reader: &'a mut (dyn tokio::io::AsyncRead + Unpin + Send),
+    pending: Vec<u8>,
+    pending_offset: usize,
 }
 
 impl<'a> ReaderWrap<'a> {
     #[cfg(not(feature = "async"))]
     pub fn new(reader: &'a mut dyn Read) -> Self {
-        ReaderWrap { reader }
+        ReaderWrap {
+            reader,
+            pending: Vec::new(),
+            pending_offset: 0,
+        }
     }
     #[cfg(feature = "async")]
     pub fn new(reader: &'a mut (dyn tokio::io::AsyncRead + Unpin + Send)) -> Self {
-        ReaderWrap { reader }
+        ReaderWrap {
+            reader,
+            pending: Vec::new(),
+            pending_offset: 0,
+        }
+    }
+
+    /// Pushes bytes back so that the next reads return them first.
+    pub fn unread(&mut self, mut bytes: Vec<u8>) {
+        bytes.extend_from_slice(&self.pending[self.pending_offset..]);
+        self.pending = bytes;
+        self.pending_offset = 0;
     }
 
+    /// Number of bytes left to read, counted up to `limit` and pushed back.
     #[cfg(not(feature = "async"))]
-    pub fn read<T: FromBytes>(&mut self, length: usize) -> T {
-        let mut vec = vec![0; length];
-        self.read2(vec.as_mut_slice())
+    pub fn peek_length(&mut self, limit: usize) -> std::io::Result<usize> {
+        let mut bytes = vec![0; limit];
+        let read = self.read2bytes(&mut bytes)?;
+        bytes.truncate(read);
+        self.unread(bytes);
+        Ok(read)
+    }
+
+    /// Number of bytes left to read, counted up to `limit` and pushed back.
+    #[cfg(feature = "async")]
+    pub async fn peek_length(&mut self, limit: usize) -> std::io::Result<usize> {
+        let mut bytes = vec![0; limit];
+        let read = self.read2bytes(&mut bytes).await?;
+        bytes.truncate(read);
+        self.unread(bytes);
+        Ok(read)
+    }
+
+    fn take_pending(&mut self, tmp: &mut [u8]) -> usize {
+        let count = tmp.len().min(self.pending.len() - self.pending_offset);
+        tmp[..count]
+            .copy_from_slice(&self.pending[self.pending_offset..self.pending_offset + count]);
+        self.pending_offset += count;
+        count
     }
 
     #[cfg(not(feature = "async"))]
-    pub fn read2<T: FromBytes>(&mut self, tmp: &mut [u8]) -> T {
-        let _ = &self.reader.read_exact(tmp).unwrap();
-        T::from_bytes(tmp)
+    pub fn read2<T: FromBytes>(&mut self, tmp: &mut [u8]) -> std::io::Result<T> {
+        self.read_exact(tmp)?;
+        Ok(T::from_bytes(tmp))
     }
 
     #[cfg(not(feature = "async"))]
     pub fn read_exact(&mut self, tmp: &mut [u8]) -> std::io::Result<()> {
-        self.reader.read_exact(tmp)
+        let count = self.take_pending(tmp);
+        self.reader.read_exact(&mut tmp[count..])
     }
 
     #[cfg(not(feature = "async"))]
     pub fn read2bytes(&mut self, tmp: &mut [u8]) -> std::io::Result<usize> {
-        self.reader.read(tmp)
+        let mut filled = self.take_pending(tmp);
+        while filled < tmp.len() {
+            let count = self.reader.read(&mut tmp[filled..])?;
+            if count == 0 {
+                break;
+            }
+            filled += count;
+        }
+        Ok(filled)
     }
 
     #[cfg(not(feature = "async"))]
-    pub fn skip(&mut self, length: usize) {
-        self.reader
-            .read_exact(vec![0; length].as_mut_slice())
-            .unwrap();
+    pub fn skip(&mut self, length: usize) -> std::io::Result<()> {
+        self.read_exact(vec![0; length].as_mut_slice())
     }
 
     #[cfg(feature = "async")]
-    pub async fn read<T: FromBytes>(&mut self, length: usize) -> T {
-        let mut vec = vec![0; length];
-        self.read2(vec.as_mut_slice()).await
-    }
-
-    #[cfg(feature = "async")]
-    pub async fn read2<T: FromBytes>(&mut self, tmp: &mut [u8]) -> T {
-        let _ = &self.reader.read_exact(tmp).await.unwrap();
-        T::from_bytes(tmp)
+    pub async fn read2<T: FromBytes>(&mut self, tmp: &mut [u8]) -> std::io::Result<T> {
+        self.read_exact(tmp).await?;
+        Ok(T::from_bytes(tmp))
     }
 
     #[cfg(feature = "async")]
     pub async fn read_exact(&mut self, tmp: &mut [u8]) -> std::io::Result<usize> {
-        self.reader.read_exact(tmp).await
+        let count = self.take_pending(tmp);
+        self.reader.read_exact(&mut tmp[count..]).await?;
+        Ok(tmp.len())
     }
 
     #[cfg(feature = "async")]
     pub async fn read2bytes(&mut self, tmp: &mut [u8]) -> std::io::Result<usize> {
-        self.reader.read(tmp).await
+        let mut filled = self.take_pending(tmp);
+        while filled < tmp.len() {
+            let count = self.reader.read(&mut tmp[filled..]).await?;
+            if count == 0 {
+                break;
+            }
+            filled += count;
+        }
+        Ok(filled)
     }
 
     #[cfg(feature = "async")]
-    pub async fn skip(&mut self, length: usize) {
-        self.reader
-            .read_exact(vec![0; length].as_mut_slice())
-            .await
-            .unwrap();
+    pub async fn skip(&mut self, length: usize) -> std::io::Result<()> {
+        self.read_exact(vec![0; length].as_mut_slice()).await?;
+        Ok(())
     }
 }
 
@@ -105,116 +157,73 @@ pub struct RawReader<'a> {
     v5_name_sts: Vec<(u32, u16, u16)>,
     string_decoder: StringDecoder,
     observations: usize,
+    char_mode: CharMode,
+    lossy_decoder: StringDecoder,
+    malformed: Vec<usize>,
+    overlay: Option<Overlay>,
+    out_of_codelist: Vec<usize>,
+    names: Vec<String>,
+    filter: Option<Filter>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Val {
     Number(f64),
     Char(String),
     Nil,
 }
 
-fn byte2number(bytearray: &[u8]) -> Option<f64> {
-    let mut array: Vec<u8> = vec![0; 8];
-    for i in 0..bytearray.len() {
-        array[i] = bytearray[i]
-    }
-    let val = i64::from_be_bytes(array[0..8].try_into().unwrap());
-    let mut mantissa = val & 0x00ffffffffffffff;
-    if mantissa == 0 {
-        return if array[0] == 0x00 {
-            Some(0f64)
-        } else if array[0] == 0x8 {
-            Some(-0f64)
-        } else if array[0] == 46 {
-            None
-        } else {
-            match array[0] {
-                b'A' | b'B' | b'C' | b'D' | b'E' | b'F' | b'G' | b'H' | b'I' | b'J' | b'K'
-                | b'L' | b'M' | b'N' | b'O' | b'P' | b'Q' | b'R' | b'S' | b'T' | b'U' | b'V'
-                | b'W' | b'X' | b'Y' | b'Z' | b'_' => None,
-                _ => {
-                    panic!("Zero Mantissa Value was not readable");
-                }
-            }
-        };
-    }
-
-    let sign = val & (0x8000000000000000u64 as i64);
-    let mut exponent = (val & 0x7f00000000000000) >> 56;
-    let shift;
-
-    if (val & 0x0080000000000000) > 0 {
-        shift = 3;
-    } else if (val & 0x0040000000000000) > 0 {
-        shift = 2;
-    } else if (val & 0x0020000000000000) > 0 {
-        shift = 1;
-    } else {
-        shift = 0;
-    }
-
-    mantissa = mantissa >> shift;
-    mantissa = mantissa & (0xffefffffffffffffu64 as i64);
-    exponent -= 65;
-    exponent <<= 2;
-    exponent += shift + 1023;
-    let ieee = sign | (exponent << 52) | mantissa;
-    Some(f64::from_be_bytes(ieee.to_be_bytes()))
+/// A cell as stored in the file, before any CHAR decoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawVal {
+    Number(f64),
+    Char(Vec<u8>),
+    Nil,
 }
 
-fn number2Byte(option: Option<f64>) -> [u8; 8] {
-    // Python uses IEEE: sign * 1.mantissa * 2 ** (exponent - 1023)
-    // IBM mainframe: sign * 0.mantissa * 16 ** (exponent - 64)
-
-    if option.is_none() {
-        return [0; 8];
-    }
-    let ieee = option.unwrap();
-
-    if ieee.is_nan() || ieee == 0.0 {
-        return [0; 8];
+impl RawVal {
+    pub fn decode(&self, decoder: StringDecoder) -> Result<Val, XPTError> {
+        Ok(match self {
+            RawVal::Number(number) => Val::Number(*number),
+            RawVal::Char(bytes) => Val::Char(decoder(bytes)?),
+            RawVal::Nil => Val::Nil,
+        })
     }
+}
 
-    if ieee.is_infinite() {
-        panic!("Cannot convert infinity");
-    }
-
-    let buffer = ieee.to_be_bytes();
-
-    let ulong = i64::from_be_bytes(buffer);
-    let mut sign = (ulong & (1 << 63)) >> 63; // 1-bit sign
-    let mut exponent = ((ulong & (0x7ff << 52)) >> 52) - 1023; // 11-bits exponent
-    let mut mantissa = ulong & 0x000fffffffffffff; // 52-bits mantissa
-
-    if exponent > 248 {
-        panic!("Cannot store magnitude more than ~ 16 ** 63 as IBM-format");
-    }
-    if exponent < -260 {
-        panic!("Cannot store magnitude less than ~ 16 ** -65 as IBM-format");
-    }
-
-    mantissa = 0x0010000000000000 | mantissa;
-
-    let quotient = exponent >> 2;
-    let remainder = exponent - (quotient << 2);
-    exponent = quotient;
-
-    mantissa <<= remainder;
-    exponent += 1;
-    exponent = exponent + 64;
-
-    sign = sign << 63;
-    exponent = exponent << 56;
-
-    // We lose some precision, but who said floats were perfect?
-    let result = sign | exponent | mantissa;
-    let mut result_bytes: [u8; 8] = [0; 8];
+/// How `read_line` treats CHAR values the decoder rejects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CharMode {
+    /// Fail the whole row with the decoder error.
+    #[default]
+    Strict,
+    /// Decode the value again with the lossy decoder, which replaces
+    /// invalid bytes with U+FFFD, and record the cell in
+    /// [`RawReader::malformed_cells`]. The lossy decoder is UTF-8 unless set
+    /// with [`RawReader::set_lossy_decoder`].
+    Lossy,
+}
 
-    for i in 0..8 {
-        result_bytes[i] = ((result as u64) >> (8 * (7 - i))) as u8;
+/// Rows of the `rows` in `lines` left once those that are the blank padding
+/// of the last 80-byte record are dropped, `available` being the bytes from
+/// the start of `lines` to the end of the file. A file without an
+/// observation count ends its data with fewer than 80 blanks, which read as
+/// extra rows when observations are shorter than that.
+pub(crate) fn trim_record_padding(
+    lines: &[u8],
+    rows: usize,
+    line_length: usize,
+    available: usize,
+) -> usize {
+    let mut rows = rows;
+    while rows > 0 && available - (rows - 1) * line_length < 80 {
+        let row = &lines[(rows - 1) * line_length..rows * line_length];
+        if row.iter().any(|b| *b != b' ') {
+            break;
+        }
+        rows -= 1;
     }
-
-    result_bytes
+    rows
 }
 
 impl Display for Val {
@@ -232,67 +241,342 @@ impl Display for Val {
 }
 impl<'a> RawReader<'a> {
     #[cfg(not(feature = "async"))]
-    pub fn read_line(&mut self) -> Result<Option<Vec<Val>>, XPTError> {
-        let mut reader = self.reader.borrow_mut();
-        let left = reader.read2bytes(&mut self.line_str_array)?;
-        if self.observations != 0 && self.line_number > self.observations {
-            return Ok(None);
+    fn next_line(&mut self) -> Result<bool, XPTError> {
+        loop {
+            let mut reader = self.reader.borrow_mut();
+            let left = reader.read2bytes(&mut self.line_str_array)?;
+            if !self.accept_line(left) {
+                return Ok(false);
+            }
+            if self.may_be_padding(&self.line_str_array) {
+                let available = left + reader.peek_length(80)?;
+                let line_length = self.line_length as usize;
+                if trim_record_padding(&self.line_str_array, 1, line_length, available) == 0 {
+                    return Ok(false);
+                }
+            }
+            drop(reader);
+            self.line_number += 1;
+            if self.keep_line()? {
+                return Ok(true);
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn next_line(&mut self) -> Result<bool, XPTError> {
+        loop {
+            let mut reader = self.reader.lock().await;
+            let left = reader.read2bytes(&mut self.line_str_array).await?;
+            if !self.accept_line(left) {
+                return Ok(false);
+            }
+            if self.may_be_padding(&self.line_str_array) {
+                let available = left + reader.peek_length(80).await?;
+                let line_length = self.line_length as usize;
+                if trim_record_padding(&self.line_str_array, 1, line_length, available) == 0 {
+                    return Ok(false);
+                }
+            }
+            drop(reader);
+            self.line_number += 1;
+            if self.keep_line()? {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn accept_line(&self, left: usize) -> bool {
+        if self.observations != 0 && self.line_number >= self.observations {
+            return false;
         }
         if left < self.line_length.try_into().unwrap() {
-            return Ok(None);
+            return false;
         }
-        if self.v5_name_sts.is_empty() {
-            return Ok(None);
+        !self.v5_name_sts.is_empty()
+    }
+
+    /// Whether `lines` could end in the padding of the last record, which
+    /// only a file without an observation count needs to look ahead for.
+    fn may_be_padding(&self, lines: &[u8]) -> bool {
+        self.observations == 0
+            && lines
+                .rchunks_exact(self.line_length as usize)
+                .next()
+                .is_some_and(|row| row.iter().all(|b| *b == b' '))
+    }
+
+    fn keep_line(&self) -> Result<bool, XPTError> {
+        match &self.filter {
+            Some(filter) => filter.matches_row(&self.row_view()),
+            None => Ok(true),
         }
+    }
+
+    fn decode_line(&mut self) -> Result<Vec<Val>, XPTError> {
+        self.malformed.clear();
         let mut vec: Vec<Val> = Vec::with_capacity(self.v5_name_sts.len());
-        for v5_name_st in &self.v5_name_sts {
+        for (i, v5_name_st) in self.v5_name_sts.iter().enumerate() {
             let start: usize = v5_name_st.0 as usize;
             let end: usize = start + (v5_name_st.1 as usize);
+            let bytes = &self.line_str_array[start..end];
             vec.push(if v5_name_st.2 == 1 {
-                if let Some(numer) = byte2number(&self.line_str_array[start..end]) {
+                if let Some(numer) = byte2number(bytes) {
                     Val::Number(numer)
                 } else {
                     Val::Nil
                 }
             } else {
                 let decoder = self.string_decoder;
-                Val::Char(decoder(&self.line_str_array[start..end])?)
+                match (decoder(bytes), &self.char_mode) {
+                    (Ok(str), _) => Val::Char(str),
+                    (Err(_), CharMode::Lossy) => {
+                        self.malformed.push(i);
+                        Val::Char((self.lossy_decoder)(bytes)?)
+                    }
+                    (Err(err), CharMode::Strict) => return Err(err),
+                }
             });
         }
-        self.line_number += 1;
-        Ok(Some(vec))
+        if let Some(overlay) = &self.overlay {
+            self.out_of_codelist = overlay.out_of_codelist(&vec);
+        }
+        Ok(vec)
+    }
+
+    fn raw_line(&self) -> Vec<RawVal> {
+        self.v5_name_sts
+            .iter()
+            .map(|(npos, nlng, ntype)| {
+                let bytes = &self.line_str_array[*npos as usize..*npos as usize + *nlng as usize];
+                if *ntype == 1 {
+                    byte2number(bytes).map_or(RawVal::Nil, RawVal::Number)
+                } else {
+                    RawVal::Char(bytes.to_vec())
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "async"))]
+    pub fn read_line(&mut self) -> Result<Option<Vec<Val>>, XPTError> {
+        if !self.next_line()? {
+            return Ok(None);
+        }
+        self.decode_line().map(Some)
     }
 
     #[cfg(feature = "async")]
     pub async fn read_line(&mut self) -> Result<Option<Vec<Val>>, XPTError> {
-        let mut reader = self.reader.lock().await;
-        let left = reader.read2bytes(&mut self.line_str_array).await?;
-        if self.observations != 0 && self.line_number > self.observations {
+        if !self.next_line().await? {
             return Ok(None);
         }
-        if left < self.line_length.try_into().unwrap() {
+        self.decode_line().map(Some)
+    }
+
+    /// Reads the next row without decoding CHAR cells, keeping their exact bytes.
+    #[cfg(not(feature = "async"))]
+    pub fn read_line_raw(&mut self) -> Result<Option<Vec<RawVal>>, XPTError> {
+        if !self.next_line()? {
             return Ok(None);
         }
-        if self.v5_name_sts.is_empty() {
+        Ok(Some(self.raw_line()))
+    }
+
+    /// Reads the next row without decoding CHAR cells, keeping their exact bytes.
+    #[cfg(feature = "async")]
+    pub async fn read_line_raw(&mut self) -> Result<Option<Vec<RawVal>>, XPTError> {
+        if !self.next_line().await? {
             return Ok(None);
         }
-        let mut vec: Vec<Val> = Vec::with_capacity(self.v5_name_sts.len());
-        for v5_name_st in &self.v5_name_sts {
-            let start: usize = v5_name_st.0 as usize;
-            let end: usize = start + (v5_name_st.1 as usize);
-            vec.push(if v5_name_st.2 == 1 {
-                if let Some(numer) = byte2number(&self.line_str_array[start..end]) {
-                    Val::Number(numer)
-                } else {
-                    Val::Nil
+        Ok(Some(self.raw_line()))
+    }
+
+    /// Reads the next row into the internal buffer and returns a view over
+    /// it. The view must be dropped before the next read.
+    #[cfg(not(feature = "async"))]
+    pub fn read_row(&mut self) -> Result<Option<RowView<'_>>, XPTError> {
+        if !self.next_line()? {
+            return Ok(None);
+        }
+        Ok(Some(self.row_view()))
+    }
+
+    /// Reads the next row into the internal buffer and returns a view over
+    /// it. The view must be dropped before the next read.
+    #[cfg(feature = "async")]
+    pub async fn read_row(&mut self) -> Result<Option<RowView<'_>>, XPTError> {
+        if !self.next_line().await? {
+            return Ok(None);
+        }
+        Ok(Some(self.row_view()))
+    }
+
+    /// Reads up to `rows` observations and returns them column by column,
+    /// `None` once the data is exhausted.
+    #[cfg(not(feature = "async"))]
+    pub fn read_batch(&mut self, rows: usize) -> Result<Option<Batch>, XPTError> {
+        let mut lines = vec![0; rows * self.line_length as usize];
+        loop {
+            let mut reader = self.reader.borrow_mut();
+            let read = reader.read2bytes(&mut lines)?;
+            let mut available = read;
+            if self.may_be_padding(&lines[..read]) {
+                available += reader.peek_length(80)?;
+            }
+            drop(reader);
+            match self.accept_batch(&mut lines, read, available)? {
+                Some(batch) if batch.rows == 0 => continue,
+                batch => return Ok(batch),
+            }
+        }
+    }
+
+    /// Reads up to `rows` observations and returns them column by column,
+    /// `None` once the data is exhausted.
+    #[cfg(feature = "async")]
+    pub async fn read_batch(&mut self, rows: usize) -> Result<Option<Batch>, XPTError> {
+        let mut lines = vec![0; rows * self.line_length as usize];
+        loop {
+            let mut reader = self.reader.lock().await;
+            let read = reader.read2bytes(&mut lines).await?;
+            let mut available = read;
+            if self.may_be_padding(&lines[..read]) {
+                available += reader.peek_length(80).await?;
+            }
+            drop(reader);
+            match self.accept_batch(&mut lines, read, available)? {
+                Some(batch) if batch.rows == 0 => continue,
+                batch => return Ok(batch),
+            }
+        }
+    }
+
+    /// Builds the batch from the lines read, dropping the padding of the last
+    /// record and those the filter rejects; the batch may then be empty
+    /// though data is left. `available` counts the bytes from the start of
+    /// `lines` to the end of the file, or at least 80 past the last line.
+    fn accept_batch(
+        &mut self,
+        lines: &mut [u8],
+        read: usize,
+        available: usize,
+    ) -> Result<Option<Batch>, XPTError> {
+        if self.line_length == 0 || self.v5_name_sts.is_empty() {
+            return Ok(None);
+        }
+        let line_length = self.line_length as usize;
+        let mut rows = read / line_length;
+        if self.observations != 0 {
+            rows = rows.min(self.observations.saturating_sub(self.line_number));
+        } else {
+            rows = trim_record_padding(lines, rows, line_length, available);
+        }
+        if rows == 0 {
+            return Ok(None);
+        }
+        self.line_number += rows;
+        if let Some(filter) = &self.filter {
+            let mut kept = 0;
+            for row in 0..rows {
+                let line = &lines[row * line_length..(row + 1) * line_length];
+                let view = RowView::new(line, &self.v5_name_sts, self.string_decoder);
+                if filter.matches_row(&view)? {
+                    lines.copy_within(
+                        row * line_length..(row + 1) * line_length,
+                        kept * line_length,
+                    );
+                    kept += 1;
                 }
-            } else {
-                let decoder = self.string_decoder;
-                Val::Char(decoder(&self.line_str_array[start..end])?)
-            });
+            }
+            rows = kept;
         }
-        self.line_number += 1;
-        Ok(Some(vec))
+        Ok(Some(Batch::from_lines(
+            lines,
+            rows,
+            line_length,
+            &self.v5_name_sts,
+        )))
+    }
+
+    fn row_view(&self) -> RowView<'_> {
+        RowView::new(&self.line_str_array, &self.v5_name_sts, self.string_decoder)
+    }
+
+    pub fn set_char_mode(&mut self, char_mode: CharMode) {
+        self.char_mode = char_mode;
+    }
+
+    /// Decoder of the values the string decoder rejects in
+    /// [`CharMode::Lossy`], such as [`GBK_LOSSY_STRING_DECODER`] behind a
+    /// strict GBK decoder.
+    pub fn set_lossy_decoder(&mut self, lossy_decoder: StringDecoder) {
+        self.lossy_decoder = lossy_decoder;
+    }
+
+    /// Column indexes of the last row read by `read_line` whose CHAR value
+    /// failed to decode and was replaced in [`CharMode::Lossy`].
+    pub fn malformed_cells(&self) -> &[usize] {
+        &self.malformed
+    }
+
+    /// Documents the columns with their define.xml definitions, after which
+    /// `read_line` checks CHAR and NUMERIC values against their codelists.
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = Some(overlay);
+    }
+
+    pub fn overlay(&self) -> Option<&Overlay> {
+        self.overlay.as_ref()
+    }
+
+    /// Column indexes of the last row read by `read_line` whose value is not
+    /// in the codelist of the overlay.
+    pub fn out_of_codelist(&self) -> &[usize] {
+        &self.out_of_codelist
+    }
+
+    /// Compiles `expression` against the columns, after which reads skip the
+    /// rows it does not match. See [`Filter`] for the syntax.
+    pub fn filter(&mut self, expression: &str) -> Result<(), XPTError> {
+        let filter = Filter::compile(expression, |name| {
+            self.names
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(name))
+                .map(|i| {
+                    let column_type = if self.v5_name_sts[i].2 == 1 {
+                        ColumnType::NUMERIC
+                    } else {
+                        ColumnType::CHAR
+                    };
+                    (i, column_type)
+                })
+        })?;
+        self.filter = Some(filter);
+        Ok(())
+    }
+
+    /// Skips the rows `filter` does not match on every later read.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = Some(filter);
+    }
+
+    pub fn string_decoder(&self) -> StringDecoder {
+        self.string_decoder
+    }
+
+    /// Length in bytes of one observation, the end of the last column.
+    pub fn line_length(&self) -> u32 {
+        self.line_length
+    }
+
+    pub(crate) fn columns(&self) -> &[(u32, u16, u16)] {
+        &self.v5_name_sts
+    }
+
+    /// Observation count of the V8 `OBSV8` header, 0 when unknown.
+    pub(crate) fn observations(&self) -> usize {
+        self.observations
     }
 }
 
@@ -302,8 +586,35 @@ pub struct Reader<'a> {
     #[cfg(feature = "async")]
     reader: Arc<Mutex<ReaderWrap<'a>>>,
     string_decoder: StringDecoder,
+    detection: Option<Detection>,
+}
+
+struct Detection {
+    sample_rows: usize,
+    apply: bool,
 }
 
+/// Rows sampled by [`Reader::new_auto`] when guessing the encoding.
+pub const DEFAULT_DETECTION_ROWS: usize = 100;
+
+pub const UTF8_STRING_DECODER: StringDecoder = |x| {
+    String::from_utf8(x.to_vec())
+        .map_err(|x| XPTError::DecodeError(x.to_string()))
+        .map(|x| x.trim().to_string())
+};
+
+/// Replaces invalid UTF-8 with U+FFFD instead of failing.
+pub const UTF8_LOSSY_STRING_DECODER: StringDecoder =
+    |x| Ok(String::from_utf8_lossy(x).trim().to_string());
+
+pub const LATIN1_STRING_DECODER: StringDecoder = |x| {
+    Ok(x.iter()
+        .map(|b| *b as char)
+        .collect::<String>()
+        .trim()
+        .to_string())
+};
+
 #[cfg(feature = "multi_encoding")]
 pub const GBK_STRING_DECODER: StringDecoder = |x| {
     GBK.decode(x, DecoderTrap::Ignore)
@@ -311,12 +622,208 @@ pub const GBK_STRING_DECODER: StringDecoder = |x| {
         .map(|x| x.trim().to_string())
 };
 
+#[cfg(feature = "multi_encoding")]
+pub const SHIFT_JIS_STRING_DECODER: StringDecoder = |x| {
+    WINDOWS_31J
+        .decode(x, DecoderTrap::Ignore)
+        .map_err(|x| XPTError::DecodeError(x.to_string()))
+        .map(|x| x.trim().to_string())
+};
+
+/// Replaces invalid GBK with U+FFFD instead of failing.
+#[cfg(feature = "multi_encoding")]
+pub const GBK_LOSSY_STRING_DECODER: StringDecoder = |x| {
+    GBK.decode(x, DecoderTrap::Replace)
+        .map_err(|x| XPTError::DecodeError(x.to_string()))
+        .map(|x| x.trim().to_string())
+};
+
+/// Replaces invalid Shift_JIS with U+FFFD instead of failing.
+#[cfg(feature = "multi_encoding")]
+pub const SHIFT_JIS_LOSSY_STRING_DECODER: StringDecoder = |x| {
+    WINDOWS_31J
+        .decode(x, DecoderTrap::Replace)
+        .map_err(|x| XPTError::DecodeError(x.to_string()))
+        .map(|x| x.trim().to_string())
+};
+
+/// A `LABELV8` or `LABELV9` record: the variable number, its full name and
+/// label and, from `LABELV9`, its format and informat written out.
+struct LongRecord {
+    var_number: u16,
+    name: Vec<u8>,
+    label: Vec<u8>,
+    formats: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl LongRecord {
+    /// Length of the lengths leading a record.
+    fn prefix_length(v9: bool) -> usize {
+        if v9 {
+            10
+        } else {
+            6
+        }
+    }
+
+    /// Text lengths given by a record's `prefix`, the variable number first.
+    fn lengths(prefix: &[u8]) -> Vec<u16> {
+        prefix
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    fn new(lengths: &[u16], mut text: Vec<u8>) -> Self {
+        let mut rest = text.split_off(lengths[1] as usize);
+        let name = text;
+        let mut formats = rest.split_off(lengths[2] as usize);
+        let label = rest;
+        LongRecord {
+            var_number: lengths[0],
+            name,
+            label,
+            formats: (lengths.len() == 5).then(|| {
+                let informat = formats.split_off(lengths[3] as usize);
+                (formats, informat)
+            }),
+        }
+    }
+}
+
+/// A format from a `LABELV9` record. Those written without a width keep the
+/// width and decimals of the NAMESTR.
+fn long_format(written: String, short: &(String, u16, u16)) -> (String, u16, u16) {
+    if written.is_empty() {
+        return short.clone();
+    }
+    match parse_format(&written) {
+        Ok((name, 0, 0)) => (name, short.1, short.2),
+        Ok(format) => format,
+        Err(_) => (written, short.1, short.2),
+    }
+}
+
+fn decode_columns(
+    v5_name_sts: &[V5NameSt],
+    v8_name_sts: &[V8NameSt],
+    long_records: &[LongRecord],
+    decoder: StringDecoder,
+) -> Result<Vec<ColumnMeta>, XPTError> {
+    let mut column_meta_array: Vec<ColumnMeta> = v5_name_sts
+        .iter()
+        .map(|name_st| ColumnMeta::from_v5(name_st, decoder))
+        .chain(
+            v8_name_sts
+                .iter()
+                .map(|name_st| ColumnMeta::from_v8(name_st, decoder)),
+        )
+        .collect::<Result<_, _>>()?;
+    for record in long_records {
+        let column = column_meta_array
+            .iter_mut()
+            .find(|column| column.var_count == record.var_number)
+            .ok_or_else(|| {
+                XPTError::ParseError(format!(
+                    "long label record for unknown variable number {}",
+                    record.var_number
+                ))
+            })?;
+        column.name = decoder(&record.name)?;
+        column.label = decoder(&record.label)?;
+        if let Some((format, informat)) = &record.formats {
+            column.format = long_format(decoder(format)?, &column.format);
+            column.in_format = long_format(decoder(informat)?, &column.in_format);
+        }
+    }
+    Ok(column_meta_array)
+}
+
+/// The two member descriptor records after the `DSCRPTR` header.
+struct MemberDescriptor {
+    dataset_name: String,
+    sas_version: String,
+    operation_system: String,
+    created: String,
+    modified: String,
+    dataset_label: String,
+    dataset_type: String,
+}
+
+impl MemberDescriptor {
+    /// The first record holds `SAS`, the name, `SASDATA`, the version, the
+    /// OS and the created time, V8 widening the name to 32 bytes over the
+    /// blanks before the time. The second holds the modified time, the label
+    /// and the type.
+    fn decode(
+        records: &[u8; 160],
+        version: DocumentHeader,
+        decoder: StringDecoder,
+    ) -> Result<Self, XPTError> {
+        let name_end = match version {
+            DocumentHeader::V5 => 16,
+            DocumentHeader::V8 => 40,
+        };
+        let version_start = name_end + 8;
+        Ok(MemberDescriptor {
+            dataset_name: decoder(&records[8..name_end])?,
+            sas_version: decoder(&records[version_start..version_start + 8])?,
+            operation_system: decoder(&records[version_start + 8..version_start + 16])?,
+            created: decoder(&records[64..80])?,
+            modified: decoder(&records[80..96])?,
+            dataset_label: decoder(&records[112..152])?,
+            dataset_type: decoder(&records[152..160])?,
+        })
+    }
+}
+
+fn sample_text(
+    detector: &mut EncodingDetector,
+    v5_name_sts: &[V5NameSt],
+    v8_name_sts: &[V8NameSt],
+    long_records: &[LongRecord],
+    descriptor: &[u8; 160],
+) {
+    for name_st in v5_name_sts {
+        detector.feed(&name_st.nname.inner);
+        detector.feed(&name_st.nlabel.inner);
+    }
+    for name_st in v8_name_sts {
+        detector.feed(&name_st.nlname.inner);
+        detector.feed(&name_st.nlabel.inner);
+    }
+    for record in long_records {
+        detector.feed(&record.name);
+        detector.feed(&record.label);
+    }
+    detector.feed(&descriptor[112..152]);
+}
+
+fn sample_rows(detector: &mut EncodingDetector, rows: &[u8], positions: &[(u32, u16, u16)]) {
+    let line_length = positions
+        .iter()
+        .map(|(npos, nlng, _)| *npos as usize + *nlng as usize)
+        .max()
+        .unwrap_or(0);
+    if line_length == 0 {
+        return;
+    }
+    for row in rows.chunks_exact(line_length) {
+        for (npos, nlng, ntype) in positions {
+            if *ntype == 2 {
+                detector.feed(&row[*npos as usize..*npos as usize + *nlng as usize]);
+            }
+        }
+    }
+}
+
 impl<'a> Reader<'a> {
     #[cfg(not(feature = "async"))]
     pub fn new(reader: &'a mut dyn Read, string_decoder: StringDecoder) -> Self {
         Reader {
             reader: Rc::new(RefCell::new(ReaderWrap::new(reader))),
             string_decoder,
+            detection: None,
         }
     }
 
@@ -328,6 +835,7 @@ impl<'a> Reader<'a> {
         Reader {
             reader: Arc::new(Mutex::new(ReaderWrap::new(reader))),
             string_decoder,
+            detection: None,
         }
     }
 
@@ -343,131 +851,162 @@ impl<'a> Reader<'a> {
         Self::new(reader, GBK_STRING_DECODER)
     }
 
+    /// Reader that guesses the encoding and falls back to UTF-8 when the
+    /// guessed one is not compiled in.
+    #[cfg(not(feature = "async"))]
+    pub fn new_auto(reader: &'a mut dyn Read) -> Self {
+        Self::new(reader, UTF8_STRING_DECODER).auto_encoding(DEFAULT_DETECTION_ROWS)
+    }
+
+    /// Reader that guesses the encoding and falls back to UTF-8 when the
+    /// guessed one is not compiled in.
+    #[cfg(feature = "async")]
+    pub fn new_auto(reader: &'a mut (dyn tokio::io::AsyncRead + Unpin + Send)) -> Self {
+        Self::new(reader, UTF8_STRING_DECODER).auto_encoding(DEFAULT_DETECTION_ROWS)
+    }
+
+    /// Samples names, labels and the CHAR values of the first `sample_rows`
+    /// rows and reports the guess in [`DocumentMeta::encoding`].
+    pub fn detect_encoding(mut self, sample_rows: usize) -> Self {
+        self.detection = Some(Detection {
+            sample_rows,
+            apply: false,
+        });
+        self
+    }
+
+    /// Like [`Reader::detect_encoding`], but also decodes with the guessed
+    /// encoding instead of the decoder given to the constructor.
+    pub fn auto_encoding(mut self, sample_rows: usize) -> Self {
+        self.detection = Some(Detection {
+            sample_rows,
+            apply: true,
+        });
+        self
+    }
+
+    fn choose_decoder(&self, guess: &EncodingGuess) -> StringDecoder {
+        match (&self.detection, guess.encoding.decoder()) {
+            (Some(detection), Some(decoder)) if detection.apply && !guess.is_ascii() => decoder,
+            _ => self.string_decoder,
+        }
+    }
+
     #[cfg(not(feature = "async"))]
     pub fn start(&mut self) -> Result<(RawReader<'a>, DocumentMeta), XPTError> {
         let mut u80 = [0; 80];
-        #[cfg(not(feature = "async"))]
         let mut reader = self.reader.borrow_mut();
-        #[cfg(feature = "async")]
-        let mut reader = self.reader.lock().unwrap();
-        let document_header = reader
-            .read2::<BufferFromByteArray<DocumentHeader>>(&mut u80)
-            .0;
-        let document_base: DocumentBase = reader.read2(&mut u80);
-        let update_date: String = reader.read2(&mut u80);
+        reader.read_exact(&mut u80)?;
+        let document_header = DocumentHeader::new(&u80)?;
+        let document_base: DocumentBase = reader.read2(&mut u80)?;
+        let mut update_date = [0; 80];
+        reader.read_exact(&mut update_date)?;
+        reader.read_exact(&mut u80)?;
         let member_title_header = match document_header {
-            DocumentHeader::V5 => {
-                reader
-                    .read2::<BufferFromByteArray<V5MemberTitleHeader>>(&mut u80)
-                    .0
-                     .0
-            }
-            DocumentHeader::V8 => {
-                reader
-                    .read2::<BufferFromByteArray<V8MemberTitleHeader>>(&mut u80)
-                    .0
-                     .0
-            }
+            DocumentHeader::V5 => V5MemberTitleHeader::new(&u80)?.0,
+            DocumentHeader::V8 => V8MemberTitleHeader::new(&u80)?.0,
         };
-        reader.read2::<String>(&mut u80);
-        let library_base = reader.read2::<DocumentBase>(&mut u80);
-        let library_update_date: String = reader.read2(&mut u80);
+        reader.read_exact(&mut u80)?;
+        let mut descriptor = [0; 160];
+        reader.read_exact(&mut descriptor)?;
+        reader.read_exact(&mut u80)?;
         let str_title_header = match document_header {
-            DocumentHeader::V5 => {
-                reader
-                    .read2::<BufferFromByteArray<V5NameStrTitleHeader>>(&mut u80)
-                    .0
-                     .0
-            }
-            DocumentHeader::V8 => {
-                reader
-                    .read2::<BufferFromByteArray<V8NameStrTitleHeader>>(&mut u80)
-                    .0
-                     .0
-            }
+            DocumentHeader::V5 => V5NameStrTitleHeader::new(&u80)?.0,
+            DocumentHeader::V8 => V8NameStrTitleHeader::new(&u80)?.0,
         };
-        let mut left_blank = member_title_header * str_title_header % 80;
+        // the NAMESTR fields read take 122 bytes; VAX writes 136, others 140
+        if member_title_header < 136 {
+            return Err(XPTError::ParseError(format!(
+                "NAMESTR length {} is too short",
+                member_title_header
+            )));
+        }
+        let left_blank = member_title_header as usize * str_title_header as usize % 80;
         let mut name_str_array = vec![0; member_title_header.into()];
         let mut line_length = 0;
-        let mut column_meta_array: Vec<ColumnMeta> = Vec::with_capacity(str_title_header.into());
+        let mut v5_name_st_array: Vec<V5NameSt> = Vec::new();
+        let mut v8_name_st_array: Vec<V8NameSt> = Vec::new();
+        let mut long_records: Vec<LongRecord> = Vec::new();
         let mut v5_name_sts: Vec<(u32, u16, u16)> = Vec::with_capacity(str_title_header.into());
-        let decoder = self.string_decoder;
         let mut observations: usize = 0;
         match document_header {
             DocumentHeader::V5 => {
-                for i in 0..str_title_header {
-                    let name_st: V5NameSt = reader.read2(&mut name_str_array);
-                    line_length = name_st.npos + ((&name_st).nlng as u32);
-                    column_meta_array.insert(
-                        i.into(),
-                        ColumnMeta::from_v5(&name_st, self.string_decoder)?,
-                    );
-                    v5_name_sts.insert(i.into(), (name_st.npos, name_st.nlng, name_st.ntype));
+                for _i in 0..str_title_header {
+                    let name_st: V5NameSt = reader.read2(&mut name_str_array)?;
+                    line_length = name_st.npos + (name_st.nlng as u32);
+                    v5_name_sts.push((name_st.npos, name_st.nlng, name_st.ntype));
+                    v5_name_st_array.push(name_st);
                 }
                 if left_blank > 0 {
-                    reader.skip((80 - left_blank) as usize);
+                    reader.skip(80 - left_blank)?;
                 }
-                let _obs_header = reader.read2::<String>(&mut u80);
+                reader.read_exact(&mut u80)?;
             }
             DocumentHeader::V8 => {
-                let mut with_long_label = false;
-                for i in 0..str_title_header {
-                    let name_st: V8NameSt = reader.read2(&mut name_str_array);
-                    line_length = name_st.npos + ((&name_st).nlng as u32);
-                    column_meta_array.insert(
-                        i.into(),
-                        ColumnMeta::from_v8(&name_st, self.string_decoder)?,
-                    );
-                    if name_st.lablen > 40 {
-                        with_long_label = true;
-                    }
-                    v5_name_sts.insert(i.into(), (name_st.npos, name_st.nlng, name_st.ntype));
+                for _i in 0..str_title_header {
+                    let name_st: V8NameSt = reader.read2(&mut name_str_array)?;
+                    line_length = name_st.npos + (name_st.nlng as u32);
+                    v5_name_sts.push((name_st.npos, name_st.nlng, name_st.ntype));
+                    v8_name_st_array.push(name_st);
                 }
                 if left_blank > 0 {
-                    reader.skip((80 - left_blank) as usize);
+                    reader.skip(80 - left_blank)?;
                 }
-                //should be handle in v8
-                if with_long_label {
-                    let title_header_count = reader
-                        .read2::<BufferFromByteArray<V8LabelStrTitleHeader>>(&mut u80)
-                        .0
-                         .0;
-                    left_blank = 0;
-                    let mut len_def = [0; 6];
-                    let mut var_map: HashMap<u16, ColumnMeta> = column_meta_array
-                        .into_iter()
-                        .map(|f| (f.var_count, f))
-                        .collect();
-                    for _i in 0..title_header_count {
-                        reader.read_exact(&mut len_def)?;
-                        deserialize_in_order!(
-                            len_def,{
-                                var_number :u16 with 2,
-                                name_len:u16 with 2,
-                                label_len:u16 with 2
-                            }
-                        );
-                        let option = var_map.get_mut(&var_number).unwrap();
-                        let mut vec2 = vec![0; name_len as usize];
-                        reader.read_exact(vec2.as_mut_slice())?;
-                        option.name = decoder(vec2.as_slice())?;
-                        vec2 = vec![0; label_len as usize];
-                        reader.read_exact(vec2.as_mut_slice())?;
-                        option.label = decoder(vec2.as_slice())?;
-                        left_blank = (left_blank + 6 + name_len + label_len) % 80;
+                reader.read_exact(&mut u80)?;
+                let mut extension = V8ExtensionHeader::new(&u80)?;
+                if let V8ExtensionHeader::LabelV8(count) | V8ExtensionHeader::LabelV9(count) =
+                    extension
+                {
+                    let prefix_length =
+                        LongRecord::prefix_length(extension != V8ExtensionHeader::LabelV8(count));
+                    let mut prefix = [0; 10];
+                    let mut record_length = 0;
+                    for _i in 0..count {
+                        reader.read_exact(&mut prefix[..prefix_length])?;
+                        let lengths = LongRecord::lengths(&prefix[..prefix_length]);
+                        let mut text = vec![0; lengths[1..].iter().map(|&l| l as usize).sum()];
+                        reader.read_exact(&mut text)?;
+                        record_length += prefix_length + text.len();
+                        long_records.push(LongRecord::new(&lengths, text));
                     }
-                    if left_blank > 0 {
-                        reader.skip((80 - left_blank) as usize);
+                    if record_length % 80 > 0 {
+                        reader.skip(80 - record_length % 80)?;
                     }
-                    column_meta_array = var_map.into_values().collect();
-                    column_meta_array.sort_by(|a, b| a.var_count.cmp(&b.var_count));
+                    reader.read_exact(&mut u80)?;
+                    extension = V8ExtensionHeader::new(&u80)?;
                 }
-                observations = reader
-                    .read2::<BufferFromByteArray<V8ObsHeaderRecord>>(&mut u80)
-                    .0
-                     .0 as usize;
+                observations = match extension {
+                    V8ExtensionHeader::Observations(observations) => observations as usize,
+                    _ => {
+                        return Err(XPTError::ParseError(
+                            "long label records are not followed by OBSV8".to_string(),
+                        ))
+                    }
+                };
             }
         }
+        let mut encoding = None;
+        if let Some(detection) = &self.detection {
+            let mut detector = EncodingDetector::new();
+            sample_text(
+                &mut detector,
+                &v5_name_st_array,
+                &v8_name_st_array,
+                &long_records,
+                &descriptor,
+            );
+            let mut rows = vec![0; detection.sample_rows * line_length as usize];
+            let read = reader.read2bytes(&mut rows)?;
+            rows.truncate(read);
+            sample_rows(&mut detector, &rows, &v5_name_sts);
+            reader.unread(rows);
+            encoding = Some(detector.guess());
+        }
+        let decoder = encoding
+            .as_ref()
+            .map_or(self.string_decoder, |guess| self.choose_decoder(guess));
+        let columns = decode_columns(&v5_name_st_array, &v8_name_st_array, &long_records, decoder)?;
+        let member = MemberDescriptor::decode(&descriptor, document_header, decoder)?;
         Ok((
             RawReader {
                 reader: self.reader.clone(),
@@ -475,19 +1014,33 @@ impl<'a> Reader<'a> {
                 line_str_array: vec![0; line_length as usize],
                 line_number: 0,
                 v5_name_sts,
-                string_decoder: self.string_decoder,
+                string_decoder: decoder,
                 observations,
+                char_mode: CharMode::Strict,
+                lossy_decoder: UTF8_LOSSY_STRING_DECODER,
+                malformed: Vec::new(),
+                overlay: None,
+                out_of_codelist: Vec::new(),
+                names: columns.iter().map(|c| c.name.clone()).collect(),
+                filter: None,
             },
             DocumentMeta {
                 version: document_header,
                 doc_version: decoder(&document_base.version.inner)?,
                 operation_system: decoder(&document_base.operation_system.inner)?,
-                doc_update_time: update_date,
-                dataset_name: decoder(&library_base.version.inner)?,
-                lib_update_time: library_update_date,
+                doc_update_time: decoder(&document_base.time.inner)?,
+                lib_update_time: decoder(&update_date[..16])?,
+                dataset_name: member.dataset_name,
+                dataset_label: member.dataset_label,
+                dataset_type: member.dataset_type,
+                dataset_version: member.sas_version,
+                dataset_operation_system: member.operation_system,
+                dataset_created: member.created,
+                dataset_modified: member.modified,
                 member_meta_length: member_title_header,
-                library: decoder(&library_base.dataset_name.inner)?,
-                columns: column_meta_array,
+                library: decoder(&document_base.header_type.inner)?,
+                columns,
+                encoding,
             },
         ))
     }
@@ -495,135 +1048,118 @@ impl<'a> Reader<'a> {
     #[cfg(feature = "async")]
     pub async fn start(&mut self) -> Result<(RawReader<'a>, DocumentMeta), XPTError> {
         let mut u80 = [0; 80];
-        #[cfg(not(feature = "async"))]
-        let mut reader = self.reader.borrow_mut();
-        #[cfg(feature = "async")]
         let mut reader = self.reader.lock().await;
-        let document_header = reader
-            .read2::<BufferFromByteArray<DocumentHeader>>(&mut u80)
-            .await
-            .0;
-        let document_base: DocumentBase = reader.read2(&mut u80).await;
-        let update_date: String = reader.read2(&mut u80).await;
+        reader.read_exact(&mut u80).await?;
+        let document_header = DocumentHeader::new(&u80)?;
+        let document_base: DocumentBase = reader.read2(&mut u80).await?;
+        let mut update_date = [0; 80];
+        reader.read_exact(&mut update_date).await?;
+        reader.read_exact(&mut u80).await?;
         let member_title_header = match document_header {
-            DocumentHeader::V5 => {
-                reader
-                    .read2::<BufferFromByteArray<V5MemberTitleHeader>>(&mut u80)
-                    .await
-                    .0
-                     .0
-            }
-            DocumentHeader::V8 => {
-                reader
-                    .read2::<BufferFromByteArray<V8MemberTitleHeader>>(&mut u80)
-                    .await
-                    .0
-                     .0
-            }
+            DocumentHeader::V5 => V5MemberTitleHeader::new(&u80)?.0,
+            DocumentHeader::V8 => V8MemberTitleHeader::new(&u80)?.0,
         };
-        reader.read2::<String>(&mut u80).await;
-        let library_base = reader.read2::<DocumentBase>(&mut u80).await;
-        let library_update_date: String = reader.read2(&mut u80).await;
+        reader.read_exact(&mut u80).await?;
+        let mut descriptor = [0; 160];
+        reader.read_exact(&mut descriptor).await?;
+        reader.read_exact(&mut u80).await?;
         let str_title_header = match document_header {
-            DocumentHeader::V5 => {
-                reader
-                    .read2::<BufferFromByteArray<V5NameStrTitleHeader>>(&mut u80)
-                    .await
-                    .0
-                     .0
-            }
-            DocumentHeader::V8 => {
-                reader
-                    .read2::<BufferFromByteArray<V8NameStrTitleHeader>>(&mut u80)
-                    .await
-                    .0
-                     .0
-            }
+            DocumentHeader::V5 => V5NameStrTitleHeader::new(&u80)?.0,
+            DocumentHeader::V8 => V8NameStrTitleHeader::new(&u80)?.0,
         };
-        let mut left_blank = member_title_header * str_title_header % 80;
+        // the NAMESTR fields read take 122 bytes; VAX writes 136, others 140
+        if member_title_header < 136 {
+            return Err(XPTError::ParseError(format!(
+                "NAMESTR length {} is too short",
+                member_title_header
+            )));
+        }
+        let left_blank = member_title_header as usize * str_title_header as usize % 80;
         let mut name_str_array = vec![0; member_title_header.into()];
         let mut line_length = 0;
-        let mut column_meta_array: Vec<ColumnMeta> = Vec::with_capacity(str_title_header.into());
+        let mut v5_name_st_array: Vec<V5NameSt> = Vec::new();
+        let mut v8_name_st_array: Vec<V8NameSt> = Vec::new();
+        let mut long_records: Vec<LongRecord> = Vec::new();
         let mut v5_name_sts: Vec<(u32, u16, u16)> = Vec::with_capacity(str_title_header.into());
-        let decoder = self.string_decoder;
         let mut observations: usize = 0;
         match document_header {
             DocumentHeader::V5 => {
-                for i in 0..str_title_header {
-                    let name_st: V5NameSt = reader.read2(&mut name_str_array).await;
-                    line_length = name_st.npos + ((&name_st).nlng as u32);
-                    column_meta_array.insert(
-                        i.into(),
-                        ColumnMeta::from_v5(&name_st, self.string_decoder)?,
-                    );
-                    v5_name_sts.insert(i.into(), (name_st.npos, name_st.nlng, name_st.ntype));
+                for _i in 0..str_title_header {
+                    let name_st: V5NameSt = reader.read2(&mut name_str_array).await?;
+                    line_length = name_st.npos + (name_st.nlng as u32);
+                    v5_name_sts.push((name_st.npos, name_st.nlng, name_st.ntype));
+                    v5_name_st_array.push(name_st);
                 }
                 if left_blank > 0 {
-                    reader.skip((80 - left_blank) as usize).await;
+                    reader.skip(80 - left_blank).await?;
                 }
-                let _obs_header = reader.read2::<String>(&mut u80).await;
+                reader.read_exact(&mut u80).await?;
             }
             DocumentHeader::V8 => {
-                let mut with_long_label = false;
-                for i in 0..str_title_header {
-                    let name_st: V8NameSt = reader.read2(&mut name_str_array).await;
-                    line_length = name_st.npos + ((&name_st).nlng as u32);
-                    column_meta_array.insert(
-                        i.into(),
-                        ColumnMeta::from_v8(&name_st, self.string_decoder)?,
-                    );
-                    if name_st.lablen > 40 {
-                        with_long_label = true;
-                    }
-                    v5_name_sts.insert(i.into(), (name_st.npos, name_st.nlng, name_st.ntype));
+                for _i in 0..str_title_header {
+                    let name_st: V8NameSt = reader.read2(&mut name_str_array).await?;
+                    line_length = name_st.npos + (name_st.nlng as u32);
+                    v5_name_sts.push((name_st.npos, name_st.nlng, name_st.ntype));
+                    v8_name_st_array.push(name_st);
                 }
                 if left_blank > 0 {
-                    reader.skip((80 - left_blank) as usize).await;
+                    reader.skip(80 - left_blank).await?;
                 }
-                //should be handle in v8
-                if with_long_label {
-                    let title_header_count = reader
-                        .read2::<BufferFromByteArray<V8LabelStrTitleHeader>>(&mut u80)
-                        .await
-                        .0
-                         .0;
-                    left_blank = 0;
-                    let mut len_def = [0; 6];
-                    let mut var_map: HashMap<u16, ColumnMeta> = column_meta_array
-                        .into_iter()
-                        .map(|f| (f.var_count, f))
-                        .collect();
-                    for _i in 0..title_header_count {
-                        reader.read_exact(&mut len_def).await?;
-                        deserialize_in_order!(
-                            len_def,{
-                                var_number :u16 with 2,
-                                name_len:u16 with 2,
-                                label_len:u16 with 2
-                            }
-                        );
-                        let option = var_map.get_mut(&var_number).unwrap();
-                        let mut vec2 = vec![0; name_len as usize];
-                        reader.read_exact(vec2.as_mut_slice()).await?;
-                        option.name = decoder(vec2.as_slice())?;
-                        vec2 = vec![0; label_len as usize];
-                        reader.read_exact(vec2.as_mut_slice()).await?;
-                        option.label = decoder(vec2.as_slice())?;
-                        left_blank = (left_blank + 6 + name_len + label_len) % 80;
+                reader.read_exact(&mut u80).await?;
+                let mut extension = V8ExtensionHeader::new(&u80)?;
+                if let V8ExtensionHeader::LabelV8(count) | V8ExtensionHeader::LabelV9(count) =
+                    extension
+                {
+                    let prefix_length =
+                        LongRecord::prefix_length(extension != V8ExtensionHeader::LabelV8(count));
+                    let mut prefix = [0; 10];
+                    let mut record_length = 0;
+                    for _i in 0..count {
+                        reader.read_exact(&mut prefix[..prefix_length]).await?;
+                        let lengths = LongRecord::lengths(&prefix[..prefix_length]);
+                        let mut text = vec![0; lengths[1..].iter().map(|&l| l as usize).sum()];
+                        reader.read_exact(&mut text).await?;
+                        record_length += prefix_length + text.len();
+                        long_records.push(LongRecord::new(&lengths, text));
                     }
-                    if left_blank > 0 {
-                        reader.skip((80 - left_blank) as usize).await;
+                    if record_length % 80 > 0 {
+                        reader.skip(80 - record_length % 80).await?;
                     }
-                    column_meta_array = var_map.into_values().collect();
-                    column_meta_array.sort_by(|a, b| a.var_count.cmp(&b.var_count));
+                    reader.read_exact(&mut u80).await?;
+                    extension = V8ExtensionHeader::new(&u80)?;
                 }
-                observations = reader
-                    .read2::<BufferFromByteArray<V8ObsHeaderRecord>>(&mut u80)
-                    .await
-                    .0
-                     .0 as usize;
+                observations = match extension {
+                    V8ExtensionHeader::Observations(observations) => observations as usize,
+                    _ => {
+                        return Err(XPTError::ParseError(
+                            "long label records are not followed by OBSV8".to_string(),
+                        ))
+                    }
+                };
             }
         }
+        let mut encoding = None;
+        if let Some(detection) = &self.detection {
+            let mut detector = EncodingDetector::new();
+            sample_text(
+                &mut detector,
+                &v5_name_st_array,
+                &v8_name_st_array,
+                &long_records,
+                &descriptor,
+            );
+            let mut rows = vec![0; detection.sample_rows * line_length as usize];
+            let read = reader.read2bytes(&mut rows).await?;
+            rows.truncate(read);
+            sample_rows(&mut detector, &rows, &v5_name_sts);
+            reader.unread(rows);
+            encoding = Some(detector.guess());
+        }
+        let decoder = encoding
+            .as_ref()
+            .map_or(self.string_decoder, |guess| self.choose_decoder(guess));
+        let columns = decode_columns(&v5_name_st_array, &v8_name_st_array, &long_records, decoder)?;
+        let member = MemberDescriptor::decode(&descriptor, document_header, decoder)?;
         Ok((
             RawReader {
                 reader: self.reader.clone(),
@@ -631,19 +1167,33 @@ impl<'a> Reader<'a> {
                 line_str_array: vec![0; line_length as usize],
                 line_number: 0,
                 v5_name_sts,
-                string_decoder: self.string_decoder,
+                string_decoder: decoder,
                 observations,
+                char_mode: CharMode::Strict,
+                lossy_decoder: UTF8_LOSSY_STRING_DECODER,
+                malformed: Vec::new(),
+                overlay: None,
+                out_of_codelist: Vec::new(),
+                names: columns.iter().map(|c| c.name.clone()).collect(),
+                filter: None,
             },
             DocumentMeta {
                 version: document_header,
                 doc_version: decoder(&document_base.version.inner)?,
                 operation_system: decoder(&document_base.operation_system.inner)?,
-                doc_update_time: update_date,
-                dataset_name: decoder(&library_base.version.inner)?,
-                lib_update_time: library_update_date,
+                doc_update_time: decoder(&document_base.time.inner)?,
+                lib_update_time: decoder(&update_date[..16])?,
+                dataset_name: member.dataset_name,
+                dataset_label: member.dataset_label,
+                dataset_type: member.dataset_type,
+                dataset_version: member.sas_version,
+                dataset_operation_system: member.operation_system,
+                dataset_created: member.created,
+                dataset_modified: member.modified,
                 member_meta_length: member_title_header,
-                library: decoder(&library_base.dataset_name.inner)?,
-                columns: column_meta_array,
+                library: decoder(&document_base.header_type.inner)?,
+                columns,
+                encoding,
             },
         ))
     }
@@ -651,8 +1201,15 @@ impl<'a> Reader<'a> {
 
 #[cfg(test)]
 mod test {
+    #[cfg(not(feature = "async"))]
+    use crate::batch::BatchColumn;
+    #[cfg(not(feature = "async"))]
+    use crate::detect::TextEncoding;
+    #[cfg(any(not(feature = "async"), not(feature = "multi_encoding")))]
     use crate::error::XPTError;
-    use crate::reader::{byte2number, number2Byte, Reader};
+    use crate::reader::Reader;
+    #[cfg(not(feature = "async"))]
+    use crate::reader::{CharMode, RawVal, UTF8_STRING_DECODER};
     #[cfg(not(feature = "async"))]
     use std::fs::File;
 
@@ -675,6 +1232,10 @@ mod test {
                 let mut reader = Reader::new_gbk(&mut file);
                 let result = reader.start().await.unwrap();
                 println!("{:?}", result.1.library);
+                assert_eq!(result.1.library, "SASLIB");
+                assert_eq!(result.1.dataset_name, "LB");
+                assert_eq!(result.1.dataset_label, "Laboratory Test Results");
+                assert_eq!(result.1.dataset_version, "9.4");
                 println!(
                     "{}",
                     result
@@ -722,6 +1283,10 @@ mod test {
         let mut reader = Reader::new_gbk(&mut file);
         let result = reader.start().unwrap();
         println!("{:?}", result.1.library);
+        assert_eq!(result.1.library, "SASLIB");
+        assert_eq!(result.1.dataset_name, "LB");
+        assert_eq!(result.1.dataset_label, "Laboratory Test Results");
+        assert_eq!(result.1.dataset_version, "9.4");
         println!(
             "{}",
             result
@@ -755,14 +1320,224 @@ mod test {
     }
 
     #[test]
-    fn test_byte2number() {
+    #[cfg(not(feature = "async"))]
+    fn test_auto_encoding() {
+        let mut bytes = std::fs::read("sample/LB2.xpt").unwrap();
+        let at = bytes.windows(7).position(|w| w == b"Glucose").unwrap();
+        // "葡萄糖" in GBK, padded to the width of "Glucose"
+        bytes[at..at + 7].copy_from_slice(&[0xc6, 0xcf, 0xcc, 0xd1, 0xcc, 0xc7, b' ']);
+        let mut input = bytes.as_slice();
+        let mut reader = Reader::new_auto(&mut input);
+        let (mut data, meta) = reader.start().unwrap();
+        let guess = meta.encoding.unwrap();
+        assert_eq!(guess.encoding, TextEncoding::Gbk);
+        assert_eq!(meta.columns.len(), 9);
+        #[cfg(feature = "multi_encoding")]
+        {
+            let line = data.read_line().unwrap().unwrap();
+            assert_eq!(line[5].to_string(), "Alanine Aminotransferase");
+            data.read_line().unwrap();
+            let line = data.read_line().unwrap().unwrap();
+            assert_eq!(line[5].to_string(), "葡萄糖");
+        }
+        #[cfg(not(feature = "multi_encoding"))]
+        assert!(data.read_line().unwrap().is_some());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_corrupted_header() {
+        let bytes = std::fs::read("sample/LB2.xpt").unwrap();
+        let start = |bytes: &[u8]| {
+            let mut input = bytes;
+            Reader::new(&mut input, UTF8_STRING_DECODER)
+                .start()
+                .map(|(_, meta)| meta)
+        };
+        let mut corrupted = bytes.clone();
+        corrupted[614..618].copy_from_slice(b"00X9");
+        assert!(matches!(start(&corrupted), Err(XPTError::ParseError(_))));
+        let mut corrupted = bytes.clone();
+        corrupted[20..48].copy_from_slice(&[b'!'; 28]);
+        assert!(matches!(start(&corrupted), Err(XPTError::ParseError(_))));
+        for length in [0, 100, 600, 700, 1930] {
+            assert!(start(&bytes[..length]).is_err(), "{} bytes", length);
+        }
+        assert_eq!(start(&bytes).unwrap().columns.len(), 9);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_read_after_unread() {
+        use crate::reader::ReaderWrap;
+
+        let mut input: &[u8] = b"abcdefgh";
+        let mut reader = ReaderWrap::new(&mut input);
+        assert_eq!(reader.peek_length(3).unwrap(), 3);
+        let mut two = [0; 2];
+        reader.read_exact(&mut two).unwrap();
+        assert_eq!(&two, b"ab");
+        reader.skip(2).unwrap();
+        let rest: u32 = reader.read2(&mut [0; 4]).unwrap();
+        assert_eq!(rest, u32::from_be_bytes(*b"efgh"));
+        assert!(reader.skip(1).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_raw_and_lossy_char() {
+        let mut bytes = std::fs::read("sample/LB2.xpt").unwrap();
+        let at = bytes.windows(7).position(|w| w == b"Glucose").unwrap();
+        bytes[at] = 0xff;
+        let mut input = bytes.as_slice();
+        let mut reader = Reader::new(&mut input, UTF8_STRING_DECODER);
+        let (mut data, _) = reader.start().unwrap();
+        data.read_line().unwrap();
+        let raw = data.read_line_raw().unwrap().unwrap();
+        assert_eq!(raw[4], RawVal::Char(b"AST     ".to_vec()));
+        assert_eq!(raw[3], RawVal::Number(102.0));
+        assert!(data.read_line().is_err());
+
+        let mut input = bytes.as_slice();
+        let mut reader = Reader::new(&mut input, UTF8_STRING_DECODER);
+        let (mut data, _) = reader.start().unwrap();
+        data.set_char_mode(CharMode::Lossy);
+        data.read_line().unwrap();
+        assert!(data.malformed_cells().is_empty());
+        data.read_line().unwrap();
+        let line = data.read_line().unwrap().unwrap();
+        assert_eq!(line[5].to_string(), "\u{fffd}lucose");
+        assert_eq!(data.malformed_cells(), &[5]);
+
+        #[cfg(feature = "multi_encoding")]
+        {
+            use crate::reader::GBK_LOSSY_STRING_DECODER;
+            use encoding::all::GBK;
+            use encoding::{DecoderTrap, Encoding};
+
+            // "葡萄" in GBK, then a byte GBK has no character for
+            bytes[at..at + 7].copy_from_slice(&[0xc6, 0xcf, 0xcc, 0xd1, 0xff, b' ', b' ']);
+            let mut input = bytes.as_slice();
+            let mut reader = Reader::new(&mut input, |x| {
+                GBK.decode(x, DecoderTrap::Strict)
+                    .map_err(|x| XPTError::DecodeError(x.to_string()))
+                    .map(|x| x.trim().to_string())
+            });
+            let (mut data, _) = reader.start().unwrap();
+            data.set_char_mode(CharMode::Lossy);
+            data.set_lossy_decoder(GBK_LOSSY_STRING_DECODER);
+            data.read_line().unwrap();
+            data.read_line().unwrap();
+            let line = data.read_line().unwrap().unwrap();
+            assert_eq!(line[5].to_string(), "葡萄\u{fffd}");
+            assert_eq!(data.malformed_cells(), &[5]);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_read_row() {
+        let mut file = File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, _) = reader.start().unwrap();
+        let mut rows = 0;
+        let mut total = 0.0;
+        while let Some(row) = data.read_row().unwrap() {
+            assert_eq!(row.len(), 9);
+            assert_eq!(row.get_utf8(1).unwrap(), "LB");
+            assert_eq!(row.get_bytes(1), b"LB");
+            assert!(row.get_f64(0).is_none());
+            if rows == 6 {
+                assert!(row.is_missing(7));
+                assert!(row.is_missing(6));
+            } else {
+                total += row.get_f64(7).unwrap();
+            }
+            if rows == 2 {
+                assert_eq!(row.get_str(5).unwrap(), "Glucose");
+            }
+            rows += 1;
+        }
+        assert_eq!(rows, 9);
+        assert_eq!(total, 22.0 + 18.0 + 5.5 + 35.0 + 0.06 + 6.0 + 27.0 + 7.0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_read_batch() {
+        let mut file = File::open("sample/LB2.xpt").unwrap();
+        let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+        let (mut data, _) = reader.start().unwrap();
+        let mut sizes = vec![];
+        let mut results = vec![];
+        let mut firsts = vec![];
+        while let Some(batch) = data.read_batch(4).unwrap() {
+            sizes.push(batch.rows);
+            if let BatchColumn::Numeric(values) = &batch.columns[7] {
+                results.extend_from_slice(values);
+            }
+            if let BatchColumn::Char(codes) = &batch.columns[4] {
+                assert_eq!(codes.len(), batch.rows);
+                firsts.push(codes.get(0).to_vec());
+            }
+        }
+        assert_eq!(sizes, vec![4, 4, 1]);
         assert_eq!(
-            byte2number(&[63, 245, 194, 143, 92, 0]).unwrap(),
-            0.059999999997671694
+            firsts,
+            vec![b"ALT".to_vec(), b"AST".to_vec(), b"GLUC".to_vec()]
         );
+        assert_eq!(results[2], Some(5.5));
+        assert_eq!(results[6], None);
+        assert_eq!(results.len(), 9);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_namestr_detail() {
+        let bytes = std::fs::read("sample/LB2.xpt").unwrap();
+        let mut input = bytes.as_slice();
+        let (mut data, meta) = Reader::new(&mut input, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        assert_eq!(meta.member_meta_length, 140);
+        let last = meta.columns.last().unwrap();
+        assert_eq!(meta.columns[0].position, 0);
+        assert_eq!(last.position + last.length as u32, data.line_length());
+        assert_eq!(data.line_length(), 137);
         assert_eq!(
-            number2Byte(Some(0.059999999997671694)),
-            [63, 245, 194, 143, 92, 0, 0, 0]
+            (meta.columns[3].justification, meta.columns[4].justification),
+            (0, 1)
         );
+        assert_eq!(&meta.columns[0].namestr[..], &bytes[640..780]);
+        let mut rows = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            rows.push(row);
+        }
+
+        // the same file with the 136-byte NAMESTRs of VAX/VMS
+        let mut vms = bytes[..640].to_vec();
+        vms[315..318].copy_from_slice(b"136");
+        for namestr in bytes[640..640 + 9 * 140].chunks_exact(140) {
+            vms.extend_from_slice(&namestr[..136]);
+        }
+        vms.resize(640 + 1280, b' ');
+        vms.extend_from_slice(&bytes[1920..]);
+        let mut input = vms.as_slice();
+        let (mut data, copy) = Reader::new(&mut input, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        assert_eq!(copy.member_meta_length, 136);
+        for (a, b) in copy.columns.iter().zip(&meta.columns) {
+            assert_eq!(
+                (&a.name, a.position, a.length),
+                (&b.name, b.position, b.length)
+            );
+            assert_eq!(a.namestr.len(), 136);
+        }
+        let mut copied = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            copied.push(row);
+        }
+        assert_eq!(copied, rows);
     }
 }
@@ -0,0 +1,268 @@
+use crate::error::XPTError;
+use crate::part::{ColumnMeta, ColumnType, DocumentMeta, StringDecoder, StringEncoder};
+use crate::reader::Reader;
+use crate::view::RowView;
+use crate::writer::{layout, relayout, MemberHeader, Writer};
+use std::fmt::Display;
+use std::path::Path;
+
+/// Declared and shrunk length of a CHAR column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnShrink {
+    pub name: String,
+    pub old_length: u16,
+    pub new_length: u16,
+}
+
+/// Outcome of [`shrink_file`].
+#[derive(Debug, Clone)]
+pub struct ShrinkReport {
+    /// Every CHAR column, shrunk or not.
+    pub columns: Vec<ColumnShrink>,
+    pub rows: usize,
+    pub old_line_length: usize,
+    pub new_line_length: usize,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl ShrinkReport {
+    /// Bytes saved on the whole file.
+    pub fn saved(&self) -> u64 {
+        self.old_size.saturating_sub(self.new_size)
+    }
+}
+
+impl Display for ShrinkReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .columns
+            .iter()
+            .map(|c| c.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(8);
+        writeln!(f, "{:width$}  Length  Shrunk", "Variable", width = width)?;
+        for column in &self.columns {
+            writeln!(
+                f,
+                "{:width$}  {:>6}  {:>6}",
+                column.name,
+                column.old_length,
+                column.new_length,
+                width = width
+            )?;
+        }
+        writeln!(
+            f,
+            "Observation length {} -> {} bytes, {} observations",
+            self.old_line_length, self.new_line_length, self.rows
+        )?;
+        let share = if self.old_size == 0 {
+            0.0
+        } else {
+            self.saved() as f64 * 100.0 / self.old_size as f64
+        };
+        writeln!(
+            f,
+            "File size {} -> {} bytes, {} saved ({:.1}%)",
+            self.old_size,
+            self.new_size,
+            self.saved(),
+            share
+        )
+    }
+}
+
+/// Finds the longest value of every CHAR column, trailing blanks excluded.
+pub struct Shrinker {
+    columns: Vec<ColumnMeta>,
+    widths: Vec<u16>,
+    rows: usize,
+}
+
+impl Shrinker {
+    pub fn new(meta: &DocumentMeta) -> Self {
+        Shrinker {
+            columns: meta.columns.clone(),
+            widths: vec![0; meta.columns.len()],
+            rows: 0,
+        }
+    }
+
+    pub fn check_row(&mut self, row: &RowView) {
+        self.rows += 1;
+        for (i, width) in self.widths.iter_mut().enumerate() {
+            if row.is_numeric(i) {
+                continue;
+            }
+            let bytes = row.get_bytes(i);
+            let used = bytes
+                .iter()
+                .rposition(|b| *b != b' ' && *b != 0)
+                .map_or(0, |p| p + 1) as u16;
+            *width = (*width).max(used);
+        }
+    }
+
+    /// The columns with CHAR lengths cut to the longest value, at least 1.
+    pub fn columns(&self) -> Vec<ColumnMeta> {
+        self.columns
+            .iter()
+            .zip(&self.widths)
+            .map(|(column, width)| {
+                let mut column = column.clone();
+                if column.column_type == ColumnType::CHAR {
+                    column.length = (*width).max(1);
+                }
+                column
+            })
+            .collect()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn report(&self, old_size: u64, new_size: u64) -> ShrinkReport {
+        let shrunk = self.columns();
+        let line_length = |columns: &[ColumnMeta]| columns.iter().map(|c| c.length as usize).sum();
+        ShrinkReport {
+            columns: self
+                .columns
+                .iter()
+                .zip(&shrunk)
+                .filter(|(column, _)| column.column_type == ColumnType::CHAR)
+                .map(|(old, new)| ColumnShrink {
+                    name: old.name.clone(),
+                    old_length: old.length,
+                    new_length: new.length,
+                })
+                .collect(),
+            rows: self.rows,
+            old_line_length: line_length(&self.columns),
+            new_line_length: line_length(&shrunk),
+            old_size,
+            new_size,
+        }
+    }
+}
+
+/// Rewrites the file at `input` to `output` with every CHAR column as long
+/// as its longest value. Names and labels are written back with
+/// `string_encoder`, CHAR values are copied as bytes.
+#[cfg(not(feature = "async"))]
+pub fn shrink_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<ShrinkReport, XPTError> {
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut shrinker = Shrinker::new(&meta);
+    while let Some(row) = data.read_row()? {
+        shrinker.check_row(&row);
+    }
+    let old_size = std::fs::metadata(&input)?.len();
+
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut header = MemberHeader::from_meta(&meta);
+    header.observations = Some(shrinker.rows());
+    let columns = shrinker.columns();
+    let layout = layout(&columns);
+    let mut writer = Writer::new(&mut out, header, columns, string_encoder)?;
+    writer.start()?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row()? {
+        relayout(&row, &layout, &mut line);
+        writer.write_line(&line)?;
+    }
+    let new_size = writer.finish()?;
+    Ok(shrinker.report(old_size, new_size))
+}
+
+/// Rewrites the file at `input` to `output` with every CHAR column as long
+/// as its longest value. Names and labels are written back with
+/// `string_encoder`, CHAR values are copied as bytes.
+#[cfg(feature = "async")]
+pub async fn shrink_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<ShrinkReport, XPTError> {
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut shrinker = Shrinker::new(&meta);
+    while let Some(row) = data.read_row().await? {
+        shrinker.check_row(&row);
+    }
+    let old_size = tokio::fs::metadata(&input).await?.len();
+
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut header = MemberHeader::from_meta(&meta);
+    header.observations = Some(shrinker.rows());
+    let columns = shrinker.columns();
+    let layout = layout(&columns);
+    let mut writer = Writer::new(&mut out, header, columns, string_encoder)?;
+    writer.start().await?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row().await? {
+        relayout(&row, &layout, &mut line);
+        writer.write_line(&line).await?;
+    }
+    let new_size = writer.finish().await?;
+    Ok(shrinker.report(old_size, new_size))
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_shrink_file() {
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::shrink::shrink_file;
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let output = std::env::temp_dir().join("xpt_r_shrink_lb2.xpt");
+        let report = shrink_file(
+            "sample/LB2.xpt",
+            &output,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(report.rows, 9);
+        let lbtest = report.columns.iter().find(|c| c.name == "LBTEST").unwrap();
+        assert_eq!((lbtest.old_length, lbtest.new_length), (40, 26));
+        let studyid = &report.columns[0];
+        assert_eq!((studyid.old_length, studyid.new_length), (12, 12));
+        assert!(report.new_line_length < report.old_line_length);
+        assert!(report.saved() > 0);
+        assert_eq!(report.new_size, std::fs::metadata(&output).unwrap().len());
+
+        let mut original = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut shrunk = std::fs::File::open(&output).unwrap();
+        let (mut a, _) = Reader::new(&mut original, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        let (mut b, meta) = Reader::new(&mut shrunk, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        assert_eq!(meta.columns[5].length, 26);
+        while let Some(row) = a.read_line().unwrap() {
+            assert_eq!(b.read_line().unwrap(), Some(row));
+        }
+        assert_eq!(b.read_line().unwrap(), None);
+        std::fs::remove_file(&output).unwrap();
+    }
+}
@@ -0,0 +1,857 @@
+use crate::error::XPTError;
+use crate::filter::{compare_numbers, compare_padded};
+use crate::number::byte2number;
+use crate::part::{ColumnType, DocumentMeta, StringDecoder, StringEncoder};
+use crate::reader::Reader;
+use crate::view::RowView;
+use crate::writer::{layout, relayout, MemberHeader, Writer};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// A sort variable, as in a `BY` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub name: String,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn ascending(name: &str) -> Self {
+        SortKey {
+            name: name.to_string(),
+            descending: false,
+        }
+    }
+
+    pub fn descending(name: &str) -> Self {
+        SortKey {
+            name: name.to_string(),
+            descending: true,
+        }
+    }
+
+    /// Parses a `BY` list such as `STUDYID USUBJID DESCENDING LBSEQ`, where
+    /// `DESCENDING` applies to the variable after it.
+    pub fn parse_by(by: &str) -> Result<Vec<SortKey>, XPTError> {
+        let mut keys = Vec::new();
+        let mut descending = false;
+        for word in by.split([' ', ',']).filter(|w| !w.is_empty()) {
+            if word.eq_ignore_ascii_case("descending") {
+                descending = true;
+                continue;
+            }
+            keys.push(SortKey {
+                name: word.to_string(),
+                descending,
+            });
+            descending = false;
+        }
+        if descending || keys.is_empty() {
+            return Err(XPTError::ParseError(format!("bad BY list {:?}", by)));
+        }
+        Ok(keys)
+    }
+}
+
+/// Rank of the special missing values, `._` lowest, then `.` and `.A`
+/// to `.Z`.
+fn missing_rank(bytes: &[u8]) -> u8 {
+    match bytes.first() {
+        Some(b'_') => 0,
+        Some(b'.') => 1,
+        Some(code) => 2 + code.saturating_sub(b'A'),
+        None => 1,
+    }
+}
+
+/// Compares two cells of a column as stored, in SAS collation.
+pub(crate) fn compare_cells(column_type: ColumnType, a: &[u8], b: &[u8]) -> Ordering {
+    match column_type {
+        ColumnType::CHAR => compare_padded(a, b),
+        ColumnType::NUMERIC => match (byte2number(a), byte2number(b)) {
+            (None, None) => missing_rank(a).cmp(&missing_rank(b)),
+            (a, b) => compare_numbers(a, b),
+        },
+    }
+}
+
+/// Orders rows by their sort variables in SAS collation: missing NUMERIC
+/// values first, CHAR values compared byte by byte as if blank-padded.
+#[derive(Debug, Clone)]
+pub struct SortOrder {
+    keys: Vec<(usize, ColumnType, bool)>,
+}
+
+impl SortOrder {
+    pub fn new(meta: &DocumentMeta, keys: &[SortKey]) -> Result<Self, XPTError> {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                meta.columns
+                    .iter()
+                    .position(|c| c.name.eq_ignore_ascii_case(&key.name))
+                    .map(|i| (i, meta.columns[i].column_type, key.descending))
+                    .ok_or_else(|| {
+                        XPTError::ParseError(format!("unknown sort variable {}", key.name))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(SortOrder { keys })
+    }
+
+    pub fn compare(&self, a: &RowView, b: &RowView) -> Ordering {
+        for (i, column_type, descending) in &self.keys {
+            let ordering = compare_cells(*column_type, a.get_bytes(*i), b.get_bytes(*i));
+            let ordering = if *descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Stable order of the lines in `lines`, as indexes.
+    fn sort_lines(
+        &self,
+        lines: &[u8],
+        line_length: usize,
+        columns: &[(u32, u16, u16)],
+        decoder: StringDecoder,
+    ) -> Vec<usize> {
+        let view = |i: usize| {
+            RowView::new(
+                &lines[i * line_length..(i + 1) * line_length],
+                columns,
+                decoder,
+            )
+        };
+        let mut order: Vec<usize> = (0..lines.len() / line_length.max(1)).collect();
+        order.sort_by(|a, b| self.compare(&view(*a), &view(*b)));
+        order
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SortOptions {
+    /// Bytes of observations held in memory before a sorted run is spilled.
+    pub max_memory: usize,
+    /// Directory of the spilled runs, the system temporary directory when
+    /// `None`.
+    pub temp_directory: Option<PathBuf>,
+    /// Runs merged at once, each an open file; more runs are merged in
+    /// several passes.
+    pub max_open_runs: usize,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        SortOptions {
+            max_memory: 256 << 20,
+            temp_directory: None,
+            max_open_runs: 64,
+        }
+    }
+}
+
+/// Outcome of [`sort_file`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortReport {
+    pub rows: usize,
+    /// Sorted runs merged into the output, 1 when the data fit in memory.
+    pub runs: usize,
+}
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Spilled run files, removed when dropped.
+struct SpillFiles {
+    directory: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl SpillFiles {
+    fn new(options: &SortOptions) -> Self {
+        SpillFiles {
+            directory: options
+                .temp_directory
+                .clone()
+                .unwrap_or_else(std::env::temp_dir),
+            paths: Vec::new(),
+        }
+    }
+
+    fn next_path(&mut self) -> PathBuf {
+        let path = self.directory.join(format!(
+            "xpt_r_sort_{}_{}.run",
+            std::process::id(),
+            SPILL_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        self.paths.push(path.clone());
+        path
+    }
+}
+
+impl Drop for SpillFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A sorted run waiting to be merged: spilled to a file, or the last one
+/// kept in memory. Files are only opened while their run is merged.
+enum RunSource {
+    Memory { lines: Vec<u8>, order: Vec<usize> },
+    File(PathBuf),
+}
+
+/// An open sorted run.
+enum Run {
+    Memory {
+        lines: Vec<u8>,
+        order: Vec<usize>,
+        next: usize,
+    },
+    #[cfg(not(feature = "async"))]
+    File(std::io::BufReader<std::fs::File>),
+    #[cfg(feature = "async")]
+    File(tokio::io::BufReader<tokio::fs::File>),
+}
+
+impl Run {
+    #[cfg(not(feature = "async"))]
+    fn open(source: RunSource) -> Result<Self, XPTError> {
+        Ok(match source {
+            RunSource::Memory { lines, order } => Run::Memory {
+                lines,
+                order,
+                next: 0,
+            },
+            RunSource::File(path) => Run::File(std::io::BufReader::new(std::fs::File::open(path)?)),
+        })
+    }
+
+    #[cfg(feature = "async")]
+    async fn open(source: RunSource) -> Result<Self, XPTError> {
+        Ok(match source {
+            RunSource::Memory { lines, order } => Run::Memory {
+                lines,
+                order,
+                next: 0,
+            },
+            RunSource::File(path) => Run::File(tokio::io::BufReader::new(
+                tokio::fs::File::open(path).await?,
+            )),
+        })
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn next_line(&mut self, line: &mut [u8]) -> Result<bool, XPTError> {
+        use std::io::Read;
+        match self {
+            Run::Memory { lines, order, next } => Ok(Self::next_memory(lines, order, next, line)),
+            Run::File(file) => match file.read_exact(line) {
+                Ok(()) => Ok(true),
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+                Err(error) => Err(error.into()),
+            },
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn next_line(&mut self, line: &mut [u8]) -> Result<bool, XPTError> {
+        use tokio::io::AsyncReadExt;
+        match self {
+            Run::Memory { lines, order, next } => Ok(Self::next_memory(lines, order, next, line)),
+            Run::File(file) => match file.read_exact(line).await {
+                Ok(_) => Ok(true),
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+                Err(error) => Err(error.into()),
+            },
+        }
+    }
+
+    fn next_memory(lines: &[u8], order: &[usize], next: &mut usize, line: &mut [u8]) -> bool {
+        let Some(i) = order.get(*next) else {
+            return false;
+        };
+        *next += 1;
+        let length = line.len();
+        line.copy_from_slice(&lines[i * length..(i + 1) * length]);
+        true
+    }
+}
+
+/// Compares raw observation lines by a [`SortOrder`].
+struct LineOrder<'a> {
+    order: &'a SortOrder,
+    columns: &'a [(u32, u16, u16)],
+    decoder: StringDecoder,
+}
+
+impl LineOrder<'_> {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.order.compare(
+            &RowView::new(a, self.columns, self.decoder),
+            &RowView::new(b, self.columns, self.decoder),
+        )
+    }
+}
+
+/// The next line of a run in the merge heap, ordered so that the line that
+/// sorts first, from the earliest run on ties, is at the top.
+struct Head<'a> {
+    line: Vec<u8>,
+    run: usize,
+    by: &'a LineOrder<'a>,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.by
+            .compare(&other.line, &self.line)
+            .then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+/// Merges sorted runs, given in input order, into one stable order.
+struct Merge<'a> {
+    runs: Vec<Run>,
+    heads: BinaryHeap<Head<'a>>,
+}
+
+impl<'a> Merge<'a> {
+    #[cfg(not(feature = "async"))]
+    fn new(
+        sources: Vec<RunSource>,
+        line_length: usize,
+        by: &'a LineOrder<'a>,
+    ) -> Result<Self, XPTError> {
+        let mut runs = Vec::with_capacity(sources.len());
+        let mut heads = BinaryHeap::with_capacity(sources.len());
+        for (run, source) in sources.into_iter().enumerate() {
+            let mut open = Run::open(source)?;
+            let mut line = vec![0; line_length];
+            if open.next_line(&mut line)? {
+                heads.push(Head { line, run, by });
+            }
+            runs.push(open);
+        }
+        Ok(Merge { runs, heads })
+    }
+
+    #[cfg(feature = "async")]
+    async fn new(
+        sources: Vec<RunSource>,
+        line_length: usize,
+        by: &'a LineOrder<'a>,
+    ) -> Result<Self, XPTError> {
+        let mut runs = Vec::with_capacity(sources.len());
+        let mut heads = BinaryHeap::with_capacity(sources.len());
+        for (run, source) in sources.into_iter().enumerate() {
+            let mut open = Run::open(source).await?;
+            let mut line = vec![0; line_length];
+            if open.next_line(&mut line).await? {
+                heads.push(Head { line, run, by });
+            }
+            runs.push(open);
+        }
+        Ok(Merge { runs, heads })
+    }
+
+    /// Moves the next merged line into `line`, which must be a line long.
+    #[cfg(not(feature = "async"))]
+    fn next_line(&mut self, line: &mut Vec<u8>) -> Result<bool, XPTError> {
+        let Some(mut head) = self.heads.pop() else {
+            return Ok(false);
+        };
+        std::mem::swap(line, &mut head.line);
+        if self.runs[head.run].next_line(&mut head.line)? {
+            self.heads.push(head);
+        }
+        Ok(true)
+    }
+
+    /// Moves the next merged line into `line`, which must be a line long.
+    #[cfg(feature = "async")]
+    async fn next_line(&mut self, line: &mut Vec<u8>) -> Result<bool, XPTError> {
+        let Some(mut head) = self.heads.pop() else {
+            return Ok(false);
+        };
+        std::mem::swap(line, &mut head.line);
+        if self.runs[head.run].next_line(&mut head.line).await? {
+            self.heads.push(head);
+        }
+        Ok(true)
+    }
+}
+
+/// Merges consecutive groups of `max_open_runs` runs into spilled runs until
+/// at most `max_open_runs` are left, so no more than that many files are
+/// open at once.
+#[cfg(not(feature = "async"))]
+fn merge_passes(
+    mut sources: Vec<RunSource>,
+    max_open_runs: usize,
+    spill: &mut SpillFiles,
+    line_length: usize,
+    by: &LineOrder,
+) -> Result<Vec<RunSource>, XPTError> {
+    use std::io::Write;
+
+    let max_open_runs = max_open_runs.max(2);
+    while sources.len() > max_open_runs {
+        let mut merged = Vec::with_capacity(sources.len().div_ceil(max_open_runs));
+        while !sources.is_empty() {
+            let group: Vec<_> = sources.drain(..max_open_runs.min(sources.len())).collect();
+            if group.len() == 1 {
+                merged.extend(group);
+                continue;
+            }
+            let path = spill.next_path();
+            let mut run = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            let mut merge = Merge::new(group, line_length, by)?;
+            let mut line = vec![0; line_length];
+            while merge.next_line(&mut line)? {
+                run.write_all(&line)?;
+            }
+            run.flush()?;
+            merged.push(RunSource::File(path));
+        }
+        sources = merged;
+    }
+    Ok(sources)
+}
+
+/// Merges consecutive groups of `max_open_runs` runs into spilled runs until
+/// at most `max_open_runs` are left, so no more than that many files are
+/// open at once.
+#[cfg(feature = "async")]
+async fn merge_passes(
+    mut sources: Vec<RunSource>,
+    max_open_runs: usize,
+    spill: &mut SpillFiles,
+    line_length: usize,
+    by: &LineOrder<'_>,
+) -> Result<Vec<RunSource>, XPTError> {
+    use tokio::io::AsyncWriteExt;
+
+    let max_open_runs = max_open_runs.max(2);
+    while sources.len() > max_open_runs {
+        let mut merged = Vec::with_capacity(sources.len().div_ceil(max_open_runs));
+        while !sources.is_empty() {
+            let group: Vec<_> = sources.drain(..max_open_runs.min(sources.len())).collect();
+            if group.len() == 1 {
+                merged.extend(group);
+                continue;
+            }
+            let path = spill.next_path();
+            let mut run = tokio::io::BufWriter::new(tokio::fs::File::create(&path).await?);
+            let mut merge = Merge::new(group, line_length, by).await?;
+            let mut line = vec![0; line_length];
+            while merge.next_line(&mut line).await? {
+                run.write_all(&line).await?;
+            }
+            run.flush().await?;
+            merged.push(RunSource::File(path));
+        }
+        sources = merged;
+    }
+    Ok(sources)
+}
+
+/// Writes `input` to `output` sorted by `keys`, keeping equal rows in input
+/// order. Observations beyond `options.max_memory` are sorted in runs
+/// spilled to temporary files and merged, `options.max_open_runs` at a time.
+#[cfg(not(feature = "async"))]
+pub fn sort_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    keys: &[SortKey],
+    options: &SortOptions,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<SortReport, XPTError> {
+    use std::io::Write;
+
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let order = SortOrder::new(&meta, keys)?;
+    let columns = data.columns().to_vec();
+    let line_length = data.line_length() as usize;
+    let run_bytes = (options.max_memory / line_length.max(1)).max(1) * line_length;
+    let mut spill = SpillFiles::new(options);
+    let mut sources = Vec::new();
+    let mut chunk = Vec::new();
+    let mut rows = 0;
+    loop {
+        let more = match data.read_row()? {
+            Some(row) => {
+                chunk.extend_from_slice(row.line());
+                rows += 1;
+                true
+            }
+            None => false,
+        };
+        if !more || chunk.len() >= run_bytes {
+            let sorted = order.sort_lines(&chunk, line_length, &columns, string_decoder);
+            if !more {
+                // nothing is left when the rows fill the spilled runs exactly
+                if !chunk.is_empty() {
+                    sources.push(RunSource::Memory {
+                        lines: std::mem::take(&mut chunk),
+                        order: sorted,
+                    });
+                }
+                break;
+            }
+            let path = spill.next_path();
+            let mut run = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            for i in sorted {
+                run.write_all(&chunk[i * line_length..(i + 1) * line_length])?;
+            }
+            run.flush()?;
+            chunk.clear();
+            sources.push(RunSource::File(path));
+        }
+    }
+    let runs = sources.len();
+    let by = LineOrder {
+        order: &order,
+        columns: &columns,
+        decoder: string_decoder,
+    };
+    let sources = merge_passes(sources, options.max_open_runs, &mut spill, line_length, &by)?;
+
+    let mut header = MemberHeader::from_meta(&meta);
+    header.observations = Some(rows);
+    let layout = layout(&meta.columns);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(&mut out, header, meta.columns, string_encoder)?;
+    writer.start()?;
+    let mut merge = Merge::new(sources, line_length, &by)?;
+    let mut line = vec![0; line_length];
+    let mut out_line = vec![b' '; writer.line_length()];
+    while merge.next_line(&mut line)? {
+        relayout(
+            &RowView::new(&line, &columns, string_decoder),
+            &layout,
+            &mut out_line,
+        );
+        writer.write_line(&out_line)?;
+    }
+    writer.finish()?;
+    Ok(SortReport { rows, runs })
+}
+
+/// Writes `input` to `output` sorted by `keys`, keeping equal rows in input
+/// order. Observations beyond `options.max_memory` are sorted in runs
+/// spilled to temporary files and merged, `options.max_open_runs` at a time.
+#[cfg(feature = "async")]
+pub async fn sort_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    keys: &[SortKey],
+    options: &SortOptions,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<SortReport, XPTError> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let order = SortOrder::new(&meta, keys)?;
+    let columns = data.columns().to_vec();
+    let line_length = data.line_length() as usize;
+    let run_bytes = (options.max_memory / line_length.max(1)).max(1) * line_length;
+    let mut spill = SpillFiles::new(options);
+    let mut sources = Vec::new();
+    let mut chunk = Vec::new();
+    let mut rows = 0;
+    loop {
+        let more = match data.read_row().await? {
+            Some(row) => {
+                chunk.extend_from_slice(row.line());
+                rows += 1;
+                true
+            }
+            None => false,
+        };
+        if !more || chunk.len() >= run_bytes {
+            let sorted = order.sort_lines(&chunk, line_length, &columns, string_decoder);
+            if !more {
+                // nothing is left when the rows fill the spilled runs exactly
+                if !chunk.is_empty() {
+                    sources.push(RunSource::Memory {
+                        lines: std::mem::take(&mut chunk),
+                        order: sorted,
+                    });
+                }
+                break;
+            }
+            let path = spill.next_path();
+            let mut run = tokio::io::BufWriter::new(tokio::fs::File::create(&path).await?);
+            for i in sorted {
+                run.write_all(&chunk[i * line_length..(i + 1) * line_length])
+                    .await?;
+            }
+            run.flush().await?;
+            chunk.clear();
+            sources.push(RunSource::File(path));
+        }
+    }
+    let runs = sources.len();
+    let by = LineOrder {
+        order: &order,
+        columns: &columns,
+        decoder: string_decoder,
+    };
+    let sources =
+        merge_passes(sources, options.max_open_runs, &mut spill, line_length, &by).await?;
+
+    let mut header = MemberHeader::from_meta(&meta);
+    header.observations = Some(rows);
+    let layout = layout(&meta.columns);
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(&mut out, header, meta.columns, string_encoder)?;
+    writer.start().await?;
+    let mut merge = Merge::new(sources, line_length, &by).await?;
+    let mut line = vec![0; line_length];
+    let mut out_line = vec![b' '; writer.line_length()];
+    while merge.next_line(&mut line).await? {
+        relayout(
+            &RowView::new(&line, &columns, string_decoder),
+            &layout,
+            &mut out_line,
+        );
+        writer.write_line(&out_line).await?;
+    }
+    writer.finish().await?;
+    Ok(SortReport { rows, runs })
+}
+
+/// Checks that the rows of `input` are ordered by `keys`, returning the
+/// 1-based number of the first row out of order.
+#[cfg(not(feature = "async"))]
+pub fn first_unsorted<P: AsRef<Path>>(
+    input: P,
+    keys: &[SortKey],
+    string_decoder: StringDecoder,
+) -> Result<Option<usize>, XPTError> {
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let order = SortOrder::new(&meta, keys)?;
+    let columns = data.columns().to_vec();
+    let mut previous: Option<Vec<u8>> = None;
+    let mut rows = 0;
+    while let Some(row) = data.read_row()? {
+        rows += 1;
+        if let Some(previous) = &previous {
+            let before = RowView::new(previous, &columns, string_decoder);
+            if order.compare(&before, &row).is_gt() {
+                return Ok(Some(rows));
+            }
+        }
+        previous = Some(row.line().to_vec());
+    }
+    Ok(None)
+}
+
+/// Checks that the rows of `input` are ordered by `keys`, returning the
+/// 1-based number of the first row out of order.
+#[cfg(feature = "async")]
+pub async fn first_unsorted<P: AsRef<Path>>(
+    input: P,
+    keys: &[SortKey],
+    string_decoder: StringDecoder,
+) -> Result<Option<usize>, XPTError> {
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let order = SortOrder::new(&meta, keys)?;
+    let columns = data.columns().to_vec();
+    let mut previous: Option<Vec<u8>> = None;
+    let mut rows = 0;
+    while let Some(row) = data.read_row().await? {
+        rows += 1;
+        if let Some(previous) = &previous {
+            let before = RowView::new(previous, &columns, string_decoder);
+            if order.compare(&before, &row).is_gt() {
+                return Ok(Some(rows));
+            }
+        }
+        previous = Some(row.line().to_vec());
+    }
+    Ok(None)
+}
+
+/// Whether the rows of `input` are ordered by `keys`.
+#[cfg(not(feature = "async"))]
+pub fn is_sorted_by<P: AsRef<Path>>(
+    input: P,
+    keys: &[SortKey],
+    string_decoder: StringDecoder,
+) -> Result<bool, XPTError> {
+    Ok(first_unsorted(input, keys, string_decoder)?.is_none())
+}
+
+/// Whether the rows of `input` are ordered by `keys`.
+#[cfg(feature = "async")]
+pub async fn is_sorted_by<P: AsRef<Path>>(
+    input: P,
+    keys: &[SortKey],
+    string_decoder: StringDecoder,
+) -> Result<bool, XPTError> {
+    Ok(first_unsorted(input, keys, string_decoder).await?.is_none())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sort::SortKey;
+
+    #[test]
+    fn test_parse_by() {
+        assert_eq!(
+            SortKey::parse_by("STUDYID, USUBJID descending LBSEQ").unwrap(),
+            vec![
+                SortKey::ascending("STUDYID"),
+                SortKey::ascending("USUBJID"),
+                SortKey::descending("LBSEQ"),
+            ]
+        );
+        assert!(SortKey::parse_by("LBSEQ DESCENDING").is_err());
+        assert!(SortKey::parse_by(" ").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_sort_file() {
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::sort::{first_unsorted, is_sorted_by, sort_file, SortOptions};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let by = |by: &str| SortKey::parse_by(by).unwrap();
+        assert!(is_sorted_by("sample/LB2.xpt", &by("USUBJID LBSEQ"), UTF8_STRING_DECODER).unwrap());
+        assert_eq!(
+            first_unsorted("sample/LB2.xpt", &by("LBTESTCD"), UTF8_STRING_DECODER).unwrap(),
+            Some(4)
+        );
+
+        // two rows per run forces five runs spilled and merged
+        let options = SortOptions {
+            max_memory: 2 * 137,
+            ..SortOptions::default()
+        };
+        let keys = by("LBTESTCD descending LBSTRESN");
+        let output = std::env::temp_dir().join("xpt_r_sort_lb2.xpt");
+        let report = sort_file(
+            "sample/LB2.xpt",
+            &output,
+            &keys,
+            &options,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!((report.rows, report.runs), (9, 5));
+        assert!(is_sorted_by(&output, &keys, UTF8_STRING_DECODER).unwrap());
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (mut data, _) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        let mut seqs = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            seqs.push(row[3].to_string());
+        }
+        // the missing ALT result of 307 sorts last when descending
+        assert_eq!(
+            seqs,
+            vec!["204", "101", "307", "308", "102", "205", "309", "206", "103"]
+        );
+
+        let in_memory = std::env::temp_dir().join("xpt_r_sort_lb2_memory.xpt");
+        let report = sort_file(
+            "sample/LB2.xpt",
+            &in_memory,
+            &keys,
+            &SortOptions::default(),
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(report.runs, 1);
+        assert_eq!(
+            std::fs::read(&in_memory).unwrap(),
+            std::fs::read(&output).unwrap()
+        );
+
+        // two runs open at a time merges the five runs in three passes
+        let passes = std::env::temp_dir().join("xpt_r_sort_lb2_passes.xpt");
+        let options = SortOptions {
+            max_memory: 2 * 137,
+            max_open_runs: 2,
+            ..SortOptions::default()
+        };
+        let report = sort_file(
+            "sample/LB2.xpt",
+            &passes,
+            &keys,
+            &options,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!((report.rows, report.runs), (9, 5));
+        assert_eq!(
+            std::fs::read(&passes).unwrap(),
+            std::fs::read(&output).unwrap()
+        );
+
+        // three rows per run spills all nine rows, with no run left in memory
+        let exact = std::env::temp_dir().join("xpt_r_sort_lb2_exact.xpt");
+        let options = SortOptions {
+            max_memory: 3 * 137,
+            ..SortOptions::default()
+        };
+        let report = sort_file(
+            "sample/LB2.xpt",
+            &exact,
+            &keys,
+            &options,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!((report.rows, report.runs), (9, 3));
+        assert_eq!(
+            std::fs::read(&exact).unwrap(),
+            std::fs::read(&output).unwrap()
+        );
+        std::fs::remove_file(&output).unwrap();
+        std::fs::remove_file(&in_memory).unwrap();
+        std::fs::remove_file(&passes).unwrap();
+        std::fs::remove_file(&exact).unwrap();
+    }
+}
@@ -0,0 +1,592 @@
+use crate::error::XPTError;
+use crate::part::{ColumnMeta, DocumentMeta, StringDecoder, StringEncoder};
+use crate::reader::{RawReader, Reader};
+use crate::writer::{header_length, layout, relayout, MemberHeader, Writer};
+use std::path::{Path, PathBuf};
+
+/// Largest part written by [`split_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitLimit {
+    /// File size in bytes, headers included.
+    Bytes(u64),
+    Rows(usize),
+}
+
+/// One part of a split dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitPart {
+    /// File name, relative to the manifest.
+    pub file: String,
+    /// Observation number of the first row in the source, from 1.
+    pub first_row: usize,
+    pub rows: usize,
+    pub bytes: u64,
+}
+
+/// The parts of a split dataset in order, stored next to them as CSV.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitManifest {
+    pub parts: Vec<SplitPart>,
+}
+
+const MANIFEST_HEADER: &str = "file,first_row,rows,bytes";
+
+impl SplitManifest {
+    pub fn rows(&self) -> usize {
+        self.parts.iter().map(|part| part.rows).sum()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", MANIFEST_HEADER);
+        for part in &self.parts {
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                part.file, part.first_row, part.rows, part.bytes
+            ));
+        }
+        csv
+    }
+
+    /// Reads the CSV written by [`SplitManifest::to_csv`]. File names are
+    /// taken as everything before the last three fields, so they may hold
+    /// commas.
+    pub fn parse(csv: &str) -> Result<Self, XPTError> {
+        let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+        if lines.next().map(str::trim) != Some(MANIFEST_HEADER) {
+            return Err(XPTError::ParseError(format!(
+                "manifest does not start with {}",
+                MANIFEST_HEADER
+            )));
+        }
+        let parts = lines
+            .map(|line| {
+                let fields: Vec<&str> = line.rsplitn(4, ',').collect();
+                let number = |i: usize| {
+                    fields
+                        .get(i)
+                        .and_then(|field| field.trim().parse().ok())
+                        .ok_or_else(|| XPTError::ParseError(format!("bad manifest line {}", line)))
+                };
+                Ok(SplitPart {
+                    file: fields.get(3).map_or("", |file| file).to_string(),
+                    first_row: number(2)? as usize,
+                    rows: number(1)? as usize,
+                    bytes: number(0)?,
+                })
+            })
+            .collect::<Result<_, XPTError>>()?;
+        Ok(SplitManifest { parts })
+    }
+}
+
+/// Observations per part for `limit`, given the header length of a part.
+pub fn rows_per_part(
+    limit: SplitLimit,
+    header_length: usize,
+    line_length: usize,
+) -> Result<usize, XPTError> {
+    match limit {
+        SplitLimit::Rows(0) => Err(XPTError::EncodeError(
+            "parts must hold at least one row".to_string(),
+        )),
+        SplitLimit::Rows(rows) => Ok(rows),
+        SplitLimit::Bytes(_) if line_length == 0 => Ok(usize::MAX),
+        SplitLimit::Bytes(bytes) => {
+            // the data is padded to whole 80-byte records
+            let data = bytes.saturating_sub(header_length as u64) / 80 * 80;
+            match (data / line_length as u64) as usize {
+                0 => Err(XPTError::EncodeError(format!(
+                    "{} bytes do not hold the {} byte header and one row",
+                    bytes, header_length
+                ))),
+                rows => Ok(rows),
+            }
+        }
+    }
+}
+
+fn part_name(stem: &str, index: usize) -> String {
+    format!("{}_{}.xpt", stem, index)
+}
+
+fn manifest_path(directory: &Path, stem: &str) -> PathBuf {
+    directory.join(format!("{}_manifest.csv", stem))
+}
+
+fn stem(input: &Path) -> String {
+    input.file_stem().map_or("part".to_string(), |stem| {
+        stem.to_string_lossy().to_string()
+    })
+}
+
+fn check_part(first: &[ColumnMeta], meta: &DocumentMeta, path: &Path) -> Result<(), XPTError> {
+    let same = first.len() == meta.columns.len()
+        && first.iter().zip(&meta.columns).all(|(a, b)| {
+            a.name == b.name && a.column_type == b.column_type && a.length == b.length
+        });
+    if !same {
+        return Err(XPTError::ParseError(format!(
+            "{}: columns differ from the first part",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+fn check_rows(path: &Path, rows: usize, expected: Option<usize>) -> Result<(), XPTError> {
+    match expected {
+        Some(expected) if expected != rows => Err(XPTError::ParseError(format!(
+            "{}: {} rows, the manifest lists {}",
+            path.display(),
+            rows,
+            expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Copies the next observation of `data` into `line`, laid out as `layout`.
+#[cfg(not(feature = "async"))]
+fn next_line(
+    data: &mut RawReader,
+    layout: &[(u32, u16, u16)],
+    line: &mut [u8],
+) -> Result<bool, XPTError> {
+    Ok(match data.read_row()? {
+        Some(row) => {
+            relayout(&row, layout, line);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Copies the next observation of `data` into `line`, laid out as `layout`.
+#[cfg(feature = "async")]
+async fn next_line(
+    data: &mut RawReader<'_>,
+    layout: &[(u32, u16, u16)],
+    line: &mut [u8],
+) -> Result<bool, XPTError> {
+    Ok(match data.read_row().await? {
+        Some(row) => {
+            relayout(&row, layout, line);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Splits the file at `input` into parts `<stem>_1.xpt`, `<stem>_2.xpt`, …
+/// in `directory`, each a complete transport file within `limit`, and
+/// writes the manifest `<stem>_manifest.csv` beside them.
+#[cfg(not(feature = "async"))]
+pub fn split_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    directory: Q,
+    limit: SplitLimit,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<SplitManifest, XPTError> {
+    let stem = stem(input.as_ref());
+    let directory = directory.as_ref();
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let total = Some(data.observations()).filter(|rows| *rows > 0);
+    let header = MemberHeader::from_meta(&meta);
+    let layout = layout(&meta.columns);
+    let mut line = vec![b' '; meta.columns.iter().map(|c| c.length as usize).sum()];
+    let rows_per_part = rows_per_part(
+        limit,
+        header_length(&header, &meta.columns, string_encoder)?,
+        line.len(),
+    )?;
+
+    let mut manifest = SplitManifest::default();
+    let mut more = next_line(&mut data, &layout, &mut line)?;
+    let mut first_row = 1;
+    loop {
+        let name = part_name(&stem, manifest.parts.len() + 1);
+        let mut out = std::io::BufWriter::new(std::fs::File::create(directory.join(&name))?);
+        let mut header = header.clone();
+        header.observations =
+            total.map(|total| rows_per_part.min((total + 1).saturating_sub(first_row)));
+        let mut writer = Writer::new(&mut out, header, meta.columns.clone(), string_encoder)?;
+        writer.start()?;
+        while more && writer.rows() < rows_per_part {
+            writer.write_line(&line)?;
+            more = next_line(&mut data, &layout, &mut line)?;
+        }
+        let rows = writer.rows();
+        let bytes = writer.finish()?;
+        manifest.parts.push(SplitPart {
+            file: name,
+            first_row,
+            rows,
+            bytes,
+        });
+        first_row += rows;
+        if !more {
+            break;
+        }
+    }
+    std::fs::write(manifest_path(directory, &stem), manifest.to_csv())?;
+    Ok(manifest)
+}
+
+/// Splits the file at `input` into parts `<stem>_1.xpt`, `<stem>_2.xpt`, …
+/// in `directory`, each a complete transport file within `limit`, and
+/// writes the manifest `<stem>_manifest.csv` beside them.
+#[cfg(feature = "async")]
+pub async fn split_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    directory: Q,
+    limit: SplitLimit,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<SplitManifest, XPTError> {
+    let stem = stem(input.as_ref());
+    let directory = directory.as_ref();
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let total = Some(data.observations()).filter(|rows| *rows > 0);
+    let header = MemberHeader::from_meta(&meta);
+    let layout = layout(&meta.columns);
+    let mut line = vec![b' '; meta.columns.iter().map(|c| c.length as usize).sum()];
+    let rows_per_part = rows_per_part(
+        limit,
+        header_length(&header, &meta.columns, string_encoder)?,
+        line.len(),
+    )?;
+
+    let mut manifest = SplitManifest::default();
+    let mut more = next_line(&mut data, &layout, &mut line).await?;
+    let mut first_row = 1;
+    loop {
+        let name = part_name(&stem, manifest.parts.len() + 1);
+        let mut out =
+            tokio::io::BufWriter::new(tokio::fs::File::create(directory.join(&name)).await?);
+        let mut header = header.clone();
+        header.observations =
+            total.map(|total| rows_per_part.min((total + 1).saturating_sub(first_row)));
+        let mut writer = Writer::new(&mut out, header, meta.columns.clone(), string_encoder)?;
+        writer.start().await?;
+        while more && writer.rows() < rows_per_part {
+            writer.write_line(&line).await?;
+            more = next_line(&mut data, &layout, &mut line).await?;
+        }
+        let rows = writer.rows();
+        let bytes = writer.finish().await?;
+        manifest.parts.push(SplitPart {
+            file: name,
+            first_row,
+            rows,
+            bytes,
+        });
+        first_row += rows;
+        if !more {
+            break;
+        }
+    }
+    tokio::fs::write(manifest_path(directory, &stem), manifest.to_csv()).await?;
+    Ok(manifest)
+}
+
+/// Concatenates `parts`, which must have the same columns, into `output`.
+/// Returns the number of rows written.
+#[cfg(not(feature = "async"))]
+pub fn merge_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    parts: &[P],
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let parts: Vec<(PathBuf, Option<usize>)> = parts
+        .iter()
+        .map(|part| (part.as_ref().to_path_buf(), None))
+        .collect();
+    merge(&parts, output.as_ref(), string_decoder, string_encoder)
+}
+
+/// Concatenates `parts`, which must have the same columns, into `output`.
+/// Returns the number of rows written.
+#[cfg(feature = "async")]
+pub async fn merge_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    parts: &[P],
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let parts: Vec<(PathBuf, Option<usize>)> = parts
+        .iter()
+        .map(|part| (part.as_ref().to_path_buf(), None))
+        .collect();
+    merge(&parts, output.as_ref(), string_decoder, string_encoder).await
+}
+
+/// Merges the parts listed in the manifest at `manifest` back into
+/// `output`, checking the row count of every part.
+#[cfg(not(feature = "async"))]
+pub fn merge_manifest<P: AsRef<Path>, Q: AsRef<Path>>(
+    manifest: P,
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let directory = manifest.as_ref().parent().unwrap_or(Path::new(""));
+    let manifest = SplitManifest::parse(&std::fs::read_to_string(manifest.as_ref())?)?;
+    let parts: Vec<(PathBuf, Option<usize>)> = manifest
+        .parts
+        .iter()
+        .map(|part| (directory.join(&part.file), Some(part.rows)))
+        .collect();
+    merge(&parts, output.as_ref(), string_decoder, string_encoder)
+}
+
+/// Merges the parts listed in the manifest at `manifest` back into
+/// `output`, checking the row count of every part.
+#[cfg(feature = "async")]
+pub async fn merge_manifest<P: AsRef<Path>, Q: AsRef<Path>>(
+    manifest: P,
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let directory = manifest.as_ref().parent().unwrap_or(Path::new(""));
+    let manifest = SplitManifest::parse(&tokio::fs::read_to_string(manifest.as_ref()).await?)?;
+    let parts: Vec<(PathBuf, Option<usize>)> = manifest
+        .parts
+        .iter()
+        .map(|part| (directory.join(&part.file), Some(part.rows)))
+        .collect();
+    merge(&parts, output.as_ref(), string_decoder, string_encoder).await
+}
+
+#[cfg(not(feature = "async"))]
+fn merge(
+    parts: &[(PathBuf, Option<usize>)],
+    output: &Path,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let mut files = parts
+        .iter()
+        .map(|(path, _)| std::fs::File::open(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut readers = Vec::with_capacity(files.len());
+    let mut first: Option<DocumentMeta> = None;
+    for (file, (path, _)) in files.iter_mut().zip(parts) {
+        let (data, meta) = Reader::new(file, string_decoder).start()?;
+        match &first {
+            Some(first) => check_part(&first.columns, &meta, path)?,
+            None => first = Some(meta),
+        }
+        readers.push(data);
+    }
+    let meta = first.ok_or_else(|| XPTError::EncodeError("no parts to merge".to_string()))?;
+    let mut header = MemberHeader::from_meta(&meta);
+    header.observations = readers
+        .iter()
+        .map(|data| Some(data.observations()).filter(|rows| *rows > 0))
+        .sum();
+    let layout = layout(&meta.columns);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(&mut out, header, meta.columns.clone(), string_encoder)?;
+    writer.start()?;
+    let mut line = vec![b' '; writer.line_length()];
+    for (data, (path, expected)) in readers.iter_mut().zip(parts) {
+        let start = writer.rows();
+        while next_line(data, &layout, &mut line)? {
+            writer.write_line(&line)?;
+        }
+        check_rows(path, writer.rows() - start, *expected)?;
+    }
+    let rows = writer.rows();
+    writer.finish()?;
+    Ok(rows)
+}
+
+#[cfg(feature = "async")]
+async fn merge(
+    parts: &[(PathBuf, Option<usize>)],
+    output: &Path,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    let mut files = Vec::with_capacity(parts.len());
+    for (path, _) in parts {
+        files.push(tokio::fs::File::open(path).await?);
+    }
+    let mut readers = Vec::with_capacity(files.len());
+    let mut first: Option<DocumentMeta> = None;
+    for (file, (path, _)) in files.iter_mut().zip(parts) {
+        let (data, meta) = Reader::new(file, string_decoder).start().await?;
+        match &first {
+            Some(first) => check_part(&first.columns, &meta, path)?,
+            None => first = Some(meta),
+        }
+        readers.push(data);
+    }
+    let meta = first.ok_or_else(|| XPTError::EncodeError("no parts to merge".to_string()))?;
+    let mut header = MemberHeader::from_meta(&meta);
+    header.observations = readers
+        .iter()
+        .map(|data| Some(data.observations()).filter(|rows| *rows > 0))
+        .sum();
+    let layout = layout(&meta.columns);
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(&mut out, header, meta.columns.clone(), string_encoder)?;
+    writer.start().await?;
+    let mut line = vec![b' '; writer.line_length()];
+    for (data, (path, expected)) in readers.iter_mut().zip(parts) {
+        let start = writer.rows();
+        while next_line(data, &layout, &mut line).await? {
+            writer.write_line(&line).await?;
+        }
+        check_rows(path, writer.rows() - start, *expected)?;
+    }
+    let rows = writer.rows();
+    writer.finish().await?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::split::{rows_per_part, SplitLimit, SplitManifest, SplitPart};
+
+    #[test]
+    fn test_rows_per_part() {
+        assert_eq!(rows_per_part(SplitLimit::Rows(4), 2000, 137).unwrap(), 4);
+        assert!(rows_per_part(SplitLimit::Rows(0), 2000, 137).is_err());
+        // 1000 bytes of data hold 960 in whole records, 7 rows of 137
+        assert_eq!(
+            rows_per_part(SplitLimit::Bytes(3000), 2000, 137).unwrap(),
+            7
+        );
+        assert!(rows_per_part(SplitLimit::Bytes(2050), 2000, 137).is_err());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = SplitManifest {
+            parts: vec![
+                SplitPart {
+                    file: "lb,site_1.xpt".to_string(),
+                    first_row: 1,
+                    rows: 4,
+                    bytes: 2640,
+                },
+                SplitPart {
+                    file: "lb,site_2.xpt".to_string(),
+                    first_row: 5,
+                    rows: 5,
+                    bytes: 2720,
+                },
+            ],
+        };
+        let parsed = SplitManifest::parse(&manifest.to_csv()).unwrap();
+        assert_eq!(parsed, manifest);
+        assert_eq!(parsed.rows(), 9);
+        assert!(SplitManifest::parse("name,rows\n").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_split_and_merge() {
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::split::{merge_manifest, split_file};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let directory = std::env::temp_dir().join("xpt_r_split_lb2");
+        std::fs::create_dir_all(&directory).unwrap();
+        let manifest = split_file(
+            "sample/LB2.xpt",
+            &directory,
+            SplitLimit::Rows(4),
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        let rows: Vec<usize> = manifest.parts.iter().map(|part| part.rows).collect();
+        assert_eq!(rows, vec![4, 4, 1]);
+        assert_eq!(manifest.parts[2].first_row, 9);
+        for part in &manifest.parts {
+            let mut file = std::fs::File::open(directory.join(&part.file)).unwrap();
+            let (mut data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+            assert_eq!(meta.columns.len(), 9);
+            let mut count = 0;
+            while data.read_line().unwrap().is_some() {
+                count += 1;
+            }
+            assert_eq!(count, part.rows);
+        }
+
+        let merged = directory.join("LB2_merged.xpt");
+        let rows = merge_manifest(
+            directory.join("LB2_manifest.csv"),
+            &merged,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(rows, 9);
+        let mut original = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let mut copy = std::fs::File::open(&merged).unwrap();
+        let (mut a, _) = Reader::new(&mut original, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        let (mut b, _) = Reader::new(&mut copy, UTF8_STRING_DECODER).start().unwrap();
+        while let Some(row) = a.read_line().unwrap() {
+            assert_eq!(b.read_line().unwrap(), Some(row));
+        }
+        assert_eq!(b.read_line().unwrap(), None);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_split_and_merge_short_v5_rows() {
+        use crate::fixture::{short_rows, write_file};
+        use crate::part::DocumentHeader;
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::split::{merge_manifest, split_file};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        // 4-byte rows leave most of each part's last record as padding
+        let directory = std::env::temp_dir().join("xpt_r_split_short");
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("short.xpt");
+        let (columns, values) = short_rows();
+        write_file(&input, DocumentHeader::V5, "SHORT", columns, &values);
+
+        let manifest = split_file(
+            &input,
+            &directory,
+            SplitLimit::Rows(2),
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        let rows: Vec<usize> = manifest.parts.iter().map(|part| part.rows).collect();
+        assert_eq!(rows, vec![2, 1]);
+        let merged = directory.join("merged.xpt");
+        let rows = merge_manifest(
+            directory.join("short_manifest.csv"),
+            &merged,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(rows, 3);
+        let mut file = std::fs::File::open(&merged).unwrap();
+        let (mut data, _) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        let mut copied = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            copied.push(row);
+        }
+        assert_eq!(copied, values);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,483 @@
+use crate::compare::AttributeDifference;
+use crate::define::display_format;
+use crate::error::XPTError;
+use crate::part::{
+    ColumnMeta, ColumnType, DocumentHeader, DocumentMeta, StringDecoder, StringEncoder,
+};
+use crate::reader::Reader;
+use crate::view::RowView;
+use crate::writer::{layout, MemberHeader, Writer};
+use std::fmt::Display;
+use std::path::Path;
+
+/// An attribute of an input column that differs from the stacked column.
+///
+/// `OnlyInBase` marks a stacked column the input lacks; its rows get missing
+/// values. `Length` holds the stacked and the input length, `Label` and
+/// `Format` the kept and the input value. `Type` conflicts stop stacking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackDifference {
+    /// Index of the input, from 0.
+    pub input: usize,
+    pub column: String,
+    pub difference: AttributeDifference,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackReport {
+    pub columns: Vec<ColumnMeta>,
+    pub differences: Vec<StackDifference>,
+    /// Rows read from every input.
+    pub rows: Vec<usize>,
+}
+
+impl StackReport {
+    pub fn type_conflicts(&self) -> impl Iterator<Item = &StackDifference> {
+        self.differences
+            .iter()
+            .filter(|d| d.difference == AttributeDifference::Type)
+    }
+}
+
+impl Display for StackReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (input, rows) in self.rows.iter().enumerate() {
+            writeln!(f, "Input {}: {} observations", input + 1, rows)?;
+        }
+        writeln!(
+            f,
+            "Stacked: {} observations, {} variables",
+            self.rows.iter().sum::<usize>(),
+            self.columns.len()
+        )?;
+        if self.differences.is_empty() {
+            return Ok(());
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  {:<5} {:<10} {:<9} {:<40} Input",
+            "Input", "Variable", "Attribute", "Stacked"
+        )?;
+        for d in &self.differences {
+            let (attribute, stacked, input) = match &d.difference {
+                AttributeDifference::OnlyInBase => ("Missing", String::new(), String::new()),
+                AttributeDifference::OnlyInCompare => ("Added", String::new(), String::new()),
+                AttributeDifference::Type => ("Type", String::new(), String::new()),
+                AttributeDifference::Length(s, i) => ("Len", s.to_string(), i.to_string()),
+                AttributeDifference::Label(s, i) => ("Label", s.clone(), i.clone()),
+                AttributeDifference::Format(s, i) => ("Format", s.clone(), i.clone()),
+            };
+            let line = format!(
+                "  {:<5} {:<10} {:<9} {:<40} {}",
+                d.input + 1,
+                d.column,
+                attribute,
+                stacked,
+                input
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Harmonizes the columns of several datasets: the union of the columns in
+/// order of first appearance, matched by name ignoring case, with the type,
+/// label and format of the first input holding each column and the largest
+/// length of all.
+pub struct Stacker {
+    columns: Vec<ColumnMeta>,
+    layout: Vec<(u32, u16, u16)>,
+    // per input, the input column of every stacked column
+    sources: Vec<Vec<Option<usize>>>,
+    differences: Vec<StackDifference>,
+}
+
+impl Stacker {
+    pub fn new(metas: &[DocumentMeta]) -> Self {
+        let mut columns: Vec<ColumnMeta> = Vec::new();
+        for meta in metas {
+            for column in &meta.columns {
+                match columns
+                    .iter_mut()
+                    .find(|c| c.name.eq_ignore_ascii_case(&column.name))
+                {
+                    Some(stacked) if stacked.column_type == column.column_type => {
+                        stacked.length = stacked.length.max(column.length);
+                    }
+                    Some(_) => {}
+                    None => columns.push(column.clone()),
+                }
+            }
+        }
+        let mut sources = Vec::with_capacity(metas.len());
+        let mut differences = Vec::new();
+        for (input, meta) in metas.iter().enumerate() {
+            let mut source = Vec::with_capacity(columns.len());
+            for stacked in &columns {
+                let mut push = |difference| {
+                    differences.push(StackDifference {
+                        input,
+                        column: stacked.name.clone(),
+                        difference,
+                    })
+                };
+                let Some(i) = meta
+                    .columns
+                    .iter()
+                    .position(|c| c.name.eq_ignore_ascii_case(&stacked.name))
+                else {
+                    push(AttributeDifference::OnlyInBase);
+                    source.push(None);
+                    continue;
+                };
+                let column = &meta.columns[i];
+                source.push(Some(i));
+                if column.column_type != stacked.column_type {
+                    push(AttributeDifference::Type);
+                    continue;
+                }
+                if column.length != stacked.length {
+                    push(AttributeDifference::Length(stacked.length, column.length));
+                }
+                if column.label != stacked.label {
+                    push(AttributeDifference::Label(
+                        stacked.label.clone(),
+                        column.label.clone(),
+                    ));
+                }
+                let (stacked_format, format) = (
+                    display_format(&stacked.format),
+                    display_format(&column.format),
+                );
+                if stacked_format != format {
+                    push(AttributeDifference::Format(
+                        stacked_format.unwrap_or_default(),
+                        format.unwrap_or_default(),
+                    ));
+                }
+            }
+            sources.push(source);
+        }
+        Stacker {
+            layout: layout(&columns),
+            columns,
+            sources,
+            differences,
+        }
+    }
+
+    pub fn columns(&self) -> &[ColumnMeta] {
+        &self.columns
+    }
+
+    pub fn differences(&self) -> &[StackDifference] {
+        &self.differences
+    }
+
+    /// Fails on the first column declared with two types.
+    pub fn check_types(&self) -> Result<(), XPTError> {
+        match self
+            .differences
+            .iter()
+            .find(|d| d.difference == AttributeDifference::Type)
+        {
+            Some(d) => Err(XPTError::EncodeError(format!(
+                "column {} of input {} is not {:?}",
+                d.column,
+                d.input + 1,
+                self.columns
+                    .iter()
+                    .find(|c| c.name == d.column)
+                    .map_or(ColumnType::NUMERIC, |c| c.column_type)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Lays a row of input `input` out in the stacked columns. CHAR values
+    /// are padded with blanks, columns the input lacks are missing.
+    pub fn fill_line(&self, input: usize, row: &RowView, line: &mut [u8]) {
+        for ((npos, nlng, ntype), source) in self.layout.iter().zip(&self.sources[input]) {
+            let cell = &mut line[*npos as usize..*npos as usize + *nlng as usize];
+            let numeric = *ntype == ColumnType::NUMERIC as u16;
+            match source {
+                Some(i) if row.is_numeric(*i) == numeric => {
+                    let bytes = row.get_bytes(*i);
+                    let length = bytes.len().min(cell.len());
+                    cell[..length].copy_from_slice(&bytes[..length]);
+                    cell[length..].fill(if numeric { 0 } else { b' ' });
+                }
+                _ if numeric => {
+                    cell.fill(0);
+                    cell[0] = b'.';
+                }
+                _ => cell.fill(b' '),
+            }
+        }
+    }
+
+    /// Header for the stacked file, taken from the first input and written
+    /// as V8 when any input is.
+    pub fn header(&self, metas: &[DocumentMeta]) -> Option<MemberHeader> {
+        let mut header = MemberHeader::from_meta(metas.first()?);
+        if metas.iter().any(|meta| meta.version == DocumentHeader::V8) {
+            header.version = DocumentHeader::V8;
+        }
+        Some(header)
+    }
+}
+
+/// Stacks the files at `inputs` into `output` in order, as SAS's `SET`
+/// statement does with several datasets.
+#[cfg(not(feature = "async"))]
+pub fn stack_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<StackReport, XPTError> {
+    let mut files = inputs
+        .iter()
+        .map(std::fs::File::open)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut readers = Vec::with_capacity(files.len());
+    let mut metas = Vec::with_capacity(files.len());
+    for file in files.iter_mut() {
+        let (data, meta) = Reader::new(file, string_decoder).start()?;
+        readers.push(data);
+        metas.push(meta);
+    }
+    let stacker = Stacker::new(&metas);
+    stacker.check_types()?;
+    let mut header = stacker
+        .header(&metas)
+        .ok_or_else(|| XPTError::EncodeError("no datasets to stack".to_string()))?;
+    if header.version == DocumentHeader::V8 {
+        let mut observations = 0;
+        for (input, data) in inputs.iter().zip(&readers) {
+            observations += match data.observations() {
+                0 => count_rows(input, string_decoder)?,
+                count => count,
+            };
+        }
+        header.observations = Some(observations);
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(&mut out, header, stacker.columns.clone(), string_encoder)?;
+    writer.start()?;
+    let mut line = vec![b' '; writer.line_length()];
+    let mut rows = Vec::with_capacity(readers.len());
+    for (input, data) in readers.iter_mut().enumerate() {
+        let start = writer.rows();
+        while let Some(row) = data.read_row()? {
+            stacker.fill_line(input, &row, &mut line);
+            writer.write_line(&line)?;
+        }
+        rows.push(writer.rows() - start);
+    }
+    writer.finish()?;
+    Ok(StackReport {
+        columns: stacker.columns,
+        differences: stacker.differences,
+        rows,
+    })
+}
+
+/// Stacks the files at `inputs` into `output` in order, as SAS's `SET`
+/// statement does with several datasets.
+#[cfg(feature = "async")]
+pub async fn stack_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    output: Q,
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<StackReport, XPTError> {
+    let mut files = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        files.push(tokio::fs::File::open(input).await?);
+    }
+    let mut readers = Vec::with_capacity(files.len());
+    let mut metas = Vec::with_capacity(files.len());
+    for file in files.iter_mut() {
+        let (data, meta) = Reader::new(file, string_decoder).start().await?;
+        readers.push(data);
+        metas.push(meta);
+    }
+    let stacker = Stacker::new(&metas);
+    stacker.check_types()?;
+    let mut header = stacker
+        .header(&metas)
+        .ok_or_else(|| XPTError::EncodeError("no datasets to stack".to_string()))?;
+    if header.version == DocumentHeader::V8 {
+        let mut observations = 0;
+        for (input, data) in inputs.iter().zip(&readers) {
+            observations += match data.observations() {
+                0 => count_rows(input, string_decoder).await?,
+                count => count,
+            };
+        }
+        header.observations = Some(observations);
+    }
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(&mut out, header, stacker.columns.clone(), string_encoder)?;
+    writer.start().await?;
+    let mut line = vec![b' '; writer.line_length()];
+    let mut rows = Vec::with_capacity(readers.len());
+    for (input, data) in readers.iter_mut().enumerate() {
+        let start = writer.rows();
+        while let Some(row) = data.read_row().await? {
+            stacker.fill_line(input, &row, &mut line);
+            writer.write_line(&line).await?;
+        }
+        rows.push(writer.rows() - start);
+    }
+    writer.finish().await?;
+    Ok(StackReport {
+        columns: stacker.columns,
+        differences: stacker.differences,
+        rows,
+    })
+}
+
+/// Observations of the file at `input`, read through when its header does
+/// not record them.
+#[cfg(not(feature = "async"))]
+fn count_rows<P: AsRef<Path>>(input: P, string_decoder: StringDecoder) -> Result<usize, XPTError> {
+    let mut file = std::fs::File::open(input)?;
+    let (mut data, _) = Reader::new(&mut file, string_decoder).start()?;
+    let mut rows = 0;
+    while data.read_row()?.is_some() {
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// Observations of the file at `input`, read through when its header does
+/// not record them.
+#[cfg(feature = "async")]
+async fn count_rows<P: AsRef<Path>>(
+    input: P,
+    string_decoder: StringDecoder,
+) -> Result<usize, XPTError> {
+    let mut file = tokio::fs::File::open(input).await?;
+    let (mut data, _) = Reader::new(&mut file, string_decoder).start().await?;
+    let mut rows = 0;
+    while data.read_row().await?.is_some() {
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_stacker() {
+        use crate::compare::AttributeDifference;
+        use crate::part::ColumnType;
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::stack::Stacker;
+
+        let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+        let (_, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        let mut other = meta.clone();
+        other.columns.remove(7);
+        other.columns[5].length = 60;
+        other.columns[5].name = "lbtest".to_string();
+        other.columns[1].label = "Domain".to_string();
+        other.columns[3].column_type = ColumnType::CHAR;
+        let mut extra = other.columns[0].clone();
+        extra.name = "SITEID".to_string();
+        other.columns.push(extra);
+
+        let stacker = Stacker::new(&[meta, other]);
+        let names: Vec<&str> = stacker.columns().iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names.len(), 10);
+        assert_eq!(names[5], "LBTEST");
+        assert_eq!(names[9], "SITEID");
+        assert_eq!(stacker.columns()[5].length, 60);
+        let differences: Vec<(usize, &str, &AttributeDifference)> = stacker
+            .differences()
+            .iter()
+            .map(|d| (d.input, d.column.as_str(), &d.difference))
+            .collect();
+        assert!(differences.contains(&(0, "SITEID", &AttributeDifference::OnlyInBase)));
+        assert!(differences.contains(&(1, "LBSTRESN", &AttributeDifference::OnlyInBase)));
+        assert!(differences.contains(&(0, "LBTEST", &AttributeDifference::Length(60, 40))));
+        assert!(differences.contains(&(1, "LBSEQ", &AttributeDifference::Type)));
+        assert!(differences.iter().any(|d| d.1 == "DOMAIN"
+            && matches!(d.2, AttributeDifference::Label(_, label) if label == "Domain")));
+        assert!(stacker.check_types().is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_stack_files() {
+        use crate::fixture::{column, write_file};
+        use crate::part::{ColumnType, DocumentHeader};
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::shrink::shrink_file;
+        use crate::stack::stack_files;
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let directory = std::env::temp_dir().join("xpt_r_stack_lb2");
+        std::fs::create_dir_all(&directory).unwrap();
+        let shrunk = directory.join("lb_shrunk.xpt");
+        shrink_file(
+            "sample/LB2.xpt",
+            &shrunk,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        let output = directory.join("lb_stacked.xpt");
+        let report = stack_files(
+            &[shrunk.as_path(), std::path::Path::new("sample/LB2.xpt")],
+            &output,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(report.rows, vec![9, 9]);
+        assert_eq!(report.columns[5].length, 40);
+        assert_eq!(report.type_conflicts().count(), 0);
+
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (mut data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(meta.columns[5].length, 40);
+        let mut rows = Vec::new();
+        while let Some(row) = data.read_line().unwrap() {
+            rows.push(row);
+        }
+        assert_eq!(rows.len(), 18);
+        assert_eq!(rows[1], rows[10]);
+        assert_eq!(
+            rows[1][5],
+            Val::Char("Aspartate Aminotransferase".to_string())
+        );
+
+        // a V8 input makes the output V8, and OBSV8 counts the V5 rows too
+        let v8 = directory.join("dm8.xpt");
+        let columns = vec![column("USUBJID", ColumnType::CHAR, 11)];
+        let dm = vec![
+            vec![Val::Char("01-701-1015".to_string())],
+            vec![Val::Char("01-701-1023".to_string())],
+        ];
+        write_file(&v8, DocumentHeader::V8, "DM", columns, &dm);
+        let report = stack_files(
+            &[std::path::Path::new("sample/LB2.xpt"), v8.as_path()],
+            &output,
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        assert_eq!(report.rows, vec![9, 2]);
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(meta.version, DocumentHeader::V8);
+        assert_eq!(data.observations(), 11);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,801 @@
+use crate::define::{civil_from_days, days_from_civil, display_format};
+use crate::error::XPTError;
+use crate::number::number2byte;
+use crate::part::{
+    parse_format, ColumnMeta, ColumnType, DocumentMeta, StringDecoder, StringEncoder,
+};
+use crate::reader::Reader;
+use crate::view::RowView;
+use crate::writer::{check_columns, layout, MemberHeader, Writer, MONTHS};
+use std::fmt::Display;
+use std::path::Path;
+
+/// Days from 1960-01-01, the SAS date origin, to 1970-01-01.
+const SAS_EPOCH_DAYS: i64 = 3653;
+
+/// SAS date values, from 15OCT1582 to 31DEC20000.
+const SAS_DAYS: std::ops::RangeInclusive<i64> = -137_774..=6_589_340;
+
+/// A change to the columns of a dataset, applied in order while copying it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transform {
+    Rename {
+        from: String,
+        to: String,
+    },
+    Relabel {
+        name: String,
+        label: String,
+    },
+    /// Sets the format, such as `DATE9.` or `8.2`, or removes it when empty.
+    Reformat {
+        name: String,
+        format: String,
+    },
+    /// Moves the listed columns to the front in the given order, the others
+    /// following in their current order.
+    Reorder(Vec<String>),
+    Drop(Vec<String>),
+    /// Writes a NUMERIC column as CHAR with a format such as `8.2`,
+    /// `BEST12.`, `DATE9.` or `E8601DA.`. Missing values become blank. The
+    /// length defaults to the format width.
+    ToChar {
+        name: String,
+        format: String,
+        length: Option<u16>,
+    },
+    /// Reads a CHAR column as NUMERIC with an informat such as `BEST32.` or
+    /// `E8601DA.`. Blank values become missing, as do values the informat
+    /// cannot read, which are counted in the report. A date or time
+    /// informat also becomes the format of the column.
+    ToNumeric {
+        name: String,
+        informat: String,
+    },
+}
+
+/// How a SAS format or informat reads and writes values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    /// `w.d`, `BESTw.` and `Zw.d`.
+    Number { zero_pad: bool, best: bool },
+    /// `DATEw.`, `ddMMMyyyy`.
+    Date,
+    /// `E8601DAw.`, `IS8601DAw.` and `YYMMDDw.`, `yyyy-mm-dd`.
+    IsoDate,
+    /// `E8601DTw.` and `IS8601DTw.`, `yyyy-mm-ddThh:mm:ss`.
+    IsoDateTime,
+    /// `TIMEw.`, `E8601TMw.` and `IS8601TMw.`, `hh:mm:ss`.
+    Time,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SasFormat {
+    style: Style,
+    width: u16,
+    decimals: u16,
+}
+
+impl SasFormat {
+    fn new(format: &str) -> Result<Self, XPTError> {
+        let (name, width, decimals) = parse_format(format)?;
+        let (style, default_width) = match name.as_str() {
+            "" | "F" => (
+                Style::Number {
+                    zero_pad: false,
+                    best: false,
+                },
+                12,
+            ),
+            "BEST" => (
+                Style::Number {
+                    zero_pad: false,
+                    best: true,
+                },
+                12,
+            ),
+            "Z" => (
+                Style::Number {
+                    zero_pad: true,
+                    best: false,
+                },
+                12,
+            ),
+            "DATE" => (Style::Date, 7),
+            "E8601DA" | "IS8601DA" | "YYMMDD" => (Style::IsoDate, 10),
+            "E8601DT" | "IS8601DT" => (Style::IsoDateTime, 19),
+            "TIME" | "E8601TM" | "IS8601TM" => (Style::Time, 8),
+            _ => {
+                return Err(XPTError::ParseError(format!(
+                    "format {} is not supported",
+                    format
+                )))
+            }
+        };
+        Ok(SasFormat {
+            style,
+            width: if width == 0 { default_width } else { width },
+            decimals,
+        })
+    }
+
+    /// Formats `value` left-aligned, `None` when it does not fit the width.
+    fn format(&self, value: Option<f64>) -> Option<String> {
+        let Some(value) = value else {
+            return Some(String::new());
+        };
+        let width = self.width as usize;
+        // days from 1970-01-01, `None` outside the dates SAS supports
+        let days = |days: f64| {
+            let days = days.floor();
+            (days >= *SAS_DAYS.start() as f64 && days <= *SAS_DAYS.end() as f64)
+                .then(|| days as i64 - SAS_EPOCH_DAYS)
+        };
+        let str = match self.style {
+            Style::Number { zero_pad, best } => {
+                if best {
+                    let mut str = value.to_string();
+                    let mut decimals = width;
+                    while str.len() > width && decimals > 0 {
+                        decimals -= 1;
+                        str = format!("{:.*}", decimals, value);
+                    }
+                    str
+                } else if zero_pad {
+                    format!("{:0width$.*}", self.decimals as usize, value, width = width)
+                } else {
+                    format!("{:.*}", self.decimals as usize, value)
+                }
+            }
+            Style::Date => {
+                let (year, month, day) = civil_from_days(days(value)?);
+                if width >= 9 {
+                    format!("{:02}{}{:04}", day, MONTHS[month as usize - 1], year)
+                } else {
+                    format!("{:02}{}{:02}", day, MONTHS[month as usize - 1], year % 100)
+                }
+            }
+            Style::IsoDate => {
+                let (year, month, day) = civil_from_days(days(value)?);
+                format!("{:04}-{:02}-{:02}", year, month, day)
+            }
+            Style::IsoDateTime => {
+                let (year, month, day) = civil_from_days(days(value / 86400.0)?);
+                let time = (value.floor() as i64).rem_euclid(86400);
+                format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    year,
+                    month,
+                    day,
+                    time / 3600,
+                    time / 60 % 60,
+                    time % 60
+                )
+            }
+            Style::Time => {
+                let seconds = value.floor() as i64;
+                let str = format!(
+                    "{:02}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                );
+                if width < 8 {
+                    str[..5].to_string()
+                } else {
+                    str
+                }
+            }
+        };
+        (str.len() <= width).then_some(str)
+    }
+
+    /// Reads a value, `Some(None)` for blank and `None` when unreadable.
+    fn read(&self, str: &str) -> Option<Option<f64>> {
+        let str = str.trim();
+        if str.is_empty() || str == "." {
+            return Some(None);
+        }
+        let number = |str: &str| str.parse::<i64>().ok();
+        let date = |year: i64, month: i64, day: i64| {
+            if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+                return None;
+            }
+            let days = days_from_civil(year, month, day) + SAS_EPOCH_DAYS;
+            // the round trip rejects days past the end of the month
+            (civil_from_days(days - SAS_EPOCH_DAYS) == (year, month, day)
+                && SAS_DAYS.contains(&days))
+            .then_some(days as f64)
+        };
+        let iso_date = |str: &str| {
+            let mut parts = str.splitn(3, '-');
+            let year = parts.next().filter(|y| y.len() == 4).and_then(number)?;
+            let month = parts.next().filter(|m| m.len() == 2).and_then(number)?;
+            let day = parts.next().filter(|d| d.len() == 2).and_then(number)?;
+            date(year, month, day)
+        };
+        let time = |str: &str| {
+            let mut parts = str.split(':');
+            let hours = parts.next().and_then(number)?;
+            let minutes = parts.next().filter(|m| m.len() == 2).and_then(number)?;
+            let seconds = match parts.next() {
+                Some(seconds) => seconds.parse::<f64>().ok()?,
+                None => 0.0,
+            };
+            let whole = hours.checked_mul(3600)?.checked_add(minutes * 60)?;
+            (parts.next().is_none() && minutes < 60 && seconds < 60.0)
+                .then_some(whole as f64 + seconds)
+        };
+        let value = match self.style {
+            Style::Number { .. } => {
+                let value = str.parse::<f64>().ok().filter(|v| v.is_finite())?;
+                // as in SAS, `w.d` places the decimal point when the text has none
+                if self.decimals > 0 && !str.contains(['.', 'e', 'E']) {
+                    value / 10f64.powi(self.decimals as i32)
+                } else {
+                    value
+                }
+            }
+            Style::Date => {
+                let upper = str.to_ascii_uppercase();
+                let (day, rest) = upper.split_at(upper.find(|c: char| c.is_ascii_alphabetic())?);
+                let month = MONTHS.iter().position(|m| rest.starts_with(m))? as i64 + 1;
+                let year = number(rest.get(3..)?)?;
+                let year = match rest.len() - 3 {
+                    4 => year,
+                    // two-digit years fall in 1940 to 2039, SAS's default YEARCUTOFF=1940
+                    2 if year < 40 => 2000 + year,
+                    2 => 1900 + year,
+                    _ => return None,
+                };
+                date(year, month, number(day)?)?
+            }
+            Style::IsoDate => iso_date(str)?,
+            Style::IsoDateTime => {
+                let (day, clock) = str.split_once('T')?;
+                let days = iso_date(day)?;
+                days * 86400.0 + time(clock)?
+            }
+            Style::Time => time(str)?,
+        };
+        Some(Some(value))
+    }
+
+    fn is_temporal(&self) -> bool {
+        !matches!(self.style, Style::Number { .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Conversion {
+    Copy,
+    ToChar(SasFormat),
+    ToNumeric(SasFormat),
+}
+
+/// Values a conversion could not write or read, per column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidValues {
+    pub column: String,
+    pub count: usize,
+    /// The first such value, as read.
+    pub example: String,
+}
+
+/// Outcome of [`transform_file`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformReport {
+    pub columns: Vec<ColumnMeta>,
+    pub rows: usize,
+    pub invalid: Vec<InvalidValues>,
+}
+
+impl Display for TransformReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} observations, {} variables",
+            self.rows,
+            self.columns.len()
+        )?;
+        for (i, column) in self.columns.iter().enumerate() {
+            let line = format!(
+                "  {:>3}  {:<8} {:<4} {:>5}  {:<10} {}",
+                i + 1,
+                column.name,
+                match column.column_type {
+                    ColumnType::NUMERIC => "Num",
+                    ColumnType::CHAR => "Char",
+                },
+                column.length,
+                display_format(&column.format).unwrap_or_default(),
+                column.label
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        for invalid in &self.invalid {
+            writeln!(
+                f,
+                "{}: {} values not converted, first {:?}",
+                invalid.column, invalid.count, invalid.example
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies [`Transform`]s to the columns of a dataset and lays its rows out
+/// in the transformed columns, positions recomputed.
+pub struct Transformer {
+    columns: Vec<ColumnMeta>,
+    layout: Vec<(u32, u16, u16)>,
+    // the input column of every output column and its conversion
+    sources: Vec<(usize, Conversion)>,
+    invalid: Vec<Option<InvalidValues>>,
+}
+
+impl Transformer {
+    pub fn new(meta: &DocumentMeta, transforms: &[Transform]) -> Result<Self, XPTError> {
+        let mut columns: Vec<(ColumnMeta, usize, Conversion)> = meta
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| (column.clone(), i, Conversion::Copy))
+            .collect();
+        let find = |columns: &[(ColumnMeta, usize, Conversion)], name: &str| {
+            columns
+                .iter()
+                .position(|(c, _, _)| c.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| XPTError::ParseError(format!("unknown column {}", name)))
+        };
+        for transform in transforms {
+            match transform {
+                Transform::Rename { from, to } => {
+                    let i = find(&columns, from)?;
+                    if !is_sas_name(to) {
+                        return Err(XPTError::ParseError(format!(
+                            "{:?} is not a valid variable name",
+                            to
+                        )));
+                    }
+                    if find(&columns, to).is_ok_and(|j| j != i) {
+                        return Err(XPTError::ParseError(format!("column {} exists", to)));
+                    }
+                    columns[i].0.name = to.clone();
+                }
+                Transform::Relabel { name, label } => {
+                    let i = find(&columns, name)?;
+                    columns[i].0.label = label.clone();
+                }
+                Transform::Reformat { name, format } => {
+                    let i = find(&columns, name)?;
+                    columns[i].0.format = parse_format(format)?;
+                }
+                Transform::Reorder(names) => {
+                    let mut reordered = Vec::with_capacity(columns.len());
+                    for name in names {
+                        let i = find(&columns, name)?;
+                        reordered.push(columns.remove(i));
+                    }
+                    reordered.append(&mut columns);
+                    columns = reordered;
+                }
+                Transform::Drop(names) => {
+                    for name in names {
+                        let i = find(&columns, name)?;
+                        columns.remove(i);
+                    }
+                }
+                Transform::ToChar {
+                    name,
+                    format,
+                    length,
+                } => {
+                    let i = find(&columns, name)?;
+                    let (column, _, conversion) = &mut columns[i];
+                    if column.column_type != ColumnType::NUMERIC
+                        || !matches!(conversion, Conversion::Copy)
+                    {
+                        return Err(XPTError::ParseError(format!(
+                            "column {} is not NUMERIC as read",
+                            name
+                        )));
+                    }
+                    let format = SasFormat::new(format)?;
+                    column.column_type = ColumnType::CHAR;
+                    column.length = length.unwrap_or(format.width);
+                    column.format = (String::new(), 0, 0);
+                    column.in_format = (String::new(), 0, 0);
+                    *conversion = Conversion::ToChar(format);
+                }
+                Transform::ToNumeric { name, informat } => {
+                    let i = find(&columns, name)?;
+                    let (column, _, conversion) = &mut columns[i];
+                    if column.column_type != ColumnType::CHAR
+                        || !matches!(conversion, Conversion::Copy)
+                    {
+                        return Err(XPTError::ParseError(format!(
+                            "column {} is not CHAR as read",
+                            name
+                        )));
+                    }
+                    let sas_format = SasFormat::new(informat)?;
+                    column.column_type = ColumnType::NUMERIC;
+                    column.length = 8;
+                    column.format = if sas_format.is_temporal() {
+                        parse_format(informat)?
+                    } else {
+                        (String::new(), 0, 0)
+                    };
+                    column.in_format = (String::new(), 0, 0);
+                    *conversion = Conversion::ToNumeric(sas_format);
+                }
+            }
+        }
+        let (columns, sources): (Vec<_>, Vec<_>) = columns
+            .into_iter()
+            .map(|(column, i, conversion)| (column, (i, conversion)))
+            .unzip();
+        check_columns(meta.version, &columns)?;
+        Ok(Transformer {
+            layout: layout(&columns),
+            invalid: vec![None; columns.len()],
+            columns,
+            sources,
+        })
+    }
+
+    pub fn columns(&self) -> &[ColumnMeta] {
+        &self.columns
+    }
+
+    /// Lays `row` out in the transformed columns, converting retyped cells.
+    pub fn fill_line(&mut self, row: &RowView, line: &mut [u8]) -> Result<(), XPTError> {
+        for (j, ((npos, nlng, _), (i, conversion))) in
+            self.layout.iter().zip(&self.sources).enumerate()
+        {
+            let cell = &mut line[*npos as usize..*npos as usize + *nlng as usize];
+            let mut invalid = |example: String| {
+                let entry = self.invalid[j].get_or_insert_with(|| InvalidValues {
+                    column: self.columns[j].name.clone(),
+                    count: 0,
+                    example,
+                });
+                entry.count += 1;
+            };
+            match conversion {
+                Conversion::Copy => {
+                    let bytes = row.get_bytes(*i);
+                    let length = bytes.len().min(cell.len());
+                    cell[..length].copy_from_slice(&bytes[..length]);
+                    cell[length..].fill(if row.is_numeric(*i) { 0 } else { b' ' });
+                }
+                Conversion::ToChar(format) => {
+                    let value = row.get_f64(*i);
+                    let str = format.format(value).unwrap_or_else(|| {
+                        invalid(value.map_or(String::new(), |v| v.to_string()));
+                        "*".repeat(format.width as usize)
+                    });
+                    let length = str.len().min(cell.len());
+                    cell[..length].copy_from_slice(&str.as_bytes()[..length]);
+                    cell[length..].fill(b' ');
+                }
+                Conversion::ToNumeric(informat) => {
+                    let str = row.get_str(*i)?;
+                    let value = informat.read(&str).unwrap_or_else(|| {
+                        invalid(str.clone());
+                        None
+                    });
+                    number2byte(value, cell)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Columns with values a conversion could not write or read so far.
+    pub fn invalid(&self) -> Vec<InvalidValues> {
+        self.invalid.iter().flatten().cloned().collect()
+    }
+}
+
+/// Whether `name` is a SAS variable name: letters, digits and underscores,
+/// not starting with a digit.
+fn is_sas_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Copies the file at `input` to `output` with `transforms` applied to its
+/// columns.
+#[cfg(not(feature = "async"))]
+pub fn transform_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    transforms: &[Transform],
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<TransformReport, XPTError> {
+    let mut file = std::fs::File::open(&input)?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut transformer = Transformer::new(&meta, transforms)?;
+    let mut header = MemberHeader::from_meta(&meta);
+    if data.observations() > 0 {
+        header.observations = Some(data.observations());
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut writer = Writer::new(
+        &mut out,
+        header,
+        transformer.columns.clone(),
+        string_encoder,
+    )?;
+    writer.start()?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row()? {
+        transformer.fill_line(&row, &mut line)?;
+        writer.write_line(&line)?;
+    }
+    let rows = writer.rows();
+    writer.finish()?;
+    Ok(TransformReport {
+        invalid: transformer.invalid(),
+        columns: transformer.columns,
+        rows,
+    })
+}
+
+/// Copies the file at `input` to `output` with `transforms` applied to its
+/// columns.
+#[cfg(feature = "async")]
+pub async fn transform_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    transforms: &[Transform],
+    string_decoder: StringDecoder,
+    string_encoder: StringEncoder,
+) -> Result<TransformReport, XPTError> {
+    let mut file = tokio::fs::File::open(&input).await?;
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut transformer = Transformer::new(&meta, transforms)?;
+    let mut header = MemberHeader::from_meta(&meta);
+    if data.observations() > 0 {
+        header.observations = Some(data.observations());
+    }
+    let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = Writer::new(
+        &mut out,
+        header,
+        transformer.columns.clone(),
+        string_encoder,
+    )?;
+    writer.start().await?;
+    let mut line = vec![b' '; writer.line_length()];
+    while let Some(row) = data.read_row().await? {
+        transformer.fill_line(&row, &mut line)?;
+        writer.write_line(&line).await?;
+    }
+    let rows = writer.rows();
+    writer.finish().await?;
+    Ok(TransformReport {
+        invalid: transformer.invalid(),
+        columns: transformer.columns,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::part::parse_format;
+    use crate::transform::SasFormat;
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            parse_format("E8601DA10.").unwrap(),
+            ("E8601DA".to_string(), 10, 0)
+        );
+        assert_eq!(parse_format("8.2").unwrap(), (String::new(), 8, 2));
+        assert_eq!(
+            parse_format("$char20.").unwrap(),
+            ("$CHAR".to_string(), 20, 0)
+        );
+        assert!(parse_format("DATE9").is_err());
+        assert!(SasFormat::new("DOLLAR8.2").is_err());
+
+        let date = SasFormat::new("DATE9.").unwrap();
+        assert_eq!(date.format(Some(0.0)).unwrap(), "01JAN1960");
+        assert_eq!(date.format(Some(23011.0)).unwrap(), "01JAN2023");
+        assert_eq!(date.read("01jan2023"), Some(Some(23011.0)));
+        assert_eq!(date.read("29FEB2023"), None);
+        assert_eq!(
+            SasFormat::new("DATE7.").unwrap().read("15MAR39"),
+            Some(Some(28928.0))
+        );
+
+        let iso = SasFormat::new("E8601DA.").unwrap();
+        assert_eq!(iso.format(Some(-1.0)).unwrap(), "1959-12-31");
+        assert_eq!(iso.read("2013-01-11"), Some(Some(19369.0)));
+        assert_eq!(iso.read("2013-01"), None);
+        assert_eq!(iso.read("  "), Some(None));
+
+        let datetime = SasFormat::new("E8601DT.").unwrap();
+        let value = datetime.read("2013-01-11T08:30").unwrap();
+        assert_eq!(value, Some(19369.0 * 86400.0 + 8.5 * 3600.0));
+        assert_eq!(datetime.format(value).unwrap(), "2013-01-11T08:30:00");
+        assert_eq!(
+            SasFormat::new("TIME5.")
+                .unwrap()
+                .format(Some(30600.0))
+                .unwrap(),
+            "08:30"
+        );
+
+        let fixed = SasFormat::new("8.2").unwrap();
+        assert_eq!(fixed.format(Some(5.5)).unwrap(), "5.50");
+        assert_eq!(fixed.format(None).unwrap(), "");
+        assert_eq!(fixed.format(Some(123456789.0)), None);
+        assert_eq!(fixed.read("550"), Some(Some(5.5)));
+        assert_eq!(fixed.read("5.5"), Some(Some(5.5)));
+        assert_eq!(fixed.read("<5"), None);
+        assert_eq!(
+            SasFormat::new("Z5.").unwrap().format(Some(42.0)).unwrap(),
+            "00042"
+        );
+        // values past the dates SAS supports are not written or read
+        assert_eq!(date.format(Some(1e70)), None);
+        assert_eq!(date.format(Some(-1e70)), None);
+        assert_eq!(date.format(Some(-137_774.0)).unwrap(), "15OCT1582");
+        assert_eq!(date.format(Some(-137_775.0)), None);
+        assert_eq!(datetime.format(Some(1e70)), None);
+        assert_eq!(datetime.read("1500-01-01T00:00"), None);
+        let time = SasFormat::new("TIME8.").unwrap();
+        assert_eq!(time.read("99999999999999999:00"), None);
+        assert_eq!(time.read("26:30"), Some(Some(95400.0)));
+        let best = SasFormat::new("BEST4.").unwrap();
+        assert_eq!(best.format(Some(0.06)).unwrap(), "0.06");
+        assert_eq!(best.format(Some(1.23456)).unwrap(), "1.23");
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_transform_file() {
+        use crate::part::ColumnType;
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::transform::{transform_file, Transform};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let output = std::env::temp_dir().join("xpt_r_transform_lb2.xpt");
+        let transform = |transforms: &[Transform]| {
+            transform_file(
+                "sample/LB2.xpt",
+                &output,
+                transforms,
+                UTF8_STRING_DECODER,
+                UTF8_STRING_ENCODER,
+            )
+        };
+        let rename = |from: &str, to: &str| Transform::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        assert!(transform(&[rename("LBTEST", "LBTESTNAME")]).is_err());
+        assert!(transform(&[rename("LBTEST", "1TEST")]).is_err());
+        assert!(transform(&[rename("LBTEST", "lbtestcd")]).is_err());
+        assert!(transform(&[Transform::Drop(vec!["NOPE".to_string()])]).is_err());
+        assert!(transform(&[Transform::Relabel {
+            name: "LBTEST".to_string(),
+            label: "x".repeat(41),
+        }])
+        .is_err());
+
+        let report = transform(&[
+            rename("LBSTRESN", "AVAL"),
+            Transform::Relabel {
+                name: "aval".to_string(),
+                label: "Analysis Value".to_string(),
+            },
+            Transform::Reformat {
+                name: "AVAL".to_string(),
+                format: "8.2".to_string(),
+            },
+            Transform::Drop(vec!["STUDYID".to_string(), "DOMAIN".to_string()]),
+            Transform::Reorder(vec!["LBSEQ".to_string(), "AVAL".to_string()]),
+            Transform::ToChar {
+                name: "LBSEQ".to_string(),
+                format: "Z4.".to_string(),
+                length: None,
+            },
+            Transform::ToNumeric {
+                name: "LBDTC".to_string(),
+                informat: "E8601DT.".to_string(),
+            },
+            Transform::ToNumeric {
+                name: "LBORRES".to_string(),
+                informat: "BEST.".to_string(),
+            },
+        ])
+        .unwrap();
+        assert_eq!(report.rows, 9);
+        assert!(report.invalid.is_empty());
+        let names: Vec<_> = report.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["LBSEQ", "AVAL", "USUBJID", "LBTESTCD", "LBTEST", "LBORRES", "LBDTC"]
+        );
+
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (mut data, meta) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        assert_eq!(meta.columns[0].column_type, ColumnType::CHAR);
+        assert_eq!(meta.columns[0].length, 4);
+        assert_eq!(meta.columns[1].label, "Analysis Value");
+        assert_eq!(meta.columns[1].format, (String::new(), 8, 2));
+        assert_eq!(meta.columns[6].format.0, "E8601DT");
+        let row = data.read_line().unwrap().unwrap();
+        assert_eq!(row[0], Val::Char("0101".to_string()));
+        assert_eq!(row[1], Val::Number(22.0));
+        assert_eq!(row[5], Val::Number(22.0));
+        assert_eq!(row[6], Val::Number(19369.0 * 86400.0 + 8.5 * 3600.0));
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_transform_out_of_range() {
+        use crate::fixture::{column, write_file};
+        use crate::part::{ColumnType, DocumentHeader};
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::transform::{transform_file, Transform};
+        use crate::writer::UTF8_STRING_ENCODER;
+
+        let input = std::env::temp_dir().join("xpt_r_transform_range.xpt");
+        let output = std::env::temp_dir().join("xpt_r_transform_range_out.xpt");
+        write_file(
+            &input,
+            DocumentHeader::V5,
+            "RANGE",
+            vec![
+                column("ADT", ColumnType::NUMERIC, 8),
+                column("ATM", ColumnType::CHAR, 20),
+            ],
+            &[vec![
+                Val::Number(1e70),
+                Val::Char("99999999999999999:00".to_string()),
+            ]],
+        );
+        let report = transform_file(
+            &input,
+            &output,
+            &[
+                Transform::ToChar {
+                    name: "ADT".to_string(),
+                    format: "DATE9.".to_string(),
+                    length: None,
+                },
+                Transform::ToNumeric {
+                    name: "ATM".to_string(),
+                    informat: "TIME8.".to_string(),
+                },
+            ],
+            UTF8_STRING_DECODER,
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        let counts: Vec<_> = report
+            .invalid
+            .iter()
+            .map(|i| (i.column.as_str(), i.count))
+            .collect();
+        assert_eq!(counts, vec![("ADT", 1), ("ATM", 1)]);
+        let mut file = std::fs::File::open(&output).unwrap();
+        let (mut data, _) = Reader::new(&mut file, UTF8_STRING_DECODER).start().unwrap();
+        let row = data.read_line().unwrap().unwrap();
+        assert_eq!(row, vec![Val::Char("*********".to_string()), Val::Nil]);
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+}
@@ -0,0 +1,549 @@
+use crate::error::XPTError;
+use crate::part::{ColumnType, DocumentHeader, DocumentMeta, StringDecoder};
+use crate::reader::{RawVal, Reader};
+use std::fmt::{Display, Write};
+use std::path::Path;
+
+/// Largest single transport file accepted by the FDA gateway.
+pub const FDA_MAX_FILE_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            }
+        )
+    }
+}
+
+/// Checks made by [`validate_meta`] and [`Validator`], following the FDA
+/// Study Data Technical Conformance Guide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// XPT001: only SAS transport version 5 is accepted.
+    TransportVersion,
+    /// XPT002: dataset names are at most 8 bytes.
+    DatasetNameLength,
+    /// XPT003: dataset names are uppercase letters and digits, starting with a letter.
+    DatasetNameCharacters,
+    /// XPT004: variable names are at most 8 bytes.
+    VariableNameLength,
+    /// XPT005: variable names are uppercase letters and digits, starting with a letter.
+    VariableNameCharacters,
+    /// XPT006: variable labels are at most 40 bytes.
+    VariableLabelLength,
+    /// XPT007: variables should carry a label.
+    VariableLabelMissing,
+    /// XPT008: CHAR variables are at most 200 bytes long.
+    CharLength,
+    /// XPT009: names, labels and values are ASCII only.
+    NonAscii,
+    /// XPT010: a transport file is at most the configured size.
+    FileSize,
+    /// XPT011: the file is named after the dataset it contains.
+    FileNameMatch,
+    /// XPT012: file names are lowercase with an `.xpt` extension.
+    FileNameFormat,
+}
+
+/// A check that findings can be reported against.
+pub trait ReportRule: Copy + PartialEq + std::fmt::Debug {
+    /// Stable identifier, e.g. `XPT004`.
+    fn id(&self) -> &'static str;
+    fn severity(&self) -> Severity;
+}
+
+impl ReportRule for Rule {
+    fn id(&self) -> &'static str {
+        match self {
+            Rule::TransportVersion => "XPT001",
+            Rule::DatasetNameLength => "XPT002",
+            Rule::DatasetNameCharacters => "XPT003",
+            Rule::VariableNameLength => "XPT004",
+            Rule::VariableNameCharacters => "XPT005",
+            Rule::VariableLabelLength => "XPT006",
+            Rule::VariableLabelMissing => "XPT007",
+            Rule::CharLength => "XPT008",
+            Rule::NonAscii => "XPT009",
+            Rule::FileSize => "XPT010",
+            Rule::FileNameMatch => "XPT011",
+            Rule::FileNameFormat => "XPT012",
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            Rule::VariableLabelMissing | Rule::FileNameFormat => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding<R = Rule> {
+    pub rule: R,
+    pub severity: Severity,
+    /// Variable the finding is about, `None` for dataset and file level findings.
+    pub variable: Option<String>,
+    pub message: String,
+}
+
+impl<R: ReportRule> Display for Finding<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: ", self.rule.id(), self.severity)?;
+        if let Some(variable) = &self.variable {
+            write!(f, "{}: ", variable)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report<R> {
+    pub findings: Vec<Finding<R>>,
+}
+
+pub type ValidationReport = Report<Rule>;
+
+impl<R> Default for Report<R> {
+    fn default() -> Self {
+        Report {
+            findings: Vec::new(),
+        }
+    }
+}
+
+impl<R: ReportRule> Report<R> {
+    pub(crate) fn push(&mut self, rule: R, variable: Option<&str>, message: String) {
+        self.findings.push(Finding {
+            rule,
+            severity: rule.severity(),
+            variable: variable.map(str::to_string),
+            message,
+        });
+    }
+
+    /// True when nothing of [`Severity::Error`] was found.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Finding<R>> {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+    }
+
+    pub fn has_rule(&self, rule: R) -> bool {
+        self.findings.iter().any(|f| f.rule == rule)
+    }
+
+    /// The findings as a JSON array of `{rule, severity, variable, message}` objects.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, finding) in self.findings.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"rule\":{},\"severity\":{},\"variable\":{},\"message\":{}}}",
+                json_string(finding.rule.id()),
+                json_string(&finding.severity.to_string()),
+                finding
+                    .variable
+                    .as_deref()
+                    .map_or("null".to_string(), json_string),
+                json_string(&finding.message)
+            );
+        }
+        json.push(']');
+        json
+    }
+}
+
+impl<R: ReportRule> Display for Report<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn json_string(str: &str) -> String {
+    let mut json = String::with_capacity(str.len() + 2);
+    json.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    pub max_file_size: u64,
+    /// Size of the transport file, when known.
+    pub file_size: Option<u64>,
+    /// Name of the transport file, when known, e.g. `lb.xpt`.
+    pub file_name: Option<String>,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            max_file_size: FDA_MAX_FILE_SIZE,
+            file_size: None,
+            file_name: None,
+        }
+    }
+}
+
+fn is_sas_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_uppercase())
+        && chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// Checks the metadata of a dataset, and the file facts given in `options`.
+pub fn validate_meta(meta: &DocumentMeta, options: &ValidationOptions) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    if let DocumentHeader::V8 = meta.version {
+        report.push(
+            Rule::TransportVersion,
+            None,
+            "file is SAS transport version 8, version 5 is required".to_string(),
+        );
+    }
+    let dataset = meta.dataset_name.as_str();
+    if dataset.len() > 8 {
+        report.push(
+            Rule::DatasetNameLength,
+            None,
+            format!("dataset name {} is longer than 8 bytes", dataset),
+        );
+    }
+    if !is_sas_name(dataset) {
+        report.push(
+            Rule::DatasetNameCharacters,
+            None,
+            format!(
+                "dataset name {:?} is not uppercase alphanumeric starting with a letter",
+                dataset
+            ),
+        );
+    }
+    for column in &meta.columns {
+        let name = Some(column.name.as_str());
+        if column.name.len() > 8 {
+            report.push(
+                Rule::VariableNameLength,
+                name,
+                format!("name is {} bytes, at most 8 allowed", column.name.len()),
+            );
+        }
+        if !is_sas_name(&column.name) {
+            report.push(
+                Rule::VariableNameCharacters,
+                name,
+                "name is not uppercase alphanumeric starting with a letter".to_string(),
+            );
+        }
+        if column.label.len() > 40 {
+            report.push(
+                Rule::VariableLabelLength,
+                name,
+                format!("label is {} bytes, at most 40 allowed", column.label.len()),
+            );
+        }
+        if column.label.is_empty() {
+            report.push(
+                Rule::VariableLabelMissing,
+                name,
+                "label is empty".to_string(),
+            );
+        }
+        if !column.name.is_ascii() || !column.label.is_ascii() {
+            report.push(
+                Rule::NonAscii,
+                name,
+                "name or label contains non-ASCII characters".to_string(),
+            );
+        }
+        if let ColumnType::CHAR = column.column_type {
+            if column.length > 200 {
+                report.push(
+                    Rule::CharLength,
+                    name,
+                    format!("length is {}, at most 200 allowed", column.length),
+                );
+            }
+        }
+    }
+    if let Some(file_size) = options.file_size {
+        if file_size > options.max_file_size {
+            report.push(
+                Rule::FileSize,
+                None,
+                format!(
+                    "file is {} bytes, at most {} allowed",
+                    file_size, options.max_file_size
+                ),
+            );
+        }
+    }
+    if let Some(file_name) = &options.file_name {
+        let stem = file_name
+            .rsplit_once('.')
+            .map_or(file_name.as_str(), |(stem, _)| stem);
+        if !stem.eq_ignore_ascii_case(dataset) {
+            report.push(
+                Rule::FileNameMatch,
+                None,
+                format!("file {} does not contain dataset {}", file_name, dataset),
+            );
+        }
+        if file_name != &file_name.to_ascii_lowercase() || !file_name.ends_with(".xpt") {
+            report.push(
+                Rule::FileNameFormat,
+                None,
+                format!(
+                    "file name {} should be lowercase with an .xpt extension",
+                    file_name
+                ),
+            );
+        }
+    }
+    report
+}
+
+/// Validates metadata and then, row by row, the CHAR values.
+pub struct Validator {
+    report: ValidationReport,
+    columns: Vec<String>,
+    // per column: rows with non-ASCII bytes and the first such row
+    non_ascii: Vec<(usize, usize)>,
+    rows: usize,
+}
+
+impl Validator {
+    pub fn new(meta: &DocumentMeta, options: &ValidationOptions) -> Self {
+        Validator {
+            report: validate_meta(meta, options),
+            columns: meta.columns.iter().map(|c| c.name.clone()).collect(),
+            non_ascii: vec![(0, 0); meta.columns.len()],
+            rows: 0,
+        }
+    }
+
+    pub fn check_row(&mut self, row: &[RawVal]) {
+        for (i, cell) in row.iter().enumerate() {
+            if let RawVal::Char(bytes) = cell {
+                if !bytes.is_ascii() {
+                    let (count, first) = &mut self.non_ascii[i];
+                    if *count == 0 {
+                        *first = self.rows + 1;
+                    }
+                    *count += 1;
+                }
+            }
+        }
+        self.rows += 1;
+    }
+
+    pub fn finish(mut self) -> ValidationReport {
+        for (i, (count, first)) in self.non_ascii.iter().enumerate() {
+            if *count > 0 {
+                self.report.push(
+                    Rule::NonAscii,
+                    Some(&self.columns[i]),
+                    format!(
+                        "{} values contain non-ASCII bytes, first in row {}",
+                        count, first
+                    ),
+                );
+            }
+        }
+        self.report
+    }
+}
+
+fn file_options(path: &Path, file_size: u64) -> ValidationOptions {
+    ValidationOptions {
+        file_size: Some(file_size),
+        file_name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string()),
+        ..ValidationOptions::default()
+    }
+}
+
+/// Validates the file at `path`: its name, size, metadata and every CHAR value.
+#[cfg(not(feature = "async"))]
+pub fn validate_file<P: AsRef<Path>>(
+    path: P,
+    string_decoder: StringDecoder,
+) -> Result<ValidationReport, XPTError> {
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path)?;
+    let options = file_options(path, file.metadata()?.len());
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start()?;
+    let mut validator = Validator::new(&meta, &options);
+    while let Some(row) = data.read_line_raw()? {
+        validator.check_row(&row);
+    }
+    Ok(validator.finish())
+}
+
+/// Validates the file at `path`: its name, size, metadata and every CHAR value.
+#[cfg(feature = "async")]
+pub async fn validate_file<P: AsRef<Path>>(
+    path: P,
+    string_decoder: StringDecoder,
+) -> Result<ValidationReport, XPTError> {
+    let path = path.as_ref();
+    let mut file = tokio::fs::File::open(path).await?;
+    let options = file_options(path, file.metadata().await?.len());
+    let mut reader = Reader::new(&mut file, string_decoder);
+    let (mut data, meta) = reader.start().await?;
+    let mut validator = Validator::new(&meta, &options);
+    while let Some(row) = data.read_line_raw().await? {
+        validator.check_row(&row);
+    }
+    Ok(validator.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{labelled_column as column, meta};
+    use crate::part::{ColumnType, DocumentHeader};
+    use crate::reader::RawVal;
+    #[cfg(not(feature = "async"))]
+    use crate::reader::UTF8_STRING_DECODER;
+    #[cfg(not(feature = "async"))]
+    use crate::validate::validate_file;
+    use crate::validate::{validate_meta, Rule, Severity, ValidationOptions, Validator};
+
+    #[test]
+    fn test_validate_meta() {
+        let valid = meta(
+            DocumentHeader::V5,
+            "LB",
+            vec![
+                column("STUDYID", "Study Identifier", ColumnType::CHAR, 12),
+                column("LBSEQ", "Sequence Number", ColumnType::NUMERIC, 8),
+            ],
+        );
+        let options = ValidationOptions {
+            file_name: Some("lb.xpt".to_string()),
+            file_size: Some(3280),
+            ..ValidationOptions::default()
+        };
+        let report = validate_meta(&valid, &options);
+        assert!(report.findings.is_empty(), "{}", report);
+
+        let invalid = meta(
+            DocumentHeader::V8,
+            "lab_results",
+            vec![
+                column("STUDYIDENT", "Study Identifier", ColumnType::CHAR, 12),
+                column("lb_seq", "", ColumnType::NUMERIC, 8),
+                column(
+                    "LBCOMM",
+                    "Comment entered by the investigator at the site visit",
+                    ColumnType::CHAR,
+                    400,
+                ),
+                column("LBTEST", "Résultat", ColumnType::CHAR, 40),
+            ],
+        );
+        let options = ValidationOptions {
+            file_name: Some("LB.XPT".to_string()),
+            file_size: Some(6 * 1024 * 1024 * 1024),
+            ..ValidationOptions::default()
+        };
+        let report = validate_meta(&invalid, &options);
+        for rule in [
+            Rule::TransportVersion,
+            Rule::DatasetNameLength,
+            Rule::DatasetNameCharacters,
+            Rule::VariableNameLength,
+            Rule::VariableNameCharacters,
+            Rule::VariableLabelLength,
+            Rule::VariableLabelMissing,
+            Rule::CharLength,
+            Rule::NonAscii,
+            Rule::FileSize,
+            Rule::FileNameMatch,
+            Rule::FileNameFormat,
+        ] {
+            assert!(report.has_rule(rule), "{:?} not reported", rule);
+        }
+        assert!(!report.is_valid());
+        let label = report
+            .findings
+            .iter()
+            .find(|f| f.rule == Rule::VariableLabelMissing)
+            .unwrap();
+        assert_eq!(label.severity, Severity::Warning);
+        assert_eq!(label.variable.as_deref(), Some("lb_seq"));
+        assert!(report
+            .to_json()
+            .starts_with("[{\"rule\":\"XPT001\",\"severity\":\"error\",\"variable\":null,"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_validate_file() {
+        let report = validate_file("sample/LB2.xpt", UTF8_STRING_DECODER).unwrap();
+        assert!(report.has_rule(Rule::FileNameFormat));
+        assert!(!report.has_rule(Rule::NonAscii));
+        assert!(!report.has_rule(Rule::CharLength));
+        assert!(!report.has_rule(Rule::FileSize));
+    }
+
+    #[test]
+    fn test_validate_values() {
+        let meta = meta(
+            DocumentHeader::V5,
+            "LB",
+            vec![
+                column("LBTEST", "Lab Test", ColumnType::CHAR, 8),
+                column("LBSEQ", "Sequence Number", ColumnType::NUMERIC, 8),
+            ],
+        );
+        let mut validator = Validator::new(&meta, &ValidationOptions::default());
+        validator.check_row(&[RawVal::Char(b"ALT".to_vec()), RawVal::Number(1.0)]);
+        validator.check_row(&[RawVal::Char(vec![0xe9]), RawVal::Nil]);
+        validator.check_row(&[RawVal::Char(vec![0xe9]), RawVal::Nil]);
+        let report = validator.finish();
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].rule, Rule::NonAscii);
+        assert_eq!(
+            report.findings[0].message,
+            "2 values contain non-ASCII bytes, first in row 2"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::error::XPTError;
+use crate::number::byte2number;
+use crate::part::StringDecoder;
+
+/// A row borrowed from the reader's line buffer.
+///
+/// Nothing is decoded until a cell is asked for, so scanning a file through
+/// views allocates only for the CHAR values actually turned into `String`s.
+pub struct RowView<'r> {
+    line: &'r [u8],
+    columns: &'r [(u32, u16, u16)],
+    decoder: StringDecoder,
+}
+
+impl<'r> RowView<'r> {
+    pub(crate) fn new(
+        line: &'r [u8],
+        columns: &'r [(u32, u16, u16)],
+        decoder: StringDecoder,
+    ) -> Self {
+        RowView {
+            line,
+            columns,
+            decoder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// The whole observation as stored in the file.
+    pub fn line(&self) -> &'r [u8] {
+        self.line
+    }
+
+    pub fn is_numeric(&self, i: usize) -> bool {
+        self.columns[i].2 == 1
+    }
+
+    /// Bytes of cell `i`, including the blank padding of CHAR values.
+    pub fn get_bytes(&self, i: usize) -> &'r [u8] {
+        let (npos, nlng, _) = self.columns[i];
+        &self.line[npos as usize..npos as usize + nlng as usize]
+    }
+
+    /// Value of a NUMERIC cell, `None` when it is missing or a CHAR cell.
+    pub fn get_f64(&self, i: usize) -> Option<f64> {
+        if self.is_numeric(i) {
+            byte2number(self.get_bytes(i))
+        } else {
+            None
+        }
+    }
+
+    /// CHAR cell decoded with the reader's decoder.
+    pub fn get_str(&self, i: usize) -> Result<String, XPTError> {
+        (self.decoder)(self.get_bytes(i))
+    }
+
+    /// CHAR cell borrowed as UTF-8 without copying, trailing blanks removed.
+    pub fn get_utf8(&self, i: usize) -> Result<&'r str, XPTError> {
+        std::str::from_utf8(self.get_bytes(i))
+            .map(|s| s.trim_end())
+            .map_err(|e| XPTError::DecodeError(e.to_string()))
+    }
+
+    /// Missing NUMERIC value, or a CHAR value that is all blanks.
+    pub fn is_missing(&self, i: usize) -> bool {
+        let bytes = self.get_bytes(i);
+        if self.is_numeric(i) {
+            byte2number(bytes).is_none()
+        } else {
+            bytes.iter().all(|b| *b == b' ' || *b == 0)
+        }
+    }
+}
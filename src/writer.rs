@@ -0,0 +1,836 @@
+use crate::define::{display_format, utc_today};
+use crate::error::XPTError;
+use crate::number::number2byte;
+use crate::part::{ColumnMeta, ColumnType, DocumentHeader, DocumentMeta, StringEncoder};
+use crate::reader::Val;
+use crate::view::RowView;
+#[cfg(feature = "multi_encoding")]
+use encoding::all::{GBK, WINDOWS_31J};
+#[cfg(feature = "multi_encoding")]
+use encoding::{EncoderTrap, Encoding};
+#[cfg(not(feature = "async"))]
+use std::io::Write;
+#[cfg(feature = "async")]
+use tokio::io::AsyncWriteExt;
+
+pub const UTF8_STRING_ENCODER: StringEncoder = |x| Ok(x.as_bytes().to_vec());
+
+pub const LATIN1_STRING_ENCODER: StringEncoder = |x| {
+    x.chars()
+        .map(|c| {
+            u8::try_from(c)
+                .map_err(|_| XPTError::EncodeError(format!("{:?} is not in ISO-8859-1", c)))
+        })
+        .collect()
+};
+
+#[cfg(feature = "multi_encoding")]
+pub const GBK_STRING_ENCODER: StringEncoder = |x| {
+    GBK.encode(x, EncoderTrap::Strict)
+        .map_err(|x| XPTError::EncodeError(x.to_string()))
+};
+
+#[cfg(feature = "multi_encoding")]
+pub const SHIFT_JIS_STRING_ENCODER: StringEncoder = |x| {
+    WINDOWS_31J
+        .encode(x, EncoderTrap::Strict)
+        .map_err(|x| XPTError::EncodeError(x.to_string()))
+};
+
+/// Size of a NAMESTR record as written, SAS's 140-byte layout.
+const NAMESTR_LENGTH: usize = 140;
+
+/// The library and member headers of the file to write.
+#[derive(Debug, Clone)]
+pub struct MemberHeader {
+    pub version: DocumentHeader,
+    pub dataset_name: String,
+    pub dataset_label: String,
+    pub dataset_type: String,
+    pub sas_version: String,
+    pub operating_system: String,
+    /// SAS datetime `ddMMMyy:hh:mm:ss`.
+    pub created: String,
+    pub modified: String,
+    /// Observation count written to the V8 `OBSV8` header, unknown when
+    /// `None`. V5 files do not record it.
+    pub observations: Option<usize>,
+}
+
+impl MemberHeader {
+    /// Header stamped with the current time.
+    pub fn new(version: DocumentHeader, dataset_name: &str) -> Self {
+        let now = sas_datetime_now();
+        // the field holds 8 bytes, too few for names such as DRAGONFLY
+        let os = std::env::consts::OS;
+        MemberHeader {
+            version,
+            dataset_name: dataset_name.to_string(),
+            dataset_label: String::new(),
+            dataset_type: String::new(),
+            sas_version: "9.4".to_string(),
+            operating_system: os[..os.len().min(8)].to_ascii_uppercase(),
+            created: now.clone(),
+            modified: now,
+            observations: None,
+        }
+    }
+
+    /// Header copied from a file that was read, to rewrite it.
+    pub fn from_meta(meta: &DocumentMeta) -> Self {
+        MemberHeader {
+            version: meta.version,
+            dataset_name: meta.dataset_name.clone(),
+            dataset_label: meta.dataset_label.clone(),
+            dataset_type: meta.dataset_type.clone(),
+            sas_version: meta.dataset_version.clone(),
+            operating_system: meta.dataset_operation_system.clone(),
+            created: meta.dataset_created.clone(),
+            modified: meta.dataset_modified.clone(),
+            observations: None,
+        }
+    }
+}
+
+pub(crate) const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// Current UTC time in the `ddMMMyy:hh:mm:ss` form of the XPT headers.
+fn sas_datetime_now() -> String {
+    let (year, month, day, time) = utc_today();
+    format!(
+        "{:02}{}{:02}:{:02}:{:02}:{:02}",
+        day,
+        MONTHS[month as usize - 1],
+        year % 100,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Positions `(npos, nlng, ntype)` of the columns laid out one after another.
+pub fn layout(columns: &[ColumnMeta]) -> Vec<(u32, u16, u16)> {
+    let mut npos = 0;
+    columns
+        .iter()
+        .map(|column| {
+            let position = (npos, column.length, column.column_type as u16);
+            npos += column.length as u32;
+            position
+        })
+        .collect()
+}
+
+/// Copies the cells of `row` into `line` at the positions of `layout`, one
+/// entry per cell. CHAR cells are cut or padded with blanks to the new
+/// length; NUMERIC cells keep their bytes, special missing values included.
+pub fn relayout(row: &RowView, layout: &[(u32, u16, u16)], line: &mut [u8]) {
+    for (i, (npos, nlng, _)) in layout.iter().enumerate() {
+        let cell = &mut line[*npos as usize..*npos as usize + *nlng as usize];
+        let bytes = row.get_bytes(i);
+        let length = bytes.len().min(cell.len());
+        cell[..length].copy_from_slice(&bytes[..length]);
+        cell[length..].fill(if row.is_numeric(i) { 0 } else { b' ' });
+    }
+}
+
+fn encode_field(
+    encoder: StringEncoder,
+    str: &str,
+    width: usize,
+    what: &str,
+) -> Result<Vec<u8>, XPTError> {
+    let mut bytes = encoder(str)?;
+    if bytes.len() > width {
+        return Err(XPTError::EncodeError(format!(
+            "{} {:?} is {} bytes, longer than {}",
+            what,
+            str,
+            bytes.len(),
+            width
+        )));
+    }
+    bytes.resize(width, b' ');
+    Ok(bytes)
+}
+
+fn header_record(title: &str, body: &str) -> Vec<u8> {
+    format!("HEADER RECORD*******{:!<28}{:<32}", title, body).into_bytes()
+}
+
+/// Appends blanks up to the next multiple of 80 bytes.
+fn pad_record(bytes: &mut Vec<u8>) {
+    let rest = bytes.len() % 80;
+    if rest > 0 {
+        bytes.resize(bytes.len() + 80 - rest, b' ');
+    }
+}
+
+pub(crate) fn check_columns(
+    version: DocumentHeader,
+    columns: &[ColumnMeta],
+) -> Result<(), XPTError> {
+    let (name_limit, format_limit, char_limit) = match version {
+        DocumentHeader::V5 => (8, 8, 200),
+        DocumentHeader::V8 => (32, 32, 32767),
+    };
+    for column in columns {
+        let fail = |message: String| {
+            Err(XPTError::EncodeError(format!(
+                "column {}: {}",
+                column.name, message
+            )))
+        };
+        if column.name.is_empty() || column.name.len() > name_limit {
+            return fail(format!("name must be 1 to {} bytes", name_limit));
+        }
+        if version == DocumentHeader::V5 && column.label.len() > 40 {
+            return fail("label is longer than 40 bytes".to_string());
+        }
+        if column.format.0.len() > format_limit || column.in_format.0.len() > format_limit {
+            return fail(format!(
+                "format and informat names must be at most {} bytes",
+                format_limit
+            ));
+        }
+        match column.column_type {
+            ColumnType::NUMERIC if !(3..=8).contains(&column.length) => {
+                return fail(format!(
+                    "numeric length {} is outside 3 to 8",
+                    column.length
+                ));
+            }
+            ColumnType::CHAR if column.length == 0 || column.length > char_limit => {
+                return fail(format!(
+                    "character length {} is outside 1 to {}",
+                    column.length, char_limit
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The header records up to and including the `OBS` header.
+fn header_bytes(
+    header: &MemberHeader,
+    columns: &[ColumnMeta],
+    layout: &[(u32, u16, u16)],
+    encoder: StringEncoder,
+) -> Result<Vec<u8>, XPTError> {
+    let field = |str: &str, width: usize, what: &str| encode_field(encoder, str, width, what);
+    let v8 = header.version == DocumentHeader::V8;
+    let (library, member, descriptor, namestr) = if v8 {
+        ("LIBV8", "MEMBV8", "DSCPTV8", "NAMSTV8")
+    } else {
+        ("LIBRARY", "MEMBER", "DSCRPTR", "NAMESTR")
+    };
+    let zeros = "000000000000000000000000000000  ";
+    let version = field(&header.sas_version, 8, "SAS version")?;
+    let os = field(&header.operating_system, 8, "operating system")?;
+    let created = field(&header.created, 16, "created time")?;
+    let modified = field(&header.modified, 16, "modified time")?;
+
+    let mut bytes = header_record(&format!("{:<8}HEADER RECORD", library), zeros);
+    bytes.extend_from_slice(b"SAS     SAS     SASLIB  ");
+    bytes.extend_from_slice(&version);
+    bytes.extend_from_slice(&os);
+    bytes.extend_from_slice(&[b' '; 24]);
+    bytes.extend_from_slice(&created);
+    bytes.extend_from_slice(&modified);
+    bytes.extend_from_slice(&[b' '; 64]);
+
+    bytes.extend(header_record(
+        &format!("{:<8}HEADER RECORD", member),
+        &format!("000000000000000001600000000{:03}  ", NAMESTR_LENGTH),
+    ));
+    bytes.extend(header_record(
+        &format!("{:<8}HEADER RECORD", descriptor),
+        zeros,
+    ));
+    bytes.extend_from_slice(b"SAS     ");
+    bytes.extend(field(
+        &header.dataset_name,
+        if v8 { 32 } else { 8 },
+        "dataset name",
+    )?);
+    bytes.extend_from_slice(b"SASDATA ");
+    bytes.extend_from_slice(&version);
+    bytes.extend_from_slice(&os);
+    if !v8 {
+        bytes.extend_from_slice(&[b' '; 24]);
+    }
+    bytes.extend_from_slice(&created);
+    bytes.extend_from_slice(&modified);
+    bytes.extend_from_slice(&[b' '; 16]);
+    bytes.extend(field(&header.dataset_label, 40, "dataset label")?);
+    bytes.extend(field(&header.dataset_type, 8, "dataset type")?);
+
+    bytes.extend(header_record(
+        &format!("{:<8}HEADER RECORD", namestr),
+        &format!("000000{:04}00000000000000000000  ", columns.len()),
+    ));
+    // LABELV9 records, which also hold format names, once any format or
+    // informat name is longer than the 8 bytes of the NAMESTR
+    let mut long_records = Vec::new();
+    let mut long_formats = false;
+    for (i, (column, (npos, nlng, ntype))) in columns.iter().zip(layout).enumerate() {
+        let name = field(&column.name, if v8 { 32 } else { 8 }, "column name")?;
+        let label = encoder(&column.label)?;
+        let format = encoder(&column.format.0)?;
+        let informat = encoder(&column.in_format.0)?;
+        // check_columns keeps V5 format names within the 8 bytes
+        let short = |bytes: &[u8]| {
+            let mut short = bytes[..bytes.len().min(8)].to_vec();
+            short.resize(8, b' ');
+            short
+        };
+        bytes.extend_from_slice(&ntype.to_be_bytes());
+        bytes.extend_from_slice(&column.name_hash.to_be_bytes());
+        bytes.extend_from_slice(&nlng.to_be_bytes());
+        bytes.extend_from_slice(&(i as u16 + 1).to_be_bytes());
+        bytes.extend_from_slice(&name[..8]);
+        let mut short_label = label.clone();
+        short_label.resize(40, b' ');
+        bytes.extend_from_slice(&short_label);
+        bytes.extend(short(&format));
+        bytes.extend_from_slice(&column.format.1.to_be_bytes());
+        bytes.extend_from_slice(&column.format.2.to_be_bytes());
+        bytes.extend_from_slice(&column.justification.to_be_bytes());
+        bytes.extend_from_slice(&[0; 2]);
+        bytes.extend(short(&informat));
+        bytes.extend_from_slice(&column.in_format.1.to_be_bytes());
+        bytes.extend_from_slice(&column.in_format.2.to_be_bytes());
+        bytes.extend_from_slice(&npos.to_be_bytes());
+        if v8 {
+            bytes.extend_from_slice(&name);
+            bytes.extend_from_slice(&(label.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&[0; 18]);
+            let format_long = format.len() > 8 || informat.len() > 8;
+            long_formats |= format_long;
+            if label.len() > 40 || format_long {
+                long_records.push((i as u16 + 1, name, label, column));
+            }
+        } else {
+            bytes.extend_from_slice(&[0; 52]);
+        }
+    }
+    pad_record(&mut bytes);
+
+    if !long_formats {
+        long_records.retain(|(_, _, label, _)| label.len() > 40);
+    }
+    if !long_records.is_empty() {
+        bytes.extend(header_record(
+            if long_formats {
+                "LABELV9 HEADER RECORD"
+            } else {
+                "LABELV8 HEADER RECORD"
+            },
+            &long_records.len().to_string(),
+        ));
+        for (var_number, name, label, column) in long_records {
+            let name = name.trim_ascii_end();
+            let mut texts = vec![name.to_vec(), label];
+            if long_formats {
+                for format in [&column.format, &column.in_format] {
+                    texts.push(encoder(&display_format(format).unwrap_or_default())?);
+                }
+            }
+            bytes.extend_from_slice(&var_number.to_be_bytes());
+            for text in &texts {
+                bytes.extend_from_slice(&(text.len() as u16).to_be_bytes());
+            }
+            for text in &texts {
+                bytes.extend_from_slice(text);
+            }
+        }
+        pad_record(&mut bytes);
+    }
+
+    if v8 {
+        bytes.extend(header_record(
+            "OBSV8   HEADER RECORD",
+            &format!("{:015}", header.observations.unwrap_or(0)),
+        ));
+    } else {
+        bytes.extend(header_record("OBS     HEADER RECORD", zeros));
+    }
+    Ok(bytes)
+}
+
+/// Bytes taken by the headers of a file with `columns`, a multiple of 80.
+pub fn header_length(
+    header: &MemberHeader,
+    columns: &[ColumnMeta],
+    string_encoder: StringEncoder,
+) -> Result<usize, XPTError> {
+    check_columns(header.version, columns)?;
+    header_bytes(header, columns, &layout(columns), string_encoder).map(|bytes| bytes.len())
+}
+
+/// Writes one member of a transport file.
+///
+/// [`Writer::start`] writes the headers, then every observation is written
+/// with [`Writer::write_row`] or, already laid out, with
+/// [`Writer::write_line`], and [`Writer::finish`] pads the last record.
+pub struct Writer<'a> {
+    #[cfg(not(feature = "async"))]
+    writer: &'a mut dyn Write,
+    #[cfg(feature = "async")]
+    writer: &'a mut (dyn tokio::io::AsyncWrite + Unpin + Send),
+    header: MemberHeader,
+    columns: Vec<ColumnMeta>,
+    layout: Vec<(u32, u16, u16)>,
+    line_length: usize,
+    line: Vec<u8>,
+    string_encoder: StringEncoder,
+    rows: usize,
+    bytes: u64,
+}
+
+impl<'a> Writer<'a> {
+    /// Checks the names, labels and lengths against the limits of
+    /// `header.version` and lays the columns out in order.
+    #[cfg(not(feature = "async"))]
+    pub fn new(
+        writer: &'a mut dyn Write,
+        header: MemberHeader,
+        columns: Vec<ColumnMeta>,
+        string_encoder: StringEncoder,
+    ) -> Result<Self, XPTError> {
+        Self::build(writer, header, columns, string_encoder)
+    }
+
+    /// Checks the names, labels and lengths against the limits of
+    /// `header.version` and lays the columns out in order.
+    #[cfg(feature = "async")]
+    pub fn new(
+        writer: &'a mut (dyn tokio::io::AsyncWrite + Unpin + Send),
+        header: MemberHeader,
+        columns: Vec<ColumnMeta>,
+        string_encoder: StringEncoder,
+    ) -> Result<Self, XPTError> {
+        Self::build(writer, header, columns, string_encoder)
+    }
+
+    fn build(
+        #[cfg(not(feature = "async"))] writer: &'a mut dyn Write,
+        #[cfg(feature = "async")] writer: &'a mut (dyn tokio::io::AsyncWrite + Unpin + Send),
+        header: MemberHeader,
+        columns: Vec<ColumnMeta>,
+        string_encoder: StringEncoder,
+    ) -> Result<Self, XPTError> {
+        check_columns(header.version, &columns)?;
+        let layout = layout(&columns);
+        let line_length = columns.iter().map(|c| c.length as usize).sum();
+        Ok(Writer {
+            writer,
+            header,
+            columns,
+            layout,
+            line_length,
+            line: vec![b' '; line_length],
+            string_encoder,
+            rows: 0,
+            bytes: 0,
+        })
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn put(&mut self, bytes: &[u8]) -> Result<(), XPTError> {
+        self.writer.write_all(bytes)?;
+        self.bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    async fn put(&mut self, bytes: &[u8]) -> Result<(), XPTError> {
+        self.writer.write_all(bytes).await?;
+        self.bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "async"))]
+    pub fn start(&mut self) -> Result<(), XPTError> {
+        let bytes = header_bytes(
+            &self.header,
+            &self.columns,
+            &self.layout,
+            self.string_encoder,
+        )?;
+        self.put(&bytes)
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn start(&mut self) -> Result<(), XPTError> {
+        let bytes = header_bytes(
+            &self.header,
+            &self.columns,
+            &self.layout,
+            self.string_encoder,
+        )?;
+        self.put(&bytes).await
+    }
+
+    fn encode_row(&mut self, row: &[Val]) -> Result<(), XPTError> {
+        if row.len() != self.columns.len() {
+            return Err(XPTError::EncodeError(format!(
+                "row has {} values for {} columns",
+                row.len(),
+                self.columns.len()
+            )));
+        }
+        for ((column, (npos, nlng, _)), value) in self.columns.iter().zip(&self.layout).zip(row) {
+            let cell = &mut self.line[*npos as usize..*npos as usize + *nlng as usize];
+            match (column.column_type, value) {
+                (ColumnType::NUMERIC, Val::Number(number)) => number2byte(Some(*number), cell)?,
+                (ColumnType::NUMERIC, Val::Nil) => number2byte(None, cell)?,
+                (ColumnType::CHAR, Val::Char(str)) => {
+                    cell.copy_from_slice(&encode_field(
+                        self.string_encoder,
+                        str,
+                        *nlng as usize,
+                        &format!("value of {}", column.name),
+                    )?);
+                }
+                (ColumnType::CHAR, Val::Nil) => cell.fill(b' '),
+                (_, value) => {
+                    return Err(XPTError::EncodeError(format!(
+                        "{:?} does not fit the {:?} column {}",
+                        value, column.column_type, column.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_line(&self, line: &[u8]) -> Result<(), XPTError> {
+        if line.len() != self.line_length {
+            return Err(XPTError::EncodeError(format!(
+                "observation is {} bytes, expected {}",
+                line.len(),
+                self.line_length
+            )));
+        }
+        Ok(())
+    }
+
+    fn padding(&self) -> Result<Vec<u8>, XPTError> {
+        if let Some(observations) = self.header.observations {
+            if observations != self.rows {
+                return Err(XPTError::EncodeError(format!(
+                    "header declares {} observations, {} were written",
+                    observations, self.rows
+                )));
+            }
+        }
+        Ok(vec![b' '; (80 - self.bytes % 80) as usize % 80])
+    }
+
+    /// Encodes and writes one observation. `Val::Nil` is the missing value
+    /// `.` in NUMERIC columns and blanks in CHAR columns.
+    #[cfg(not(feature = "async"))]
+    pub fn write_row(&mut self, row: &[Val]) -> Result<(), XPTError> {
+        self.encode_row(row)?;
+        let line = std::mem::take(&mut self.line);
+        let result = self.write_line(&line);
+        self.line = line;
+        result
+    }
+
+    /// Encodes and writes one observation. `Val::Nil` is the missing value
+    /// `.` in NUMERIC columns and blanks in CHAR columns.
+    #[cfg(feature = "async")]
+    pub async fn write_row(&mut self, row: &[Val]) -> Result<(), XPTError> {
+        self.encode_row(row)?;
+        let line = std::mem::take(&mut self.line);
+        let result = self.write_line(&line).await;
+        self.line = line;
+        result
+    }
+
+    /// Writes one observation laid out as [`Writer::layout`].
+    #[cfg(not(feature = "async"))]
+    pub fn write_line(&mut self, line: &[u8]) -> Result<(), XPTError> {
+        self.check_line(line)?;
+        self.put(line)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Writes one observation laid out as [`Writer::layout`].
+    #[cfg(feature = "async")]
+    pub async fn write_line(&mut self, line: &[u8]) -> Result<(), XPTError> {
+        self.check_line(line)?;
+        self.put(line).await?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Pads the last record with blanks and flushes, returning the number
+    /// of bytes written.
+    #[cfg(not(feature = "async"))]
+    pub fn finish(mut self) -> Result<u64, XPTError> {
+        let padding = self.padding()?;
+        self.put(&padding)?;
+        self.writer.flush()?;
+        Ok(self.bytes)
+    }
+
+    /// Pads the last record with blanks and flushes, returning the number
+    /// of bytes written.
+    #[cfg(feature = "async")]
+    pub async fn finish(mut self) -> Result<u64, XPTError> {
+        let padding = self.padding()?;
+        self.put(&padding).await?;
+        self.writer.flush().await?;
+        Ok(self.bytes)
+    }
+
+    /// Positions `(npos, nlng, ntype)` of the columns in an observation.
+    pub fn layout(&self) -> &[(u32, u16, u16)] {
+        &self.layout
+    }
+
+    pub fn line_length(&self) -> usize {
+        self.line_length
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::column;
+    use crate::part::{ColumnType, DocumentHeader};
+    use crate::writer::{layout, MemberHeader, LATIN1_STRING_ENCODER};
+
+    #[test]
+    fn test_layout() {
+        let columns = vec![
+            column("USUBJID", ColumnType::CHAR, 20),
+            column("AGE", ColumnType::NUMERIC, 8),
+            column("SEX", ColumnType::CHAR, 1),
+        ];
+        assert_eq!(layout(&columns), vec![(0, 20, 2), (20, 8, 1), (28, 1, 2)]);
+        assert_eq!(LATIN1_STRING_ENCODER("Ä").unwrap(), vec![0xC4]);
+        assert!(LATIN1_STRING_ENCODER("中").is_err());
+        let header = MemberHeader::new(DocumentHeader::V5, "DM");
+        assert_eq!(header.created.len(), 16);
+        assert_eq!(&header.created[7..8], ":");
+        assert!(header.operating_system.len() <= 8);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_round_trip() {
+        use crate::reader::{Reader, UTF8_STRING_DECODER};
+        use crate::writer::{Writer, UTF8_STRING_ENCODER};
+
+        for version in [DocumentHeader::V5, DocumentHeader::V8] {
+            let mut file = std::fs::File::open("sample/LB2.xpt").unwrap();
+            let mut reader = Reader::new(&mut file, UTF8_STRING_DECODER);
+            let (mut data, meta) = reader.start().unwrap();
+            let mut rows = Vec::new();
+            while let Some(row) = data.read_line().unwrap() {
+                rows.push(row);
+            }
+            let mut columns = meta.columns.clone();
+            columns[5].label = "Lab Test or Examination Name, as collected on the CRF".to_string();
+            let mut header = MemberHeader::from_meta(&meta);
+            header.version = version;
+            header.observations = Some(rows.len());
+            let mut output = Vec::new();
+            let mut writer = Writer::new(&mut output, header, columns.clone(), UTF8_STRING_ENCODER);
+            if version == DocumentHeader::V5 {
+                assert!(writer.is_err());
+                columns[5].label.truncate(40);
+                writer = Writer::new(
+                    &mut output,
+                    MemberHeader::from_meta(&meta),
+                    columns.clone(),
+                    UTF8_STRING_ENCODER,
+                );
+            }
+            let mut writer = writer.unwrap();
+            writer.start().unwrap();
+            for row in &rows {
+                writer.write_row(row).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+            assert_eq!(bytes as usize, output.len());
+            assert_eq!(output.len() % 80, 0);
+
+            let mut input = output.as_slice();
+            let mut reader = Reader::new(&mut input, UTF8_STRING_DECODER);
+            let (mut data, copy) = reader.start().unwrap();
+            assert_eq!(copy.version, version);
+            assert_eq!(copy.dataset_name, "LB");
+            assert_eq!(copy.dataset_label, "Laboratory Test Results");
+            assert_eq!(copy.dataset_created, meta.dataset_created);
+            assert_eq!(copy.columns.len(), columns.len());
+            for (a, b) in copy.columns.iter().zip(&columns) {
+                assert_eq!(a.name, b.name);
+                assert_eq!(a.label, b.label);
+                assert_eq!(a.length, b.length);
+                assert_eq!(a.format, b.format);
+            }
+            let mut copied = Vec::new();
+            while let Some(row) = data.read_line().unwrap() {
+                copied.push(row);
+            }
+            assert_eq!(copied, rows);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_short_v8_rows() {
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::writer::{Writer, UTF8_STRING_ENCODER};
+
+        // three 4-byte rows leave 68 bytes of padding that must not be read as rows
+        let mut header = MemberHeader::new(DocumentHeader::V8, "SHORT");
+        header.observations = Some(3);
+        let mut output = Vec::new();
+        let mut writer = Writer::new(
+            &mut output,
+            header,
+            vec![column("ARM", ColumnType::CHAR, 4)],
+            UTF8_STRING_ENCODER,
+        )
+        .unwrap();
+        writer.start().unwrap();
+        for arm in ["A", "B", "C"] {
+            writer.write_row(&[Val::Char(arm.to_string())]).unwrap();
+        }
+        writer.finish().unwrap();
+        let mut input = output.as_slice();
+        let (mut data, _) = Reader::new(&mut input, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        let mut rows = 0;
+        while data.read_line().unwrap().is_some() {
+            rows += 1;
+        }
+        assert_eq!(rows, 3);
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_short_v5_rows() {
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::writer::{Writer, UTF8_STRING_ENCODER};
+
+        // V5 records no observation count, so the blanks padding the last
+        // record must not be read as rows of either type
+        let cases = [
+            (
+                column("ARM", ColumnType::CHAR, 4),
+                ["A", "B", "C"].map(|arm| Val::Char(arm.to_string())),
+            ),
+            (
+                column("DOSE", ColumnType::NUMERIC, 8),
+                [1.0, 2.5, 10.0].map(Val::Number),
+            ),
+        ];
+        for (column, values) in cases {
+            let mut output = Vec::new();
+            let mut writer = Writer::new(
+                &mut output,
+                MemberHeader::new(DocumentHeader::V5, "SHORT"),
+                vec![column],
+                UTF8_STRING_ENCODER,
+            )
+            .unwrap();
+            writer.start().unwrap();
+            for value in &values {
+                writer.write_row(std::slice::from_ref(value)).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut input = output.as_slice();
+            let (mut data, _) = Reader::new(&mut input, UTF8_STRING_DECODER)
+                .start()
+                .unwrap();
+            let mut rows = Vec::new();
+            while let Some(row) = data.read_line().unwrap() {
+                rows.push(row[0].clone());
+            }
+            assert_eq!(rows, values);
+
+            for batch_rows in [1, 2, 3, 100] {
+                let mut input = output.as_slice();
+                let (mut data, _) = Reader::new(&mut input, UTF8_STRING_DECODER)
+                    .start()
+                    .unwrap();
+                let mut rows = 0;
+                while let Some(batch) = data.read_batch(batch_rows).unwrap() {
+                    rows += batch.rows;
+                }
+                assert_eq!(rows, 3);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_long_format_names() {
+        use crate::part::{ColumnMeta, V8ExtensionHeader, XptHeader};
+        use crate::reader::{Reader, Val, UTF8_STRING_DECODER};
+        use crate::writer::{Writer, UTF8_STRING_ENCODER};
+
+        let mut visit = column("VISITDATETIME", ColumnType::NUMERIC, 8);
+        visit.label = "Date and Time of the Visit, as collected on the CRF".to_string();
+        visit.format = ("E8601DTWITHZONE".to_string(), 25, 0);
+        visit.in_format = ("E8601DT".to_string(), 19, 0);
+        let mut columns = vec![column("VISIT", ColumnType::CHAR, 10), visit];
+        let write = |columns: Vec<ColumnMeta>, version| {
+            let mut header = MemberHeader::new(version, "SV");
+            header.observations = Some(1);
+            let mut output = Vec::new();
+            let mut writer = Writer::new(&mut output, header, columns, UTF8_STRING_ENCODER)?;
+            writer.start()?;
+            writer.write_row(&[Val::Char("SCREENING".to_string()), Val::Number(1.0)])?;
+            writer.finish()?;
+            Ok::<_, crate::error::XPTError>(output)
+        };
+        assert!(write(columns.clone(), DocumentHeader::V5).is_err());
+        let output = write(columns.clone(), DocumentHeader::V8).unwrap();
+        // 280 bytes of NAMESTRs take four records after the eight before them
+        let extension = V8ExtensionHeader::new(output[960..1040].try_into().unwrap()).unwrap();
+        assert_eq!(extension, V8ExtensionHeader::LabelV9(1));
+
+        let mut input = output.as_slice();
+        let (mut data, meta) = Reader::new(&mut input, UTF8_STRING_DECODER)
+            .start()
+            .unwrap();
+        assert_eq!(meta.columns[1].name, "VISITDATETIME");
+        assert_eq!(meta.columns[1].label, columns[1].label);
+        assert_eq!(meta.columns[1].format, columns[1].format);
+        assert_eq!(meta.columns[1].in_format, columns[1].in_format);
+        assert_eq!(meta.columns[0].format, (String::new(), 0, 0));
+        assert_eq!(data.read_line().unwrap().unwrap()[1], Val::Number(1.0));
+
+        // a long label alone is a LABELV8 record
+        columns[1].format = ("E8601DT".to_string(), 19, 0);
+        let output = write(columns.clone(), DocumentHeader::V8).unwrap();
+        let extension = V8ExtensionHeader::new(output[960..1040].try_into().unwrap()).unwrap();
+        assert_eq!(extension, V8ExtensionHeader::LabelV8(1));
+
+        // a record for a variable that does not exist is an error, not a panic
+        let mut broken = output.clone();
+        broken[1040..1042].copy_from_slice(&9u16.to_be_bytes());
+        let mut input = broken.as_slice();
+        assert!(Reader::new(&mut input, UTF8_STRING_DECODER)
+            .start()
+            .is_err());
+    }
+}